@@ -1,6 +1,6 @@
 use utoipa::{openapi::security::{ApiKey, ApiKeyValue, SecurityScheme}, Modify, OpenApi};
 
-use api_polyorbite::route::{route,auth};
+use api_polyorbite::route::{admin, auth, group, mfa, oidc, password, route, user};
 
 struct SecurityAddon;
 
@@ -10,6 +10,10 @@ impl Modify for SecurityAddon {
             components.add_security_scheme(
                 "jwt",
                 SecurityScheme::ApiKey(ApiKey::Header(ApiKeyValue::new("Authorization"))),
+            );
+            components.add_security_scheme(
+                "api_key",
+                SecurityScheme::ApiKey(ApiKey::Header(ApiKeyValue::new("X-Api-Key"))),
             )
         }
     }
@@ -20,12 +24,71 @@ impl Modify for SecurityAddon {
         modifiers(&SecurityAddon),
         paths(
             auth::sign_in,
+            auth::sign_in_mfa,
+            auth::refresh,
+            auth::logout,
+            auth::jwks,
+            password::change_password,
+            password::forgot,
+            password::reset,
+            oidc::discovery,
+            oidc::authorize,
+            oidc::userinfo,
+            user::get_users,
+            user::get_user,
+            user::create_user,
+            user::update_user,
+            user::delete_user,
+            user::get_me,
+            user::update_me,
+            user::get_picture,
+            user::put_picture,
+            user::delete_picture,
+            admin::revoke_sessions,
+            admin::clear_lockout,
+            admin::password_report,
+            admin::create_api_key,
+            admin::get_api_keys,
+            admin::revoke_api_key,
+            mfa::enroll,
+            mfa::confirm,
+            group::get_groups,
+            group::get_group,
+            group::add_member,
+            group::remove_member,
+            group::add_subgroup,
+            group::remove_subgroup,
             route::get_user
         ),
         components(
             schemas(
                 api_polyorbite::route::auth::AuthBody,
                 api_polyorbite::route::auth::SignInData,
+                api_polyorbite::route::auth::RefreshData,
+                api_polyorbite::route::auth::MfaData,
+                api_polyorbite::route::auth::MfaChallenge,
+                api_polyorbite::route::user::UserData,
+                api_polyorbite::route::user::UserPage,
+                api_polyorbite::route::user::CreateUserData,
+                api_polyorbite::route::user::CreatedUser,
+                api_polyorbite::route::user::UpdateUserData,
+                api_polyorbite::route::user::Values,
+                api_polyorbite::common::password::GeneratedKind,
+                api_polyorbite::route::admin::PasswordReportEntry,
+                api_polyorbite::route::admin::CreateApiKeyData,
+                api_polyorbite::route::admin::CreatedApiKey,
+                api_polyorbite::common::token::ApiKey,
+                api_polyorbite::common::role::Role,
+                api_polyorbite::route::password::ChangePasswordData,
+                api_polyorbite::route::password::ForgotData,
+                api_polyorbite::common::password::PolicyViolation,
+                api_polyorbite::route::password::ResetData,
+                api_polyorbite::route::mfa::EnrollResponse,
+                api_polyorbite::route::mfa::ConfirmData,
+                api_polyorbite::route::mfa::RecoveryCodes,
+                api_polyorbite::route::group::GroupResponse,
+                api_polyorbite::route::group::MemberData,
+                api_polyorbite::route::group::SubgroupData,
                 api_polyorbite::route::route::UserResponse
             )
         ),
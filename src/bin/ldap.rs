@@ -2,7 +2,7 @@ use std::vec;
 
 use dotenv::dotenv;
 
-use api_polyorbite::common::{user::{ModifyUser, UserBuilder}, Config, Ldap};
+use api_polyorbite::common::{password::PlaintextPolicy, user::{ModifyUser, UserBuilder}, Config, Ldap};
 
 #[tokio::main]
 async fn main() {
@@ -50,7 +50,7 @@ async fn test_user(ldap: &mut Ldap) {
 
     println!("new_user: {:?}", new_user);
 
-    let res = ldap.users.new_user(new_user).await;
+    let res = ldap.users.new_user(new_user, false).await;
     if res.is_err() {
         panic!("{:?}", res.err().unwrap());
     }
@@ -69,5 +69,5 @@ async fn test_user(ldap: &mut Ldap) {
 
     let user = ldap.users.user("user_test").await.unwrap();
     println!("user: {:?}", user);
-    println!("verif : {}", user.verify_password("password"));
+    println!("verif : {:?}", user.verify_password("password", PlaintextPolicy::Reject));
 }
\ No newline at end of file
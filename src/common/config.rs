@@ -1,7 +1,32 @@
+use super::password::{Hash, PlaintextPolicy, DEFAULT_HASH};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuthMode {
+    Bind,
+    BindWithHashFallback,
+    Hash,
+}
+
+impl AuthMode {
+    pub fn new(mode: &str) -> Self {
+        match mode {
+            "bind" => AuthMode::Bind,
+            "bind_fallback" => AuthMode::BindWithHashFallback,
+            "hash" => AuthMode::Hash,
+            _ => panic!("AUTH_MODE must be one of bind, bind_fallback or hash"),
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Config {
     // pub database_url: String,
     pub jwt_secret: String,
+    pub jwt_algorithm: String,
+    pub jwt_private_key: Option<String>,
+    pub jwt_public_key: Option<String>,
+    pub jwt_kid: Option<String>,
+    pub jwt_verification_keys: Vec<(String, String)>,
     pub jwt_expires_in: String,
     pub jwt_maxage: i32,
     pub ldap_user: String,
@@ -11,12 +36,65 @@ pub struct Config {
     pub ldap_base_dn: String,
     pub ldap_users_base_dn: String,
     pub ldap_groups_base_dn: String,
+    pub auth_mode: AuthMode,
+    pub refresh_maxage: i64,
+    pub refresh_store: Option<String>,
+    pub denylist_store: Option<String>,
+    pub admin_groups: Vec<String>,
+    pub team_lead_groups: Vec<String>,
+    pub member_groups: Vec<String>,
+    pub oidc_issuer: String,
+    pub oidc_clients: Option<String>,
+    pub mfa_issuer: String,
+    pub mfa_secret_attribute: String,
+    pub mfa_recovery_attribute: String,
+    pub api_key_store: Option<String>,
+    pub login_max_failures: u32,
+    pub login_max_failures_per_ip: u32,
+    pub login_lockout: i64,
+    pub client_ip_header: Option<String>,
+    pub client_ip_proxies: usize,
+    pub smtp_url: Option<String>,
+    pub mail_from: String,
+    pub mail_file: Option<String>,
+    pub password_reset_url: String,
+    pub password_reset_maxage: i64,
+    pub password_reset_max_per_user: usize,
+    pub password_reset_max_per_ip: usize,
+    pub password_min_length: usize,
+    pub password_max_length: usize,
+    pub password_character_classes: Vec<String>,
+    pub password_banned_words: Vec<String>,
+    pub password_breached_list: Option<String>,
+    pub password_hash: Hash,
+    pub password_rehash: bool,
+    pub plaintext_passwords: PlaintextPolicy,
+    pub password_changed_attribute: String,
+    pub password_must_change_attribute: String,
+    pub password_max_age: i64,
+    pub password_history: usize,
+    pub password_history_store: Option<String>,
+    pub password_generated_length: usize,
+    pub passphrase_words: usize,
+    pub passphrase_wordlist: Option<String>,
+    pub picture_max_size: usize,
+    pub picture_max_dimension: u32,
+    pub picture_thumbnail_sizes: Vec<String>,
+    pub user_field_permissions: Vec<String>,
+    pub user_attributes: Vec<String>,
+    pub user_custom_attributes: Vec<String>,
+    pub user_object_classes: Vec<String>,
 }
 
 impl Config {
     pub fn init() -> Config {
         // let database_url = std::env::var("DATABASE_URL").expect("DATABASE_URL must be set");
-        let jwt_secret = std::env::var("JWT_SECRET").expect("JWT_SECRET must be set");
+        let jwt_secret = std::env::var("JWT_SECRET").unwrap_or_default();
+        let jwt_algorithm = std::env::var("JWT_ALGORITHM").unwrap_or("HS256".to_string());
+        let jwt_private_key = std::env::var("JWT_PRIVATE_KEY").ok();
+        let jwt_public_key = std::env::var("JWT_PUBLIC_KEY").ok();
+        let jwt_kid = std::env::var("JWT_KID").ok();
+        let jwt_verification_keys = std::env::var("JWT_VERIFICATION_KEYS").unwrap_or_default();
         let jwt_expires_in = std::env::var("JWT_EXPIRED_IN").expect("JWT_EXPIRED_IN must be set");
         let jwt_maxage = std::env::var("JWT_MAXAGE").expect("JWT_MAXAGE must be set");
 
@@ -27,10 +105,70 @@ impl Config {
         let ldap_base_dn = std::env::var("LDAP_BASE").expect("LDAP_BASE must be set");
         let ldap_users_base_dn = std::env::var("LDAP_USERS_BASE").expect("LDAP_USERS_BASE must be set");
         let ldap_groups_base_dn = std::env::var("LDAP_GROUPS_BASE").expect("LDAP_GROUPS_BASE must be set");
+        let auth_mode = std::env::var("AUTH_MODE").unwrap_or("bind".to_string());
+        let refresh_maxage = std::env::var("REFRESH_MAXAGE").unwrap_or("43200".to_string());
+        let refresh_store = std::env::var("REFRESH_STORE").ok();
+        let denylist_store = std::env::var("DENYLIST_STORE").ok().or(refresh_store.as_ref().map(|path| format!("{}.denylist", path)));
+        let admin_groups = std::env::var("ADMIN_GROUPS").unwrap_or("admin".to_string());
+        let team_lead_groups = std::env::var("TEAM_LEAD_GROUPS").unwrap_or_default();
+        let member_groups = std::env::var("MEMBER_GROUPS").unwrap_or_default();
+        let oidc_issuer = std::env::var("OIDC_ISSUER").unwrap_or("http://localhost:4242".to_string());
+        let oidc_clients = std::env::var("OIDC_CLIENTS").ok();
+        let mfa_issuer = std::env::var("MFA_ISSUER").unwrap_or("Polyorbite".to_string());
+        let mfa_secret_attribute = std::env::var("MFA_SECRET_ATTRIBUTE").unwrap_or("totpSecret".to_string());
+        let mfa_recovery_attribute = std::env::var("MFA_RECOVERY_ATTRIBUTE").unwrap_or("totpRecoveryCode".to_string());
+        let api_key_store = std::env::var("API_KEY_STORE").ok();
+        let login_max_failures = std::env::var("LOGIN_MAX_FAILURES").unwrap_or("5".to_string());
+        let login_max_failures_per_ip = std::env::var("LOGIN_MAX_FAILURES_PER_IP").unwrap_or("20".to_string());
+        let login_lockout = std::env::var("LOGIN_LOCKOUT").unwrap_or("15".to_string());
+        let client_ip_header = std::env::var("CLIENT_IP_HEADER").ok();
+        let client_ip_proxies = std::env::var("CLIENT_IP_PROXIES").unwrap_or("1".to_string());
+        let smtp_url = std::env::var("SMTP_URL").ok();
+        let mail_from = std::env::var("MAIL_FROM").unwrap_or("Polyorbite <noreply@polyorbite.ca>".to_string());
+        let mail_file = std::env::var("MAIL_FILE").ok();
+        let password_reset_url = std::env::var("PASSWORD_RESET_URL").unwrap_or("http://localhost:3000/reset-password".to_string());
+        let password_reset_maxage = std::env::var("PASSWORD_RESET_MAXAGE").unwrap_or("30".to_string());
+        let password_reset_max_per_user = std::env::var("PASSWORD_RESET_MAX_PER_USER").unwrap_or("3".to_string());
+        let password_reset_max_per_ip = std::env::var("PASSWORD_RESET_MAX_PER_IP").unwrap_or("10".to_string());
+        let password_min_length = std::env::var("PASSWORD_MIN_LENGTH").unwrap_or("8".to_string());
+        let password_max_length = std::env::var("PASSWORD_MAX_LENGTH").unwrap_or("128".to_string());
+        let password_character_classes = std::env::var("PASSWORD_CHARACTER_CLASSES").unwrap_or_default();
+        let password_banned_words = std::env::var("PASSWORD_BANNED_WORDS").unwrap_or("polyorbite,password".to_string());
+        let password_breached_list = std::env::var("PASSWORD_BREACHED_LIST").ok();
+        let password_hash = std::env::var("PASSWORD_HASH").unwrap_or(DEFAULT_HASH.as_str().to_string());
+        let password_rehash = std::env::var("PASSWORD_REHASH").unwrap_or("true".to_string());
+        let plaintext_passwords = std::env::var("PLAINTEXT_PASSWORDS").unwrap_or("upgrade".to_string());
+        let password_changed_attribute = std::env::var("PASSWORD_CHANGED_ATTRIBUTE").unwrap_or("pwdChangedTime".to_string());
+        let password_must_change_attribute = std::env::var("PASSWORD_MUST_CHANGE_ATTRIBUTE").unwrap_or("pwdReset".to_string());
+        let password_max_age = std::env::var("PASSWORD_MAX_AGE").unwrap_or("0".to_string());
+        let password_history = std::env::var("PASSWORD_HISTORY").unwrap_or("5".to_string());
+        let password_history_store = std::env::var("PASSWORD_HISTORY_STORE").ok();
+        let password_generated_length = std::env::var("PASSWORD_GENERATED_LENGTH").unwrap_or("20".to_string());
+        let passphrase_words = std::env::var("PASSPHRASE_WORDS").unwrap_or("6".to_string());
+        let passphrase_wordlist = std::env::var("PASSPHRASE_WORDLIST").ok();
+        let picture_max_size = std::env::var("PICTURE_MAX_SIZE").unwrap_or("5242880".to_string());
+        let picture_max_dimension = std::env::var("PICTURE_MAX_DIMENSION").unwrap_or("1024".to_string());
+        let picture_thumbnail_sizes = std::env::var("PICTURE_THUMBNAIL_SIZES").unwrap_or("64,128,256".to_string());
+        let user_attributes = std::env::var("USER_ATTRIBUTES").unwrap_or_default();
+        let user_custom_attributes = std::env::var("USER_CUSTOM_ATTRIBUTES").unwrap_or_default();
+        let user_object_classes = std::env::var("USER_OBJECT_CLASSES").unwrap_or("inetOrgPerson".to_string());
+        let user_field_permissions = std::env::var("USER_FIELD_PERMISSIONS").unwrap_or("number:self,genie:self,picture:self,matricule:admin".to_string());
 
         Config {
             // database_url,
             jwt_secret,
+            jwt_algorithm,
+            jwt_private_key,
+            jwt_public_key,
+            jwt_kid,
+            jwt_verification_keys: jwt_verification_keys
+                .split(',')
+                .filter(|k| !k.is_empty())
+                .map(|k| {
+                    let (kid, path) = k.split_once('=').expect("JWT_VERIFICATION_KEYS must be a list of kid=path");
+                    (kid.to_string(), path.to_string())
+                })
+                .collect(),
             jwt_expires_in,
             jwt_maxage: jwt_maxage.parse::<i32>().unwrap(),
             ldap_user,
@@ -40,6 +178,62 @@ impl Config {
             ldap_base_dn,
             ldap_users_base_dn,
             ldap_groups_base_dn,
+            auth_mode: AuthMode::new(auth_mode.as_str()),
+            refresh_maxage: refresh_maxage.parse::<i64>().unwrap(),
+            refresh_store,
+            denylist_store,
+            admin_groups: Config::list(&admin_groups),
+            team_lead_groups: Config::list(&team_lead_groups),
+            member_groups: Config::list(&member_groups),
+            oidc_issuer,
+            oidc_clients,
+            mfa_issuer,
+            mfa_secret_attribute,
+            mfa_recovery_attribute,
+            api_key_store,
+            login_max_failures: login_max_failures.parse::<u32>().unwrap(),
+            login_max_failures_per_ip: login_max_failures_per_ip.parse::<u32>().unwrap(),
+            login_lockout: login_lockout.parse::<i64>().unwrap(),
+            client_ip_header,
+            client_ip_proxies: client_ip_proxies.parse::<usize>().unwrap(),
+            smtp_url,
+            mail_from,
+            mail_file,
+            password_reset_url,
+            password_reset_maxage: password_reset_maxage.parse::<i64>().unwrap(),
+            password_reset_max_per_user: password_reset_max_per_user.parse::<usize>().unwrap(),
+            password_reset_max_per_ip: password_reset_max_per_ip.parse::<usize>().unwrap(),
+            password_min_length: password_min_length.parse::<usize>().unwrap(),
+            password_max_length: password_max_length.parse::<usize>().unwrap(),
+            password_character_classes: Config::list(&password_character_classes),
+            password_banned_words: Config::list(&password_banned_words),
+            password_breached_list,
+            password_hash: password_hash.parse::<Hash>().unwrap(),
+            password_rehash: password_rehash.parse::<bool>().unwrap(),
+            plaintext_passwords: PlaintextPolicy::new(plaintext_passwords.as_str()),
+            password_changed_attribute,
+            password_must_change_attribute,
+            password_max_age: password_max_age.parse::<i64>().unwrap(),
+            password_history: password_history.parse::<usize>().unwrap(),
+            password_history_store,
+            password_generated_length: password_generated_length.parse::<usize>().unwrap(),
+            passphrase_words: passphrase_words.parse::<usize>().unwrap(),
+            passphrase_wordlist,
+            picture_max_size: picture_max_size.parse::<usize>().unwrap(),
+            picture_max_dimension: picture_max_dimension.parse::<u32>().unwrap(),
+            picture_thumbnail_sizes: Config::list(&picture_thumbnail_sizes),
+            user_field_permissions: Config::list(&user_field_permissions),
+            user_attributes: Config::list(&user_attributes),
+            user_custom_attributes: Config::list(&user_custom_attributes),
+            user_object_classes: Config::list(&user_object_classes),
         }
     }
+
+    fn list(value: &str) -> Vec<String> {
+        value
+            .split(',')
+            .map(|v| v.trim().to_string())
+            .filter(|v| !v.is_empty())
+            .collect()
+    }
 }
\ No newline at end of file
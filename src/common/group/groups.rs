@@ -3,6 +3,8 @@ use std::{collections::{HashMap, HashSet}, sync::Arc};
 use ldap3::{LdapConnAsync, Mod, Scope};
 use tokio::sync::Mutex;
 
+use crate::common::user::User;
+
 use super::Group;
 
 #[derive(Debug)]
@@ -61,7 +63,7 @@ impl Groups {
             .await?
             .success()?;
 
-        let filter = format!("(&(objectClass=groupOfNames)(cn={}))", id);
+        let filter = format!("(&(objectClass=groupOfNames)(cn={}))", ldap3::ldap_escape(id));
 
         let (rs, _res) = ldap
             .search(self.base_dn.as_str(), Scope::Subtree, filter.as_str(), vec!["*"])
@@ -89,6 +91,32 @@ impl Groups {
         self.groups.lock().await.values().map(|u| u.clone()).collect()
     }
 
+    /// Expand `direct` with every group reached through DN parents or group membership.
+    pub async fn transitive_groups(&self, direct: &HashSet<String>) -> HashSet<String> {
+        let groups = self.groups.lock().await;
+        let mut result = direct.clone();
+        let mut queue: Vec<String> = direct.iter().cloned().collect();
+
+        while let Some(cn) = queue.pop() {
+            let mut next: Vec<String> = groups
+                .values()
+                .filter(|g| g.group_members.contains(&cn))
+                .map(|g| g.cn.clone())
+                .collect();
+            if let Some(group) = groups.get(&cn) {
+                next.extend(group.parents.iter().cloned());
+            }
+
+            for cn in next {
+                if result.insert(cn.clone()) {
+                    queue.push(cn);
+                }
+            }
+        }
+
+        result
+    }
+
     pub async fn add_group_owner(&mut self, group: &str, owner: Vec<&str>) -> ldap3::result::Result<bool> {
         let (conn, mut ldap) = LdapConnAsync::new(self.ldap_url.as_str()).await?;
         ldap3::drive!(conn);
@@ -97,7 +125,7 @@ impl Groups {
             .await?
             .success()?;
 
-        let filter = format!("(&(objectClass=groupOfNames)(cn={}))", group);
+        let filter = format!("(&(objectClass=groupOfNames)(cn={}))", ldap3::ldap_escape(group));
 
         let (rs, _res) = ldap
             .search(self.base_dn.as_str(), Scope::Subtree, filter.as_str(), vec!["owner"])
@@ -137,5 +165,172 @@ impl Groups {
         Ok(true)
     }
 
+    pub async fn add_member(&mut self, group: &str, member_dn: &str) -> ldap3::result::Result<bool> {
+        self.modify_members(group, Mod::Add("member", HashSet::from([member_dn]))).await
+    }
+
+    pub async fn remove_member(&mut self, group: &str, member_dn: &str) -> ldap3::result::Result<bool> {
+        self.modify_members(group, Mod::Delete("member", HashSet::from([member_dn]))).await
+    }
+
+    async fn modify_members(&mut self, group: &str, change: Mod<&str>) -> ldap3::result::Result<bool> {
+        let dn = match self.group(group).await {
+            Some(group) => group.dn,
+            None => return Ok(false),
+        };
+
+        let (conn, mut ldap) = LdapConnAsync::new(self.ldap_url.as_str()).await?;
+        ldap3::drive!(conn);
+
+        ldap.simple_bind(self.ldap_user.as_str(), self.ldap_password.as_str())
+            .await?
+            .success()?;
+
+        let res = ldap
+            .modify(dn.as_str(), vec![change])
+            .await?
+            .success();
+
+        ldap.unbind().await?;
+
+        if res.is_err() {
+            return Ok(false);
+        }
+
+        self.update_group(group).await?;
+
+        Ok(true)
+    }
+
+    /// A user manages a group when they own it, or one of its parents, either
+    /// directly or through one of their groups.
+    pub async fn can_manage(&self, user: &User, cn: &str) -> bool {
+        let user_groups = self.transitive_groups(&user.member.clone().unwrap_or_default()).await;
+        let groups = self.groups.lock().await;
+
+        let group = match groups.get(cn) {
+            Some(group) => group,
+            None => return false,
+        };
+
+        std::iter::once(&group.cn)
+            .chain(group.parents.iter())
+            .filter_map(|cn| groups.get(cn))
+            .any(|g| g.owner_user.contains(&user.uid) || !g.owner_group.is_disjoint(&user_groups))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{HashMap, HashSet};
+
+    use ldap3::SearchEntry;
+
+    use crate::common::{
+        role::{Role, Roles},
+        user::fixture::user,
+    };
+
+    use super::{Group, Groups};
+
+    const GROUPS_BASE: &str = "ou=groups,dc=polyorbite,dc=com";
+    const USERS_BASE: &str = "ou=people,dc=polyorbite,dc=com";
+
+    fn group(cn: &str, parent: Option<&str>, members: &[&str], owners: &[&str]) -> Group {
+        let dn = match parent {
+            Some(parent) => format!("cn={},cn={},{}", cn, parent, GROUPS_BASE),
+            None => format!("cn={},{}", cn, GROUPS_BASE),
+        };
+        let mut attrs = HashMap::new();
+        attrs.insert("cn".to_string(), vec![cn.to_string()]);
+        attrs.insert("member".to_string(), members.iter().map(|m| m.to_string()).collect());
+        attrs.insert("owner".to_string(), owners.iter().map(|o| o.to_string()).collect());
+
+        Group::new(SearchEntry {
+            dn,
+            attrs,
+            bin_attrs: HashMap::new(),
+        })
+    }
+
+    /// polyorbite is owned by alice, its avionique sub-group by the leads
+    /// group, which contains the captains group where bob is a member.
+    async fn directory() -> Groups {
+        let groups = Groups::new(String::new(), String::new(), String::new(), GROUPS_BASE.to_string(), String::new());
+        let alice = format!("uid=alice,{}", USERS_BASE);
+        let bob = format!("uid=bob,{}", USERS_BASE);
+        let leads = format!("cn=leads,{}", GROUPS_BASE);
+        let captains = format!("cn=captains,{}", GROUPS_BASE);
+
+        for group in [
+            group("polyorbite", None, &[&alice], &[&alice]),
+            group("avionique", Some("polyorbite"), &[&alice], &[&leads]),
+            group("leads", None, &[&captains], &[]),
+            group("captains", None, &[&bob], &[]),
+            group("structure", None, &[&alice], &[]),
+        ] {
+            groups.groups.lock().await.insert(group.cn.clone(), group);
+        }
+
+        groups
+    }
+
+    #[tokio::test]
+    async fn transitive_groups_follow_group_membership_and_parents() {
+        let groups = directory().await;
+
+        let captains = groups.transitive_groups(&HashSet::from(["captains".to_string()])).await;
+        assert_eq!(captains, HashSet::from(["captains".to_string(), "leads".to_string()]));
+
+        let avionique = groups.transitive_groups(&HashSet::from(["avionique".to_string()])).await;
+        assert_eq!(avionique, HashSet::from(["avionique".to_string(), "polyorbite".to_string()]));
+    }
 
-}
\ No newline at end of file
+    #[tokio::test]
+    async fn roles_are_granted_through_transitive_groups() {
+        let groups = directory().await;
+        let admin = vec!["polyorbite".to_string()];
+        let team_lead = vec!["leads".to_string()];
+
+        let avionique = groups.transitive_groups(&HashSet::from(["avionique".to_string()])).await;
+        assert!(Roles::mapped(&avionique, &admin, &team_lead, &[]).has(Role::Admin));
+
+        let captains = groups.transitive_groups(&HashSet::from(["captains".to_string()])).await;
+        let roles = Roles::mapped(&captains, &admin, &team_lead, &[]);
+        assert!(roles.has(Role::TeamLead) && !roles.has(Role::Admin));
+
+        let structure = groups.transitive_groups(&HashSet::from(["structure".to_string()])).await;
+        let roles = Roles::mapped(&structure, &admin, &team_lead, &team_lead);
+        assert!(!roles.has(Role::Member));
+    }
+
+    #[tokio::test]
+    async fn direct_owner_manages_group_and_sub_groups() {
+        let groups = directory().await;
+        let alice = user("alice", "Alice", "Tremblay", "Polytechnique", &["polyorbite"]);
+
+        assert!(groups.can_manage(&alice, "polyorbite").await);
+        assert!(groups.can_manage(&alice, "avionique").await);
+        assert!(!groups.can_manage(&alice, "structure").await);
+    }
+
+    #[tokio::test]
+    async fn owner_group_is_resolved_transitively() {
+        let groups = directory().await;
+        let bob = user("bob", "Bob", "Gagnon", "ETS", &["captains"]);
+
+        assert!(groups.can_manage(&bob, "avionique").await);
+        assert!(!groups.can_manage(&bob, "polyorbite").await);
+        assert!(!groups.can_manage(&bob, "leads").await);
+    }
+
+    #[tokio::test]
+    async fn non_owner_and_unknown_group_are_refused() {
+        let groups = directory().await;
+        let carole = user("carole", "Carole", "De La Salle", "Polytechnique", &[]);
+
+        assert!(!groups.can_manage(&carole, "polyorbite").await);
+        assert!(!groups.can_manage(&carole, "avionique").await);
+        assert!(!groups.can_manage(&user("alice", "Alice", "Tremblay", "Polytechnique", &[]), "unknown").await);
+    }
+}
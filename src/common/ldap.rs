@@ -1,7 +1,8 @@
-use super::user::Users;
+use super::user::{AttributeMap, Users};
 use super::group::Groups;
 use super::Config;
 use std::env;
+use std::sync::Arc;
 
 use ldap3::LdapConnAsync;
 
@@ -21,6 +22,7 @@ impl Ldap {
             config.ldap_password.clone(),
             config.ldap_users_base_dn.clone(),
             config.ldap_base_dn.clone(),
+            Arc::new(AttributeMap::new(&config)),
         );
 
         let _ = users.update().await;
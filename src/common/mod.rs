@@ -3,6 +3,13 @@ mod config;
 pub mod user;
 pub mod group;
 pub mod password;
+pub mod token;
+pub mod oidc;
+pub mod totp;
+pub mod role;
+pub mod throttle;
+pub mod mailer;
+pub mod picture;
 
 pub use ldap::Ldap;
-pub use config::Config;
+pub use config::{AuthMode, Config};
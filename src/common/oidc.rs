@@ -0,0 +1,179 @@
+use std::{collections::HashMap, fs, sync::Mutex};
+
+use base64::prelude::*;
+use chrono::Utc;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use subtle::ConstantTimeEq;
+
+use super::token::{generate_token, hash_token};
+
+const CODE_MAXAGE: i64 = 60;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct OidcClient {
+    pub client_id: String,
+    pub client_secret: Option<String>,
+    pub redirect_uris: Vec<String>,
+}
+
+impl OidcClient {
+    pub fn load(path: &str) -> Vec<OidcClient> {
+        let content = fs::read_to_string(path).expect("OIDC_CLIENTS must be a readable file");
+        serde_json::from_str(&content).expect("OIDC_CLIENTS must be a JSON list of clients")
+    }
+
+    pub fn is_public(&self) -> bool {
+        self.client_secret.is_none()
+    }
+
+    /// A public client has no secret to check, the others are compared in constant time.
+    pub fn verify_secret(&self, secret: Option<&str>) -> bool {
+        match (&self.client_secret, secret) {
+            (None, _) => true,
+            (Some(expected), Some(secret)) => expected.as_bytes().ct_eq(secret.as_bytes()).into(),
+            (Some(_), None) => false,
+        }
+    }
+
+    /// Redirect URIs are compared as strings, without any normalization.
+    pub fn allows_redirect(&self, redirect_uri: &str) -> bool {
+        self.redirect_uris.iter().any(|uri| uri == redirect_uri)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct AuthorizationCode {
+    pub client_id: String,
+    pub redirect_uri: String,
+    pub username: String,
+    pub scope: String,
+    pub nonce: Option<String>,
+    pub code_challenge: Option<String>,
+    pub expires_at: i64,
+}
+
+impl AuthorizationCode {
+    pub fn issued_to(&self, client_id: &str, redirect_uri: &str) -> bool {
+        self.client_id == client_id && self.redirect_uri == redirect_uri
+    }
+
+    pub fn verify_pkce(&self, code_verifier: Option<&str>) -> bool {
+        match (&self.code_challenge, code_verifier) {
+            (None, _) => true,
+            (Some(challenge), Some(verifier)) => BASE64_URL_SAFE_NO_PAD.encode(Sha256::digest(verifier.as_bytes())) == *challenge,
+            (Some(_), None) => false,
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct AuthorizationCodes {
+    codes: Mutex<HashMap<String, AuthorizationCode>>,
+}
+
+impl AuthorizationCodes {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn issue(&self, client_id: String, redirect_uri: String, username: String, scope: String, nonce: Option<String>, code_challenge: Option<String>) -> String {
+        let now = Utc::now().timestamp();
+        let code = generate_token();
+
+        let mut codes = self.codes.lock().unwrap();
+        codes.retain(|_, c| c.expires_at > now);
+        codes.insert(hash_token(&code), AuthorizationCode {
+            client_id,
+            redirect_uri,
+            username,
+            scope,
+            nonce,
+            code_challenge,
+            expires_at: now + CODE_MAXAGE,
+        });
+
+        code
+    }
+
+    /// Codes are single use: they are removed as soon as they are presented.
+    pub fn take(&self, code: &str) -> Option<AuthorizationCode> {
+        let code = self.codes.lock().unwrap().remove(&hash_token(code))?;
+        if code.expires_at < Utc::now().timestamp() {
+            return None;
+        }
+        Some(code)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AuthorizationCodes, OidcClient};
+
+    fn client(secret: Option<&str>) -> OidcClient {
+        OidcClient {
+            client_id: "wiki".to_string(),
+            client_secret: secret.map(|s| s.to_string()),
+            redirect_uris: vec!["https://wiki.polyorbite.com/callback".to_string()],
+        }
+    }
+
+    fn issue(codes: &AuthorizationCodes, code_challenge: Option<&str>) -> String {
+        codes.issue(
+            "wiki".to_string(),
+            "https://wiki.polyorbite.com/callback".to_string(),
+            "alice".to_string(),
+            "openid".to_string(),
+            None,
+            code_challenge.map(|c| c.to_string()),
+        )
+    }
+
+    #[test]
+    fn the_s256_challenge_is_checked() {
+        // RFC 7636, appendix B
+        let codes = AuthorizationCodes::new();
+        let code = issue(&codes, Some("E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM"));
+        let code = codes.take(&code).unwrap();
+
+        assert!(code.verify_pkce(Some("dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk")));
+        assert!(!code.verify_pkce(Some("dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXl")));
+        assert!(!code.verify_pkce(None));
+
+        let code = issue(&codes, None);
+        assert!(codes.take(&code).unwrap().verify_pkce(None));
+    }
+
+    #[test]
+    fn codes_are_single_use() {
+        let codes = AuthorizationCodes::new();
+        let code = issue(&codes, None);
+
+        assert!(codes.take("unknown").is_none());
+        assert_eq!(codes.take(&code).unwrap().username, "alice");
+        assert!(codes.take(&code).is_none());
+    }
+
+    #[test]
+    fn redirect_uris_must_match_exactly() {
+        let client = client(None);
+        assert!(client.allows_redirect("https://wiki.polyorbite.com/callback"));
+        assert!(!client.allows_redirect("https://wiki.polyorbite.com/callback/"));
+        assert!(!client.allows_redirect("https://wiki.polyorbite.com/callback?next=/admin"));
+        assert!(!client.allows_redirect("https://WIKI.polyorbite.com/callback"));
+
+        let codes = AuthorizationCodes::new();
+        let code = codes.take(&issue(&codes, None)).unwrap();
+        assert!(code.issued_to("wiki", "https://wiki.polyorbite.com/callback"));
+        assert!(!code.issued_to("wiki", "https://wiki.polyorbite.com/callback/"));
+        assert!(!code.issued_to("chat", "https://wiki.polyorbite.com/callback"));
+    }
+
+    #[test]
+    fn client_secrets_are_checked() {
+        assert!(client(None).verify_secret(None));
+        assert!(client(Some("s3cret")).verify_secret(Some("s3cret")));
+        assert!(!client(Some("s3cret")).verify_secret(Some("s3cre")));
+        assert!(!client(Some("s3cret")).verify_secret(None));
+    }
+}
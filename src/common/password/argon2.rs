@@ -0,0 +1,29 @@
+use argon2::{
+    password_hash::{rand_core::OsRng, PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
+    Argon2,
+};
+
+/// `{ARGON2}` followed by a PHC string, as written by the OpenLDAP pw-argon2
+/// module: `{ARGON2}$argon2id$v=19$m=...,t=...,p=...$<salt>$<hash>`.
+pub struct LdapArgon2;
+
+impl LdapArgon2 {
+    pub fn hash(secret: &str) -> String {
+        let salt = SaltString::generate(&mut OsRng);
+        let hash = Argon2::default().hash_password(secret.as_bytes(), &salt).unwrap();
+        format!("{{ARGON2}}{}", hash)
+    }
+
+    fn parse(encoded: &str) -> Option<PasswordHash<'_>> {
+        PasswordHash::new(encoded).ok().filter(|hash| hash.salt.is_some() && hash.hash.is_some())
+    }
+
+    pub fn is_valid(encoded: &str) -> bool {
+        Self::parse(encoded).is_some()
+    }
+
+    pub fn verify(secret: &str, encoded: &str) -> Option<bool> {
+        let hash = Self::parse(encoded)?;
+        Some(Argon2::default().verify_password(secret.as_bytes(), &hash).is_ok())
+    }
+}
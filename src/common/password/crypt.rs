@@ -0,0 +1,27 @@
+use std::sync::LazyLock;
+
+use regex::Regex;
+
+static CRYPT_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^(\$(1|5|6|2[abxy]?)\$[^$]+(\$[^$]+)*\$[./0-9A-Za-z]+|[./0-9A-Za-z]{13})$").unwrap());
+
+/// `{CRYPT}` hashes hold a crypt(3) string: MD5 (`$1$`), SHA-256 (`$5$`),
+/// SHA-512 (`$6$`), bcrypt (`$2b$`) or traditional DES.
+pub struct LdapCrypt;
+
+impl LdapCrypt {
+    pub fn hash(secret: &str) -> String {
+        format!("{{CRYPT}}{}", pwhash::sha512_crypt::hash(secret).unwrap())
+    }
+
+    pub fn is_valid(encoded: &str) -> bool {
+        CRYPT_REGEX.is_match(encoded)
+    }
+
+    pub fn verify(secret: &str, encoded: &str) -> Option<bool> {
+        if !Self::is_valid(encoded) {
+            return None;
+        }
+        Some(pwhash::unix::verify(secret, encoded))
+    }
+}
@@ -0,0 +1,40 @@
+use base64::prelude::*;
+use sha1::Digest;
+use subtle::ConstantTimeEq;
+
+/// `{SHA}`, `{MD5}` and their salted variants: base64 of the digest of the
+/// password followed by the salt, with the salt appended to the digest.
+pub struct LdapDigest;
+
+impl LdapDigest {
+    pub fn hash<D: Digest>(scheme: &str, secret: &str, salt_size: usize) -> String {
+        let salt: Vec<u8> = (0..salt_size).map(|_| rand::random::<u8>()).collect();
+        let checksum = D::new().chain_update(secret.as_bytes()).chain_update(&salt).finalize();
+        let data = [checksum.as_slice(), &salt].concat();
+
+        format!("{{{}}}{}", scheme, BASE64_STANDARD.encode(data))
+    }
+
+    fn decode<D: Digest>(encoded: &str, salted: bool) -> Option<Vec<u8>> {
+        let data = BASE64_STANDARD.decode(encoded.trim()).ok()?;
+        let size = <D as Digest>::output_size();
+
+        if data.len() < size || (!salted && data.len() != size) {
+            return None;
+        }
+        Some(data)
+    }
+
+    pub fn is_valid<D: Digest>(encoded: &str, salted: bool) -> bool {
+        Self::decode::<D>(encoded, salted).is_some()
+    }
+
+    /// `encoded` is the hash without its `{SCHEME}` prefix.
+    pub fn verify<D: Digest>(secret: &str, encoded: &str, salted: bool) -> Option<bool> {
+        let data = Self::decode::<D>(encoded, salted)?;
+
+        let (checksum, salt) = data.split_at(<D as Digest>::output_size());
+        let new_checksum = D::new().chain_update(secret.as_bytes()).chain_update(salt).finalize();
+        Some(new_checksum.as_slice().ct_eq(checksum).into())
+    }
+}
@@ -0,0 +1,48 @@
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PasswordError {
+    /// The stored value has no `{SCHEME}` prefix.
+    Plaintext,
+    UnsupportedScheme(String),
+    InvalidHash,
+}
+
+impl PasswordError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::Plaintext => "plaintext",
+            Self::UnsupportedScheme(_) => "unsupported_scheme",
+            Self::InvalidHash => "invalid_hash",
+        }
+    }
+}
+
+impl fmt::Display for PasswordError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Plaintext => write!(f, "Password is stored in plaintext"),
+            Self::UnsupportedScheme(scheme) => write!(f, "Unsupported password scheme: {}", scheme),
+            Self::InvalidHash => write!(f, "Invalid password hash"),
+        }
+    }
+}
+
+/// What to do with a userPassword that has no `{SCHEME}` prefix.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlaintextPolicy {
+    Reject,
+    Upgrade,
+    Accept,
+}
+
+impl PlaintextPolicy {
+    pub fn new(policy: &str) -> Self {
+        match policy {
+            "reject" => Self::Reject,
+            "upgrade" => Self::Upgrade,
+            "accept" => Self::Accept,
+            _ => panic!("PLAINTEXT_PASSWORDS must be one of reject, upgrade or accept"),
+        }
+    }
+}
@@ -0,0 +1,100 @@
+use chrono::{DateTime, Duration, NaiveDateTime, Utc};
+
+use crate::common::{user::User, Config};
+
+/// When an account has to change its password before signing in.
+pub struct PasswordExpiry {
+    must_change_attribute: String,
+    changed_attribute: String,
+    /// In days, 0 never expires a password.
+    max_age: i64,
+}
+
+impl PasswordExpiry {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            must_change_attribute: config.password_must_change_attribute.clone(),
+            changed_attribute: config.password_changed_attribute.clone(),
+            max_age: config.password_max_age,
+        }
+    }
+
+    /// Whether the account is flagged, or its password is older than the maximum age.
+    pub fn change_required(&self, user: &User, now: DateTime<Utc>) -> bool {
+        let flagged = user
+            .attribute(&self.must_change_attribute)
+            .is_some_and(|values| values.iter().any(|v| v.eq_ignore_ascii_case("TRUE")));
+        if flagged || self.max_age <= 0 {
+            return flagged;
+        }
+
+        // GeneralizedTime, the fraction and time zone are ignored
+        let changed = user
+            .attribute(&self.changed_attribute)
+            .and_then(|values| values.first())
+            .and_then(|value| NaiveDateTime::parse_from_str(value.get(..14)?, "%Y%m%d%H%M%S").ok());
+
+        match changed {
+            Some(changed) => changed.and_utc() + Duration::days(self.max_age) < now,
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use chrono::{TimeZone, Utc};
+    use ldap3::SearchEntry;
+
+    use crate::common::user::{AttributeMap, User};
+
+    use super::PasswordExpiry;
+
+    fn expiry(max_age: i64) -> PasswordExpiry {
+        PasswordExpiry {
+            must_change_attribute: "pwdReset".to_string(),
+            changed_attribute: "pwdChangedTime".to_string(),
+            max_age,
+        }
+    }
+
+    fn user(attributes: &[(&str, &str)]) -> User {
+        let mut attrs = HashMap::from([("uid".to_string(), vec!["alice".to_string()])]);
+        for (name, value) in attributes {
+            attrs.insert(name.to_string(), vec![value.to_string()]);
+        }
+        User::new(SearchEntry { dn: String::new(), attrs, bin_attrs: HashMap::new() }, &AttributeMap::default())
+    }
+
+    #[test]
+    fn a_reset_password_must_be_changed() {
+        let now = Utc::now();
+        assert!(expiry(0).change_required(&user(&[("pwdReset", "TRUE")]), now));
+        assert!(expiry(90).change_required(&user(&[("pwdReset", "true")]), now));
+        assert!(!expiry(0).change_required(&user(&[("pwdReset", "FALSE")]), now));
+        assert!(!expiry(0).change_required(&user(&[]), now));
+    }
+
+    #[test]
+    fn a_password_expires_after_the_maximum_age() {
+        let now = Utc.with_ymd_and_hms(2024, 4, 1, 12, 0, 0).unwrap();
+        let old = user(&[("pwdChangedTime", "20240101120000Z")]);
+
+        assert!(expiry(90).change_required(&old, now));
+        assert!(!expiry(91).change_required(&old, now));
+        assert!(!expiry(0).change_required(&old, now));
+        assert!(!expiry(90).change_required(&user(&[]), now));
+    }
+
+    #[test]
+    fn the_change_time_is_read_as_generalized_time() {
+        let now = Utc.with_ymd_and_hms(2024, 4, 1, 12, 0, 0).unwrap();
+
+        assert!(expiry(30).change_required(&user(&[("pwdChangedTime", "20240101120000.123456Z")]), now));
+        assert!(expiry(30).change_required(&user(&[("pwdChangedTime", "20240101120000-0500")]), now));
+        assert!(!expiry(30).change_required(&user(&[("pwdChangedTime", "2024-01-01")]), now));
+        assert!(!expiry(30).change_required(&user(&[("pwdChangedTime", "2024")]), now));
+    }
+}
@@ -0,0 +1,160 @@
+use std::fs;
+
+use rand::{seq::SliceRandom, Rng};
+use serde::Deserialize;
+use utoipa::ToSchema;
+
+use crate::common::Config;
+
+use super::PasswordPolicy;
+
+const LOWERCASE: &str = "abcdefghijklmnopqrstuvwxyz";
+const UPPERCASE: &str = "ABCDEFGHIJKLMNOPQRSTUVWXYZ";
+const DIGITS: &str = "0123456789";
+const SYMBOLS: &str = "!#$%&*+-=?@^_~";
+const PASSPHRASE_SEPARATOR: &str = "-";
+const MAX_ATTEMPTS: usize = 100;
+const WORDLIST: &str = include_str!("wordlist.txt");
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum GeneratedKind {
+    Password,
+    #[default]
+    Passphrase,
+}
+
+pub struct PasswordGenerator {
+    length: usize,
+    words: usize,
+    wordlist: Vec<String>,
+}
+
+impl PasswordGenerator {
+    pub fn new(config: &Config) -> Self {
+        let wordlist = match &config.passphrase_wordlist {
+            Some(path) => {
+                let content = fs::read_to_string(path).expect("PASSPHRASE_WORDLIST must be a readable file");
+                let words = Self::parse_wordlist(&content);
+                if words.is_empty() {
+                    panic!("PASSPHRASE_WORDLIST must list at least one word");
+                }
+                words
+            }
+            None => Self::parse_wordlist(WORDLIST),
+        };
+
+        Self {
+            length: config.password_generated_length,
+            words: config.passphrase_words,
+            wordlist,
+        }
+    }
+
+    /// One word per line, the diceware numbers in front of them are ignored.
+    fn parse_wordlist(content: &str) -> Vec<String> {
+        content
+            .lines()
+            .filter_map(|line| line.split_whitespace().last())
+            .map(|word| word.to_lowercase())
+            .collect()
+    }
+
+    /// A candidate that follows the policy, `None` when the policy cannot be satisfied.
+    pub fn generate(&self, kind: GeneratedKind, policy: &PasswordPolicy, personal_words: &[String]) -> Option<String> {
+        (0..MAX_ATTEMPTS)
+            .map(|_| match kind {
+                GeneratedKind::Password => self.password(policy),
+                GeneratedKind::Passphrase => self.passphrase(),
+            })
+            .find(|candidate| policy.validate(candidate, personal_words).is_ok())
+    }
+
+    /// Random characters with at least one of every class.
+    fn password(&self, policy: &PasswordPolicy) -> String {
+        let mut rng = rand::thread_rng();
+        let length = self.length.clamp(policy.min_length(), policy.max_length().max(policy.min_length()));
+        let all: Vec<char> = [LOWERCASE, UPPERCASE, DIGITS, SYMBOLS].concat().chars().collect();
+
+        let mut chars: Vec<char> = [LOWERCASE, UPPERCASE, DIGITS, SYMBOLS]
+            .iter()
+            .filter_map(|class| class.chars().collect::<Vec<_>>().choose(&mut rng).copied())
+            .collect();
+        while chars.len() < length {
+            chars.push(*all.choose(&mut rng).unwrap());
+        }
+        chars.truncate(length);
+        chars.shuffle(&mut rng);
+
+        chars.into_iter().collect()
+    }
+
+    /// Diceware words, one of them capitalized and another one followed by a digit.
+    fn passphrase(&self) -> String {
+        let mut rng = rand::thread_rng();
+        let mut words: Vec<String> = (0..self.words.max(1))
+            .map(|_| self.wordlist.choose(&mut rng).unwrap().clone())
+            .collect();
+
+        let capitalized = rng.gen_range(0..words.len());
+        words[capitalized] = words[capitalized]
+            .char_indices()
+            .map(|(i, c)| if i == 0 { c.to_ascii_uppercase() } else { c })
+            .collect();
+
+        let numbered = rng.gen_range(0..words.len());
+        words[numbered].push_str(&rng.gen_range(0..10).to_string());
+
+        words.join(PASSPHRASE_SEPARATOR)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::common::password::{CharacterClass, PasswordPolicy};
+
+    use super::{GeneratedKind, PasswordGenerator, WORDLIST};
+
+    fn generator() -> PasswordGenerator {
+        PasswordGenerator { length: 20, words: 6, wordlist: PasswordGenerator::parse_wordlist(WORDLIST) }
+    }
+
+    fn classes() -> Vec<CharacterClass> {
+        vec![CharacterClass::Lowercase, CharacterClass::Uppercase, CharacterClass::Digit, CharacterClass::Symbol]
+    }
+
+    #[test]
+    fn generated_passwords_follow_the_policy() {
+        let policy = PasswordPolicy::with_rules(12, 16, classes());
+        for _ in 0..50 {
+            let password = generator().generate(GeneratedKind::Password, &policy, &[]).unwrap();
+            assert_eq!(password.chars().count(), 16);
+            assert!(policy.validate(&password, &[]).is_ok());
+        }
+    }
+
+    #[test]
+    fn generated_passphrases_follow_the_policy() {
+        let policy = PasswordPolicy::with_rules(20, 128, classes());
+        let generator = generator();
+        for _ in 0..50 {
+            let passphrase = generator.generate(GeneratedKind::Passphrase, &policy, &["able".to_string()]).unwrap();
+            let words: Vec<String> = passphrase.split('-').map(|w| w.trim_end_matches(|c: char| c.is_ascii_digit()).to_lowercase()).collect();
+
+            assert_eq!(words.len(), 6);
+            assert!(words.iter().all(|w| generator.wordlist.contains(w) && w != "able"));
+            assert!(policy.validate(&passphrase, &["able".to_string()]).is_ok());
+        }
+    }
+
+    #[test]
+    fn an_impossible_policy_gives_nothing() {
+        let policy = PasswordPolicy::with_rules(8, 10, classes());
+        assert!(generator().generate(GeneratedKind::Passphrase, &policy, &[]).is_none());
+    }
+
+    #[test]
+    fn diceware_numbers_are_ignored() {
+        assert_eq!(PasswordGenerator::parse_wordlist("11111\tabacus\n11112 Abdomen\n\nacid\n"), vec!["abacus", "abdomen", "acid"]);
+    }
+}
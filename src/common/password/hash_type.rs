@@ -1,12 +1,66 @@
+use std::str::FromStr;
+
+use super::PasswordError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Hash {
+    SHA,
     SSHA,
+    SSHA256,
+    SSHA512,
+    MD5,
+    SMD5,
+    CRYPT,
+    PBKDF2SHA256,
+    ARGON2,
 }
 
 impl Hash {
-    pub fn from_str(hash: &str) -> Self {
-        match hash {
-            "SSHA" => Hash::SSHA,
-            _ => panic!("Unsupported hash type"),
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Hash::SHA => "SHA",
+            Hash::SSHA => "SSHA",
+            Hash::SSHA256 => "SSHA256",
+            Hash::SSHA512 => "SSHA512",
+            Hash::MD5 => "MD5",
+            Hash::SMD5 => "SMD5",
+            Hash::CRYPT => "CRYPT",
+            Hash::PBKDF2SHA256 => "PBKDF2-SHA256",
+            Hash::ARGON2 => "ARGON2",
+        }
+    }
+
+    /// Rank of the scheme, a password is only rehashed to a stronger one.
+    pub fn strength(&self) -> u8 {
+        match self {
+            Hash::MD5 => 0,
+            Hash::SHA => 1,
+            Hash::SMD5 => 2,
+            Hash::SSHA => 3,
+            Hash::CRYPT => 4,
+            Hash::SSHA256 => 5,
+            Hash::SSHA512 => 6,
+            Hash::PBKDF2SHA256 => 7,
+            Hash::ARGON2 => 8,
         }
     }
-}
\ No newline at end of file
+}
+
+impl FromStr for Hash {
+    type Err = PasswordError;
+
+    fn from_str(hash: &str) -> Result<Self, Self::Err> {
+        match hash.to_uppercase().as_str() {
+            "SHA" => Ok(Hash::SHA),
+            "SSHA" => Ok(Hash::SSHA),
+            "SSHA256" => Ok(Hash::SSHA256),
+            "SSHA512" => Ok(Hash::SSHA512),
+            "MD5" => Ok(Hash::MD5),
+            "SMD5" => Ok(Hash::SMD5),
+            "CRYPT" => Ok(Hash::CRYPT),
+            "PBKDF2-SHA256" => Ok(Hash::PBKDF2SHA256),
+            "ARGON2" => Ok(Hash::ARGON2),
+            _ => Err(PasswordError::UnsupportedScheme(hash.to_string())),
+        }
+    }
+}
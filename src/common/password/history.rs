@@ -0,0 +1,147 @@
+use std::{collections::HashMap, fs::{self, OpenOptions, Permissions}, io::{self, Write}, os::unix::fs::{OpenOptionsExt, PermissionsExt}, path::PathBuf, sync::Mutex};
+
+use super::Password;
+
+/// Hashes of the passwords a user set, the most recent last.
+pub trait HistoryStore: Send + Sync {
+    fn hashes(&self, username: &str) -> Vec<String>;
+    fn push(&self, username: &str, hash: String, keep: usize);
+}
+
+fn push(history: &mut HashMap<String, Vec<String>>, username: &str, hash: String, keep: usize) {
+    let hashes = history.entry(username.to_string()).or_default();
+    hashes.push(hash);
+    let excess = hashes.len().saturating_sub(keep);
+    hashes.drain(..excess);
+}
+
+#[derive(Default)]
+pub struct MemoryHistoryStore {
+    history: Mutex<HashMap<String, Vec<String>>>,
+}
+
+impl MemoryHistoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl HistoryStore for MemoryHistoryStore {
+    fn hashes(&self, username: &str) -> Vec<String> {
+        self.history.lock().unwrap().get(username).cloned().unwrap_or_default()
+    }
+
+    fn push(&self, username: &str, hash: String, keep: usize) {
+        push(&mut self.history.lock().unwrap(), username, hash, keep);
+    }
+}
+
+pub struct FileHistoryStore {
+    path: PathBuf,
+    history: Mutex<HashMap<String, Vec<String>>>,
+}
+
+impl FileHistoryStore {
+    pub fn new(path: PathBuf) -> Self {
+        // Starting empty would let every user reuse an old password
+        let history = match fs::read_to_string(&path) {
+            Ok(content) => serde_json::from_str(&content).expect("PASSWORD_HISTORY_STORE must be a JSON file written by this server"),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => HashMap::new(),
+            Err(e) => panic!("PASSWORD_HISTORY_STORE must be readable: {}", e),
+        };
+
+        Self {
+            path,
+            history: Mutex::new(history),
+        }
+    }
+
+    /// The file holds password hashes, only the server may read it.
+    fn persist(&self, history: &HashMap<String, Vec<String>>) -> io::Result<()> {
+        let content = serde_json::to_string(history)?;
+        let mut file = OpenOptions::new().write(true).create(true).truncate(true).mode(0o600).open(&self.path)?;
+        // `mode` only applies to a new file
+        file.set_permissions(Permissions::from_mode(0o600))?;
+        file.write_all(content.as_bytes())
+    }
+}
+
+impl HistoryStore for FileHistoryStore {
+    fn hashes(&self, username: &str) -> Vec<String> {
+        self.history.lock().unwrap().get(username).cloned().unwrap_or_default()
+    }
+
+    fn push(&self, username: &str, hash: String, keep: usize) {
+        let mut history = self.history.lock().unwrap();
+        push(&mut history, username, hash, keep);
+
+        if let Err(e) = self.persist(&history) {
+            tracing::debug!("🔥 Failed to write the password history to {:?}: {:?}", self.path, e);
+        }
+    }
+}
+
+/// Whether `password` matches the current hash or one of the remembered ones.
+pub fn is_reused(store: &dyn HistoryStore, username: &str, current: &str, password: &str) -> bool {
+    std::iter::once(current.to_string())
+        .chain(store.hashes(username))
+        .any(|hash| Password::verify(password, &hash) == Ok(true))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, os::unix::fs::PermissionsExt};
+
+    use crate::common::password::{Hash, Password};
+
+    use super::{is_reused, FileHistoryStore, HistoryStore, MemoryHistoryStore};
+
+    #[test]
+    fn only_the_last_hashes_are_kept() {
+        let store = MemoryHistoryStore::new();
+        for hash in ["a", "b", "c", "d"] {
+            store.push("alice", hash.to_string(), 3);
+        }
+
+        assert_eq!(store.hashes("alice"), vec!["b", "c", "d"]);
+        assert!(store.hashes("bob").is_empty());
+    }
+
+    #[test]
+    fn the_current_and_remembered_passwords_are_reused() {
+        let store = MemoryHistoryStore::new();
+        store.push("alice", Password::hash("first", Hash::SSHA), 2);
+        store.push("alice", Password::hash("second", Hash::SSHA), 2);
+        let current = Password::hash("third", Hash::SSHA);
+
+        assert!(is_reused(&store, "alice", &current, "first"));
+        assert!(is_reused(&store, "alice", &current, "second"));
+        assert!(is_reused(&store, "alice", &current, "third"));
+        assert!(!is_reused(&store, "alice", &current, "fourth"));
+        assert!(!is_reused(&store, "bob", &current, "first"));
+    }
+
+    #[test]
+    fn the_history_file_is_private_and_reloaded() {
+        let path = env::temp_dir().join(format!("history-{}.json", std::process::id()));
+        FileHistoryStore::new(path.clone()).push("alice", "a".to_string(), 3);
+
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        let hashes = FileHistoryStore::new(path.clone()).hashes("alice");
+        fs::remove_file(&path).unwrap();
+        assert_eq!(mode & 0o777, 0o600);
+        assert_eq!(hashes, vec!["a"]);
+    }
+
+    #[test]
+    #[should_panic(expected = "PASSWORD_HISTORY_STORE")]
+    fn a_corrupt_history_file_is_refused() {
+        let path = env::temp_dir().join(format!("history-corrupt-{}.json", std::process::id()));
+        fs::write(&path, "{\"alice\": [").unwrap();
+        let store = std::panic::catch_unwind(|| FileHistoryStore::new(path.clone()));
+        fs::remove_file(&path).unwrap();
+        if let Err(e) = store {
+            std::panic::resume_unwind(e);
+        }
+    }
+}
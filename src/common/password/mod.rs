@@ -1,8 +1,21 @@
 mod hash_type;
 mod password;
-mod ssha;
+mod digest;
+mod crypt;
+mod pbkdf2;
+mod argon2;
+mod error;
+mod policy;
+mod history;
+mod generator;
+mod expiry;
 
 pub use password::Password;
 pub use hash_type::Hash;
+pub use error::{PasswordError, PlaintextPolicy};
+pub use policy::{CharacterClass, PasswordPolicy, PolicyViolation};
+pub use generator::{GeneratedKind, PasswordGenerator};
+pub use expiry::PasswordExpiry;
+pub use history::{is_reused, FileHistoryStore, HistoryStore, MemoryHistoryStore};
 
-pub const DEFAULT_HASH: Hash = Hash::SSHA;
\ No newline at end of file
+pub const DEFAULT_HASH: Hash = Hash::ARGON2;
@@ -1,28 +1,209 @@
-use super::ssha;
+use md5::Md5;
+use sha1::Sha1;
+use sha2::{Sha256, Sha512};
+use subtle::ConstantTimeEq;
+
+use super::argon2::LdapArgon2;
+use super::crypt::LdapCrypt;
+use super::digest::LdapDigest;
 use super::hash_type::Hash;
+use super::pbkdf2::LdapPbkdf2;
+use super::{PasswordError, PlaintextPolicy};
+
+const SALT_SIZE: usize = 4;
+const SHA2_SALT_SIZE: usize = 8;
 
 pub struct Password;
 
 impl Password {
     pub fn hash(password: &str, hash: Hash) -> String {
         match hash {
-            Hash::SSHA => ssha::LdapSaltedSha1::hash(password),
+            Hash::SHA => LdapDigest::hash::<Sha1>(hash.as_str(), password, 0),
+            Hash::SSHA => LdapDigest::hash::<Sha1>(hash.as_str(), password, SALT_SIZE),
+            Hash::SSHA256 => LdapDigest::hash::<Sha256>(hash.as_str(), password, SHA2_SALT_SIZE),
+            Hash::SSHA512 => LdapDigest::hash::<Sha512>(hash.as_str(), password, SHA2_SALT_SIZE),
+            Hash::MD5 => LdapDigest::hash::<Md5>(hash.as_str(), password, 0),
+            Hash::SMD5 => LdapDigest::hash::<Md5>(hash.as_str(), password, SALT_SIZE),
+            Hash::CRYPT => LdapCrypt::hash(password),
+            Hash::PBKDF2SHA256 => LdapPbkdf2::hash(password),
+            Hash::ARGON2 => LdapArgon2::hash(password),
+        }
+    }
+
+    /// Split `{SCHEME}value`, `None` when the value has no scheme.
+    pub fn scheme(hash: &str) -> Option<Result<(Hash, &str), PasswordError>> {
+        let rest = hash.strip_prefix('{')?;
+        let (code, value) = rest.split_once('}')?;
+        Some(code.parse::<Hash>().map(|hash| (hash, value)))
+    }
+
+    /// The scheme of a stored hash, once its format has been checked.
+    pub fn check(hash: &str) -> Result<Hash, PasswordError> {
+        let (hash_type, value) = Password::scheme(hash).ok_or(PasswordError::Plaintext)??;
+
+        let valid = match hash_type {
+            Hash::SHA => LdapDigest::is_valid::<Sha1>(value, false),
+            Hash::SSHA => LdapDigest::is_valid::<Sha1>(value, true),
+            Hash::SSHA256 => LdapDigest::is_valid::<Sha256>(value, true),
+            Hash::SSHA512 => LdapDigest::is_valid::<Sha512>(value, true),
+            Hash::MD5 => LdapDigest::is_valid::<Md5>(value, false),
+            Hash::SMD5 => LdapDigest::is_valid::<Md5>(value, true),
+            Hash::CRYPT => LdapCrypt::is_valid(value),
+            Hash::PBKDF2SHA256 => LdapPbkdf2::is_valid(value),
+            Hash::ARGON2 => LdapArgon2::is_valid(value),
+        };
+
+        match valid {
+            true => Ok(hash_type),
+            false => Err(PasswordError::InvalidHash),
         }
     }
 
-    pub fn verify(password: &str, hash: &str) -> bool {
-        let reg = regex::Regex::new(r"\{(?P<code>.*?)\}").unwrap();
-        let captures = reg.captures(hash).ok_or("Invalid hash format");
-        if captures.is_err() {
-            return password == hash;
+    /// Whether `hash` should be replaced by a `target` hash of the same password,
+    /// never by a weaker scheme than the current one.
+    pub fn needs_rehash(hash: &str, target: Hash, plaintext: PlaintextPolicy) -> bool {
+        match Password::scheme(hash) {
+            Some(Ok((current, _))) => target.strength() > current.strength(),
+            Some(Err(_)) => false,
+            None => plaintext == PlaintextPolicy::Upgrade && !hash.is_empty(),
         }
-        let captures = captures.unwrap();
-        let hash_type = Hash::from_str(captures.name("code").unwrap().as_str());
+    }
+
+    fn verify_scheme(password: &str, hash_type: Hash, value: &str) -> Option<bool> {
         match hash_type {
-            Hash::SSHA => ssha::LdapSaltedSha1::verify(password, hash),
-        } 
+            Hash::SHA => LdapDigest::verify::<Sha1>(password, value, false),
+            Hash::SSHA => LdapDigest::verify::<Sha1>(password, value, true),
+            Hash::SSHA256 => LdapDigest::verify::<Sha256>(password, value, true),
+            Hash::SSHA512 => LdapDigest::verify::<Sha512>(password, value, true),
+            Hash::MD5 => LdapDigest::verify::<Md5>(password, value, false),
+            Hash::SMD5 => LdapDigest::verify::<Md5>(password, value, true),
+            Hash::CRYPT => LdapCrypt::verify(password, value),
+            Hash::PBKDF2SHA256 => LdapPbkdf2::verify(password, value),
+            Hash::ARGON2 => LdapArgon2::verify(password, value),
+        }
+    }
+
+    /// `Ok(false)` on a wrong password, an error when `hash` cannot be used.
+    pub fn verify(password: &str, hash: &str) -> Result<bool, PasswordError> {
+        let (hash_type, value) = Password::scheme(hash).ok_or(PasswordError::Plaintext)??;
+        Password::verify_scheme(password, hash_type, value).ok_or(PasswordError::InvalidHash)
+    }
+
+    /// Same as `verify`, comparing values without a scheme unless `plaintext` rejects them.
+    pub fn verify_with(password: &str, hash: &str, plaintext: PlaintextPolicy) -> Result<bool, PasswordError> {
+        match Password::verify(password, hash) {
+            Err(PasswordError::Plaintext) if plaintext != PlaintextPolicy::Reject => {
+                Ok(password.as_bytes().ct_eq(hash.as_bytes()).into())
+            }
+            result => result,
+        }
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The password is "secret", in the order printed by testdata/slappasswd.sh.
+    // These were not made by slappasswd yet: `openssl dgst` over the password and
+    // the salt 01 02 03 .. for the digests, `openssl passwd -6/-5/-1 -salt saltsalt`
+    // and Perl's crypt() for {CRYPT}, `openssl kdf ... PBKDF2` with the salt
+    // 0123456789abcdef for {PBKDF2-SHA256}. Replace them with the script output.
+    const VECTORS: [&str; 11] = [
+        "{SHA}5en6G6MezRroT3XKqkdPOmY/BfQ=",
+        "{SSHA}uJDd0BIdJ9Z7yDCZNWdgYeb33+cBAgME",
+        "{SSHA256}A7N1lAy5bBb4T6qH9e85zAvHBmzNPhRFbZ105DjjWDIBAgMEBQYHCA==",
+        "{SSHA512}KO8EsMPQTwZrxxbOkDAOOXEeVCc2grMQg1pnZwZhC1bBQLby8zCmFn7qTZRvoTd+yQdROQQNYHWpTUST4zjTdQECAwQFBgcI",
+        "{MD5}Xr4ilOzQ4PCOq3aQ0qbuaQ==",
+        "{SMD5}LF/f6q9WuGXFii+gm3ssYQECAwQ=",
+        "{CRYPT}$6$saltsalt$TVLlQcbpFVof5W3Yz4DTP6gRstiNuHwwTt6GLc1E5n0U0aDehy0S5knV8wiOQSpT0Y77vwPZN.Pq.H91p5hVO1",
+        "{CRYPT}$5$saltsalt$0IyaXrmV7.sGNS6tirgqHLqX/G.FBvgkYA.lpPdS5sA",
+        "{CRYPT}$1$saltsalt$9xy1btjgzLYfb7hivXtC//",
+        "{CRYPT}abNANd1rDfiNc",
+        "{PBKDF2-SHA256}10000$MDEyMzQ1Njc4OWFiY2RlZg$6umsHhz0yhb.YIJ/FUgCrsOB.tK.gGdB.tvI2KPIHns",
+    ];
 
+    #[test]
+    fn verify_known_hashes() {
+        for hash in VECTORS {
+            assert_eq!(Password::verify("secret", hash), Ok(true), "{}", hash);
+            assert_eq!(Password::verify("Secret", hash), Ok(false), "{}", hash);
+            assert!(Password::check(hash).is_ok(), "{}", hash);
+        }
+    }
+
+    #[test]
+    fn hash_and_verify_every_scheme() {
+        let schemes = [
+            Hash::SHA,
+            Hash::SSHA,
+            Hash::SSHA256,
+            Hash::SSHA512,
+            Hash::MD5,
+            Hash::SMD5,
+            Hash::CRYPT,
+            Hash::PBKDF2SHA256,
+            Hash::ARGON2,
+        ];
+
+        for scheme in schemes {
+            let hash = Password::hash("secret", scheme);
+            assert!(hash.starts_with(&format!("{{{}}}", scheme.as_str())), "{}", hash);
+            assert_eq!(Password::check(&hash), Ok(scheme), "{}", hash);
+            assert_eq!(Password::verify("secret", &hash), Ok(true), "{}", hash);
+            assert_eq!(Password::verify("other", &hash), Ok(false), "{}", hash);
+        }
+    }
+
+    // Example from the Argon2 reference implementation README.
+    #[test]
+    fn verify_reference_argon2_hash() {
+        let hash = "{ARGON2}$argon2i$v=19$m=65536,t=2,p=4$c29tZXNhbHQ$RdescudvJCsgt3ub+b+dWRWJTmaaJObG";
+        assert_eq!(Password::verify("password", hash), Ok(true));
+        assert_eq!(Password::verify("secret", hash), Ok(false));
+    }
+
+    #[test]
+    fn rehash_only_to_stronger_schemes() {
+        assert!(Password::needs_rehash(VECTORS[1], Hash::ARGON2, PlaintextPolicy::Reject));
+        assert!(!Password::needs_rehash(VECTORS[1], Hash::SSHA, PlaintextPolicy::Reject));
+        assert!(Password::needs_rehash(VECTORS[4], Hash::SSHA, PlaintextPolicy::Reject));
+        assert!(!Password::needs_rehash("{ARGON3}abc", Hash::ARGON2, PlaintextPolicy::Upgrade));
+        assert!(Password::needs_rehash("plaintext", Hash::ARGON2, PlaintextPolicy::Upgrade));
+        assert!(!Password::needs_rehash("plaintext", Hash::ARGON2, PlaintextPolicy::Accept));
+    }
 
+    #[test]
+    fn strong_hashes_are_never_rehashed_to_ssha() {
+        let argon2 = Password::hash("secret", Hash::ARGON2);
+        for hash in [argon2.as_str(), VECTORS[3], VECTORS[6], VECTORS[10]] {
+            assert!(!Password::needs_rehash(hash, Hash::SSHA, PlaintextPolicy::Upgrade), "{}", hash);
+        }
+        assert!(!Password::needs_rehash(VECTORS[3], Hash::SSHA256, PlaintextPolicy::Upgrade));
+        assert!(!Password::needs_rehash(&argon2, Hash::PBKDF2SHA256, PlaintextPolicy::Upgrade));
+    }
+
+    #[test]
+    fn plaintext_follows_the_policy() {
+        assert_eq!(Password::verify("secret", "secret"), Err(PasswordError::Plaintext));
+        assert_eq!(Password::verify_with("secret", "secret", PlaintextPolicy::Reject), Err(PasswordError::Plaintext));
+        assert_eq!(Password::verify_with("secret", "secret", PlaintextPolicy::Accept), Ok(true));
+        assert_eq!(Password::verify_with("other", "secret", PlaintextPolicy::Upgrade), Ok(false));
+        assert_eq!(Password::verify_with("secret", VECTORS[0], PlaintextPolicy::Reject), Ok(true));
+    }
+
+    #[test]
+    fn unknown_or_malformed_hashes_are_errors() {
+        assert_eq!(Password::verify("secret", "{ARGON3}c2VjcmV0"), Err(PasswordError::UnsupportedScheme("ARGON3".to_string())));
+        for hash in [
+            "{SSHA}not base64",
+            "{SHA}c2VjcmV0",
+            "{PBKDF2-SHA256}10000$c2FsdA",
+            "{ARGON2}$argon2id$v=19$broken",
+            "{CRYPT}$6$",
+        ] {
+            assert_eq!(Password::verify("secret", hash), Err(PasswordError::InvalidHash), "{}", hash);
+            assert_eq!(Password::check(hash), Err(PasswordError::InvalidHash), "{}", hash);
+        }
+    }
+}
@@ -0,0 +1,52 @@
+use base64::prelude::*;
+use sha2::Sha256;
+use subtle::ConstantTimeEq;
+
+const ITERATIONS: u32 = 10000;
+const SALT_SIZE: usize = 16;
+const KEY_SIZE: usize = 32;
+
+/// `{PBKDF2-SHA256}<iterations>$<salt>$<key>` as written by the OpenLDAP
+/// pw-pbkdf2 module, the salt and key use base64 with `.` instead of `+`.
+pub struct LdapPbkdf2;
+
+impl LdapPbkdf2 {
+    fn encode(data: &[u8]) -> String {
+        BASE64_STANDARD_NO_PAD.encode(data).replace('+', ".")
+    }
+
+    fn decode(data: &str) -> Option<Vec<u8>> {
+        BASE64_STANDARD_NO_PAD.decode(data.trim_end_matches('=').replace('.', "+")).ok()
+    }
+
+    pub fn hash(secret: &str) -> String {
+        let salt = rand::random::<[u8; SALT_SIZE]>();
+        let key = pbkdf2::pbkdf2_hmac_array::<Sha256, KEY_SIZE>(secret.as_bytes(), &salt, ITERATIONS);
+
+        format!("{{PBKDF2-SHA256}}{}${}${}", ITERATIONS, Self::encode(&salt), Self::encode(&key))
+    }
+
+    fn parse(encoded: &str) -> Option<(u32, Vec<u8>, Vec<u8>)> {
+        let mut parts = encoded.split('$');
+        let iterations = parts.next()?.parse::<u32>().ok()?;
+        let salt = Self::decode(parts.next()?)?;
+        let key = Self::decode(parts.next()?)?;
+
+        if iterations == 0 || key.is_empty() || parts.next().is_some() {
+            return None;
+        }
+        Some((iterations, salt, key))
+    }
+
+    pub fn is_valid(encoded: &str) -> bool {
+        Self::parse(encoded).is_some()
+    }
+
+    pub fn verify(secret: &str, encoded: &str) -> Option<bool> {
+        let (iterations, salt, key) = Self::parse(encoded)?;
+
+        let mut new_key = vec![0u8; key.len()];
+        pbkdf2::pbkdf2_hmac::<Sha256>(secret.as_bytes(), &salt, iterations, &mut new_key);
+        Some(new_key.ct_eq(&key).into())
+    }
+}
@@ -0,0 +1,252 @@
+use std::{collections::HashSet, fs};
+
+use serde::Serialize;
+use sha1::{Digest, Sha1};
+use utoipa::ToSchema;
+
+use crate::common::{user::User, Config};
+
+/// Personal words shorter than this are too common to be rejected.
+const MIN_PERSONAL_WORD: usize = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CharacterClass {
+    Lowercase,
+    Uppercase,
+    Digit,
+    Symbol,
+}
+
+impl CharacterClass {
+    pub fn new(class: &str) -> Option<Self> {
+        match class.to_lowercase().as_str() {
+            "lower" | "lowercase" => Some(Self::Lowercase),
+            "upper" | "uppercase" => Some(Self::Uppercase),
+            "digit" | "digits" => Some(Self::Digit),
+            "symbol" | "symbols" => Some(Self::Symbol),
+            _ => None,
+        }
+    }
+
+    fn matches(&self, c: char) -> bool {
+        match self {
+            Self::Lowercase => c.is_lowercase(),
+            Self::Uppercase => c.is_uppercase(),
+            Self::Digit => c.is_numeric(),
+            Self::Symbol => !c.is_alphanumeric(),
+        }
+    }
+
+    fn code(&self) -> &'static str {
+        match self {
+            Self::Lowercase => "missing_lowercase",
+            Self::Uppercase => "missing_uppercase",
+            Self::Digit => "missing_digit",
+            Self::Symbol => "missing_symbol",
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Lowercase => "a lowercase letter",
+            Self::Uppercase => "an uppercase letter",
+            Self::Digit => "a digit",
+            Self::Symbol => "a symbol",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+pub struct PolicyViolation {
+    pub code: &'static str,
+    pub message: String,
+}
+
+impl PolicyViolation {
+    pub fn new(code: &'static str, message: String) -> Self {
+        Self { code, message }
+    }
+}
+
+pub struct PasswordPolicy {
+    min_length: usize,
+    max_length: usize,
+    classes: Vec<CharacterClass>,
+    banned_words: Vec<String>,
+    breached: HashSet<String>,
+}
+
+impl PasswordPolicy {
+    pub fn new(config: &Config) -> Self {
+        let classes = config
+            .password_character_classes
+            .iter()
+            .map(|class| CharacterClass::new(class).expect("PASSWORD_CHARACTER_CLASSES must only list lower, upper, digit and symbol"))
+            .collect();
+
+        let breached = match &config.password_breached_list {
+            Some(path) => Self::parse_breached(&fs::read_to_string(path).expect("PASSWORD_BREACHED_LIST must be a readable file")),
+            None => HashSet::new(),
+        };
+
+        Self {
+            min_length: config.password_min_length,
+            max_length: config.password_max_length,
+            classes,
+            banned_words: config.password_banned_words.iter().map(|w| w.to_lowercase()).collect(),
+            breached,
+        }
+    }
+
+    /// One entry per line, either the password itself or its SHA-1 in hex,
+    /// optionally followed by `:count` like the Have I Been Pwned dumps.
+    fn parse_breached(content: &str) -> HashSet<String> {
+        content
+            .lines()
+            .map(|line| line.trim_end_matches('\r'))
+            .filter(|line| !line.is_empty())
+            .map(|line| match line.split_once(':') {
+                Some((hash, count)) if Self::is_sha1(hash) && count.chars().all(|c| c.is_ascii_digit()) => hash.to_uppercase(),
+                _ if Self::is_sha1(line) => line.to_uppercase(),
+                _ => line.to_string(),
+            })
+            .collect()
+    }
+
+    fn is_sha1(value: &str) -> bool {
+        value.len() == 40 && value.chars().all(|c| c.is_ascii_hexdigit())
+    }
+
+    fn is_breached(&self, password: &str) -> bool {
+        if self.breached.is_empty() {
+            return false;
+        }
+        let digest: String = Sha1::digest(password.as_bytes()).iter().map(|b| format!("{:02X}", b)).collect();
+        self.breached.contains(password) || self.breached.contains(&digest)
+    }
+
+    /// Words taken from the account that a password should not contain.
+    pub fn personal_words(user: &User) -> Vec<String> {
+        Self::words(&user.uid, &user.mail, &[&user.first_name, &user.last_name, &user.name])
+    }
+
+    /// Same as `personal_words`, for an account that does not exist yet.
+    pub fn words(uid: &str, mails: &[String], names: &[&str]) -> Vec<String> {
+        let mut words = vec![uid.to_string()];
+        for mail in mails {
+            words.push(mail.clone());
+            if let Some((local, _)) = mail.split_once('@') {
+                words.push(local.to_string());
+            }
+        }
+        for name in names {
+            words.extend(name.split_whitespace().map(|w| w.to_string()));
+        }
+        words
+    }
+
+    pub fn min_length(&self) -> usize {
+        self.min_length
+    }
+
+    pub fn max_length(&self) -> usize {
+        self.max_length
+    }
+
+    pub fn validate(&self, password: &str, personal_words: &[String]) -> Result<(), Vec<PolicyViolation>> {
+        let mut violations = Vec::new();
+        let length = password.chars().count();
+
+        if length < self.min_length {
+            violations.push(PolicyViolation::new("too_short", format!("The password must contain at least {} characters", self.min_length)));
+        }
+        if length > self.max_length {
+            violations.push(PolicyViolation::new("too_long", format!("The password must contain at most {} characters", self.max_length)));
+        }
+
+        for class in &self.classes {
+            if !password.chars().any(|c| class.matches(c)) {
+                violations.push(PolicyViolation::new(class.code(), format!("The password must contain {}", class.name())));
+            }
+        }
+
+        let lowercase = password.to_lowercase();
+        if let Some(word) = self.banned_words.iter().find(|w| !w.is_empty() && lowercase.contains(w.as_str())) {
+            violations.push(PolicyViolation::new("banned_word", format!("The password cannot contain \"{}\"", word)));
+        }
+
+        let personal = personal_words
+            .iter()
+            .map(|w| w.to_lowercase())
+            .any(|w| w.chars().count() >= MIN_PERSONAL_WORD && lowercase.contains(&w));
+        if personal {
+            violations.push(PolicyViolation::new("personal_information", "The password cannot contain your username, name or email".to_string()));
+        }
+
+        if self.is_breached(password) {
+            violations.push(PolicyViolation::new("breached", "The password appears in a list of leaked passwords".to_string()));
+        }
+
+        match violations.is_empty() {
+            true => Ok(()),
+            false => Err(violations),
+        }
+    }
+}
+
+#[cfg(test)]
+impl PasswordPolicy {
+    pub(super) fn with_rules(min_length: usize, max_length: usize, classes: Vec<CharacterClass>) -> Self {
+        Self { min_length, max_length, classes, banned_words: vec![], breached: HashSet::new() }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CharacterClass, PasswordPolicy};
+
+    fn policy() -> PasswordPolicy {
+        PasswordPolicy {
+            min_length: 8,
+            max_length: 16,
+            classes: vec![CharacterClass::Uppercase, CharacterClass::Digit],
+            banned_words: vec!["polyorbite".to_string()],
+            // a plain entry, the SHA-1 of "Tr0ub4dor&3" and the one of "P4ssw0rd" with a count
+            breached: PasswordPolicy::parse_breached("Hunter2hunter2\r\n874572e7a5ae6a49466a6ac578b98adba78c6aa6\n264BC0768362A68984FAEA923EFAA21F67F4D10A:12\n"),
+        }
+    }
+
+    fn codes(password: &str, personal_words: &[String]) -> Vec<&'static str> {
+        policy().validate(password, personal_words).err().unwrap_or_default().iter().map(|v| v.code).collect()
+    }
+
+    #[test]
+    fn length_and_classes_are_checked() {
+        assert_eq!(codes("Sh0rt", &[]), vec!["too_short"]);
+        assert_eq!(codes("Much2LongForThePolicy", &[]), vec!["too_long"]);
+        assert_eq!(codes("lowercase only", &[]), vec!["missing_uppercase", "missing_digit"]);
+        assert_eq!(codes("Correct7Horse", &[]), Vec::<&str>::new());
+    }
+
+    #[test]
+    fn banned_and_personal_words_are_refused() {
+        let words = PasswordPolicy::words("alice", &["alice.t@polyorbite.com".to_string()], &["Alice Tremblay"]);
+
+        assert_eq!(codes("My1POLYORBITE", &[]), vec!["banned_word"]);
+        assert_eq!(codes("Tremblay2024", &words), vec!["personal_information"]);
+        assert_eq!(codes("Xalice.t9Y", &words), vec!["personal_information"]);
+        // too short to be a personal word
+        assert_eq!(codes("Al7Horses", &PasswordPolicy::words("al", &[], &[])), Vec::<&str>::new());
+    }
+
+    #[test]
+    fn breached_passwords_are_found_in_plain_and_as_sha1() {
+        let policy = policy();
+
+        assert!(policy.is_breached("Hunter2hunter2"));
+        assert!(!policy.is_breached("hunter2hunter2"));
+        assert!(policy.is_breached("Tr0ub4dor&3"));
+        assert!(policy.is_breached("P4ssw0rd"));
+        assert_eq!(codes("Tr0ub4dor&3", &[]), vec!["breached"]);
+    }
+}
@@ -0,0 +1,22 @@
+#!/bin/sh
+# Print the known-answer vectors of password.rs, hashes of "secret" made by
+# slappasswd with the OpenLDAP pw-sha2 and pw-pbkdf2 contrib modules.
+# MODULE_PATH is where the modules are installed (/usr/lib/ldap on Debian).
+set -e
+
+MODULE_PATH=${MODULE_PATH:-/usr/lib/ldap}
+hash() {
+    slappasswd -o module-path="$MODULE_PATH" "$@" -s secret
+}
+
+hash -h '{SHA}'
+hash -h '{SSHA}'
+hash -o module-load=pw-sha2.la -h '{SSHA256}'
+hash -o module-load=pw-sha2.la -h '{SSHA512}'
+hash -h '{MD5}'
+hash -h '{SMD5}'
+hash -h '{CRYPT}' -c '$6$%.8s'
+hash -h '{CRYPT}' -c '$5$%.8s'
+hash -h '{CRYPT}' -c '$1$%.8s'
+hash -h '{CRYPT}' -c '%.2s'
+hash -o module-load=pw-pbkdf2.la -h '{PBKDF2-SHA256}'
@@ -0,0 +1,202 @@
+use std::{collections::HashMap, fmt, io::Cursor, sync::Mutex};
+
+use image::{codecs::jpeg::JpegEncoder, imageops::FilterType, DynamicImage, ImageDecoder, ImageFormat, ImageReader, Limits, Rgb, RgbImage};
+use sha2::{Digest, Sha256};
+
+use super::Config;
+
+const JPEG_QUALITY: u8 = 85;
+/// Decoding larger images is refused, whatever their file size.
+const MAX_SOURCE_DIMENSION: u32 = 8192;
+
+/// Thumbnails by user and size, with the ETag of the picture they were made from.
+type Thumbnails = HashMap<(String, u32), (String, Vec<u8>)>;
+
+#[derive(Debug, PartialEq)]
+pub enum PictureError {
+    TooLarge,
+    UnsupportedFormat,
+    Invalid,
+}
+
+impl fmt::Display for PictureError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::TooLarge => write!(f, "The picture is too large"),
+            Self::UnsupportedFormat => write!(f, "The picture must be a PNG, JPEG or WebP image"),
+            Self::Invalid => write!(f, "The picture cannot be decoded"),
+        }
+    }
+}
+
+/// Stored pictures are JPEG, thumbnails are square crops cached per user.
+pub struct Pictures {
+    max_size: usize,
+    max_dimension: u32,
+    thumbnail_sizes: Vec<u32>,
+    thumbnails: Mutex<Thumbnails>,
+}
+
+impl Pictures {
+    pub fn new(config: &Config) -> Self {
+        let thumbnail_sizes = config
+            .picture_thumbnail_sizes
+            .iter()
+            .map(|size| match size.parse() {
+                Ok(size) if size > 0 => size,
+                _ => panic!("PICTURE_THUMBNAIL_SIZES must be a comma separated list of sizes in pixels, got {}", size),
+            })
+            .collect();
+
+        Self {
+            max_size: config.picture_max_size,
+            max_dimension: config.picture_max_dimension,
+            thumbnail_sizes,
+            thumbnails: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn max_size(&self) -> usize {
+        self.max_size
+    }
+
+    pub fn thumbnail_sizes(&self) -> &[u32] {
+        &self.thumbnail_sizes
+    }
+
+    pub fn etag(picture: &[u8]) -> String {
+        let digest = Sha256::digest(picture);
+        format!("\"{}\"", digest[..16].iter().map(|b| format!("{:02x}", b)).collect::<String>())
+    }
+
+    /// Decodes an upload, turns it upright and re-encodes it without its metadata.
+    pub fn normalize(&self, upload: &[u8]) -> Result<Vec<u8>, PictureError> {
+        if upload.len() > self.max_size {
+            return Err(PictureError::TooLarge);
+        }
+
+        let image = Self::decode(upload, &[ImageFormat::Png, ImageFormat::Jpeg, ImageFormat::WebP])?;
+        let image = match image.width() > self.max_dimension || image.height() > self.max_dimension {
+            true => image.resize(self.max_dimension, self.max_dimension, FilterType::Lanczos3),
+            false => image,
+        };
+
+        Self::encode(&image)
+    }
+
+    pub fn thumbnail(&self, uid: &str, picture: &[u8], size: u32) -> Result<Vec<u8>, PictureError> {
+        let etag = Self::etag(picture);
+        let key = (uid.to_string(), size);
+        if let Some((cached, thumbnail)) = self.thumbnails.lock().unwrap().get(&key) {
+            if *cached == etag {
+                return Ok(thumbnail.clone());
+            }
+        }
+
+        let image = Self::decode(picture, &[ImageFormat::Jpeg, ImageFormat::Png, ImageFormat::WebP])?;
+        let thumbnail = Self::encode(&image.resize_to_fill(size, size, FilterType::Lanczos3))?;
+        self.thumbnails.lock().unwrap().insert(key, (etag, thumbnail.clone()));
+
+        Ok(thumbnail)
+    }
+
+    pub fn forget(&self, uid: &str) {
+        self.thumbnails.lock().unwrap().retain(|(cached, _), _| cached != uid);
+    }
+
+    fn decode(bytes: &[u8], formats: &[ImageFormat]) -> Result<DynamicImage, PictureError> {
+        let mut reader = ImageReader::new(Cursor::new(bytes)).with_guessed_format().map_err(|_| PictureError::Invalid)?;
+        match reader.format() {
+            Some(format) if formats.contains(&format) => (),
+            _ => return Err(PictureError::UnsupportedFormat),
+        }
+
+        let mut limits = Limits::default();
+        limits.max_image_width = Some(MAX_SOURCE_DIMENSION);
+        limits.max_image_height = Some(MAX_SOURCE_DIMENSION);
+        reader.limits(limits);
+
+        let mut decoder = reader.into_decoder().map_err(|_| PictureError::Invalid)?;
+        let orientation = decoder.orientation().map_err(|_| PictureError::Invalid)?;
+        let mut image = DynamicImage::from_decoder(decoder).map_err(|_| PictureError::Invalid)?;
+        image.apply_orientation(orientation);
+
+        Ok(image)
+    }
+
+    /// JPEG has no transparency, transparent pixels are laid on white.
+    fn encode(image: &DynamicImage) -> Result<Vec<u8>, PictureError> {
+        let rgba = image.to_rgba8();
+        let rgb = RgbImage::from_fn(rgba.width(), rgba.height(), |x, y| {
+            let [r, g, b, a] = rgba.get_pixel(x, y).0;
+            let blend = |c: u8| ((c as u16 * a as u16 + 255 * (255 - a as u16)) / 255) as u8;
+            Rgb([blend(r), blend(g), blend(b)])
+        });
+
+        let mut jpeg = Vec::new();
+        JpegEncoder::new_with_quality(&mut jpeg, JPEG_QUALITY)
+            .encode_image(&rgb)
+            .map_err(|_| PictureError::Invalid)?;
+
+        Ok(jpeg)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, io::Cursor, sync::Mutex};
+
+    use image::{DynamicImage, ImageFormat, Rgba, RgbaImage};
+
+    use super::{PictureError, Pictures};
+
+    fn pictures() -> Pictures {
+        Pictures {
+            max_size: 64 * 1024,
+            max_dimension: 32,
+            thumbnail_sizes: vec![8],
+            thumbnails: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn png(width: u32, height: u32) -> Vec<u8> {
+        let image = RgbaImage::from_pixel(width, height, Rgba([255, 0, 0, 0]));
+        let mut png = Vec::new();
+        DynamicImage::ImageRgba8(image).write_to(&mut Cursor::new(&mut png), ImageFormat::Png).unwrap();
+        png
+    }
+
+    #[test]
+    fn uploads_are_scaled_down_and_re_encoded_as_jpeg() {
+        let jpeg = pictures().normalize(&png(64, 16)).unwrap();
+        let image = image::load_from_memory(&jpeg).unwrap();
+
+        assert_eq!(image::guess_format(&jpeg).unwrap(), ImageFormat::Jpeg);
+        assert_eq!((image.width(), image.height()), (32, 8));
+        // fully transparent pixels end up white
+        assert!(image.to_rgb8().pixels().all(|p| p.0.iter().all(|c| *c > 240)));
+    }
+
+    #[test]
+    fn invalid_uploads_are_rejected() {
+        let pictures = pictures();
+
+        assert_eq!(pictures.normalize(b"GIF89a\x01\x00\x01\x00"), Err(PictureError::UnsupportedFormat));
+        assert_eq!(pictures.normalize(&png(4, 4)[..40]), Err(PictureError::Invalid));
+        assert_eq!(pictures.normalize(&vec![0; 64 * 1024 + 1]), Err(PictureError::TooLarge));
+    }
+
+    #[test]
+    fn thumbnails_are_square_and_follow_the_picture() {
+        let pictures = pictures();
+        let picture = pictures.normalize(&png(32, 16)).unwrap();
+
+        let thumbnail = pictures.thumbnail("alice", &picture, 8).unwrap();
+        assert_eq!(image::load_from_memory(&thumbnail).unwrap().width(), 8);
+        assert_eq!(pictures.thumbnail("alice", &picture, 8).unwrap(), thumbnail);
+
+        let other = pictures.normalize(&png(16, 16)).unwrap();
+        pictures.thumbnail("alice", &other, 8).unwrap();
+        assert_eq!(pictures.thumbnails.lock().unwrap()[&("alice".to_string(), 8)].0, Pictures::etag(&other));
+    }
+}
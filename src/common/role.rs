@@ -0,0 +1,124 @@
+use std::collections::HashSet;
+
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use super::Config;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "kebab-case")]
+pub enum Role {
+    Member,
+    TeamLead,
+    Admin,
+}
+
+impl Role {
+    pub fn as_str(&self) -> &str {
+        match self {
+            Role::Member => "member",
+            Role::TeamLead => "team-lead",
+            Role::Admin => "admin",
+        }
+    }
+
+    /// Roles granted along with this one.
+    pub fn implied(&self) -> Vec<Role> {
+        match self {
+            Role::Member => vec![Role::Member],
+            Role::TeamLead => vec![Role::TeamLead, Role::Member],
+            Role::Admin => vec![Role::Admin, Role::TeamLead, Role::Member],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Roles(HashSet<Role>);
+
+impl Roles {
+    /// Map the (transitive) groups of a user to roles. When no member group is
+    /// configured, every user of the directory is a member.
+    pub fn from_groups(groups: &HashSet<String>, config: &Config) -> Self {
+        Self::mapped(groups, &config.admin_groups, &config.team_lead_groups, &config.member_groups)
+    }
+
+    pub(crate) fn mapped(
+        groups: &HashSet<String>,
+        admin_groups: &[String],
+        team_lead_groups: &[String],
+        member_groups: &[String],
+    ) -> Self {
+        let mut roles = HashSet::new();
+        let in_any = |mapped: &[String]| mapped.iter().any(|g| groups.contains(g));
+
+        if in_any(admin_groups) {
+            roles.extend(Role::Admin.implied());
+        }
+        if in_any(team_lead_groups) {
+            roles.extend(Role::TeamLead.implied());
+        }
+        if member_groups.is_empty() || in_any(member_groups) {
+            roles.extend(Role::Member.implied());
+        }
+
+        Self(roles)
+    }
+
+    pub fn from_roles(roles: &[Role]) -> Self {
+        Self(roles.iter().flat_map(|r| r.implied()).collect())
+    }
+
+    pub fn intersection(&self, other: &Roles) -> Self {
+        Self(self.0.intersection(&other.0).cloned().collect())
+    }
+
+    pub fn has(&self, role: Role) -> bool {
+        self.0.contains(&role)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::{Role, Roles};
+
+    fn list(groups: &[&str]) -> Vec<String> {
+        groups.iter().map(|g| g.to_string()).collect()
+    }
+
+    fn roles(groups: &[&str], admin: &[&str], team_lead: &[&str], member: &[&str]) -> Roles {
+        let groups: HashSet<String> = list(groups).into_iter().collect();
+        Roles::mapped(&groups, &list(admin), &list(team_lead), &list(member))
+    }
+
+    #[test]
+    fn higher_roles_imply_the_lower_ones() {
+        let admin = roles(&["admin"], &["admin"], &["leads"], &["club"]);
+        assert!(admin.has(Role::Admin) && admin.has(Role::TeamLead) && admin.has(Role::Member));
+
+        let lead = roles(&["leads"], &["admin"], &["leads"], &["club"]);
+        assert!(!lead.has(Role::Admin) && lead.has(Role::TeamLead) && lead.has(Role::Member));
+    }
+
+    #[test]
+    fn member_groups_restrict_who_is_a_member() {
+        let outsider = roles(&["alumni"], &["admin"], &["leads"], &["club"]);
+        assert!(!outsider.has(Role::Member));
+
+        let member = roles(&["alumni", "club"], &["admin"], &["leads"], &["club"]);
+        assert!(member.has(Role::Member) && !member.has(Role::TeamLead));
+
+        let anyone = roles(&[], &["admin"], &["leads"], &[]);
+        assert!(anyone.has(Role::Member) && !anyone.has(Role::Admin));
+    }
+
+    #[test]
+    fn a_session_keeps_only_the_roles_still_granted() {
+        let requested = Roles::from_roles(&[Role::Admin]);
+        let granted = roles(&["leads"], &["admin"], &["leads"], &[]);
+        let kept = requested.intersection(&granted);
+
+        assert!(!kept.has(Role::Admin) && kept.has(Role::TeamLead) && kept.has(Role::Member));
+    }
+}
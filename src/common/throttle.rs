@@ -0,0 +1,346 @@
+use std::{collections::HashMap, sync::Mutex};
+
+use chrono::Utc;
+
+const BASE_DELAY: i64 = 1;
+const MAX_DELAY: i64 = 60;
+
+#[derive(Debug, Clone, Default)]
+pub struct Attempts {
+    pub failures: u32,
+    pub last_failure: i64,
+    pub locked_until: i64,
+}
+
+pub trait AttemptStore: Send + Sync {
+    fn get(&self, key: &str) -> Option<Attempts>;
+    /// Replace the attempts of `key` by `f` of the current ones, as one operation.
+    fn update(&self, key: &str, f: &mut dyn FnMut(Option<Attempts>) -> Attempts);
+    fn remove(&self, key: &str);
+    fn prune(&self, before: i64);
+}
+
+#[derive(Default)]
+pub struct MemoryAttemptStore {
+    attempts: Mutex<HashMap<String, Attempts>>,
+}
+
+impl MemoryAttemptStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl AttemptStore for MemoryAttemptStore {
+    fn get(&self, key: &str) -> Option<Attempts> {
+        self.attempts.lock().unwrap().get(key).cloned()
+    }
+
+    fn update(&self, key: &str, f: &mut dyn FnMut(Option<Attempts>) -> Attempts) {
+        let mut attempts = self.attempts.lock().unwrap();
+        let updated = f(attempts.remove(key));
+        attempts.insert(key.to_string(), updated);
+    }
+
+    fn remove(&self, key: &str) {
+        self.attempts.lock().unwrap().remove(key);
+    }
+
+    fn prune(&self, before: i64) {
+        self.attempts
+            .lock()
+            .unwrap()
+            .retain(|_, a| a.last_failure >= before || a.locked_until > before);
+    }
+}
+
+/// Failed attempts per key: every failure doubles the delay before the next
+/// attempt, and `max_failures` failures lock the key for `lockout` seconds.
+/// Failures are forgotten once `lockout` seconds passed without a new one.
+pub struct Throttle {
+    store: Box<dyn AttemptStore>,
+    max_failures: u32,
+    lockout: i64,
+}
+
+impl Throttle {
+    pub fn new(store: Box<dyn AttemptStore>, max_failures: u32, lockout: i64) -> Self {
+        Self {
+            store,
+            max_failures,
+            lockout,
+        }
+    }
+
+    /// The attempts that still count at `now`.
+    fn current(&self, attempts: Option<Attempts>, now: i64) -> Attempts {
+        attempts
+            .filter(|a| a.locked_until > now || now - a.last_failure < self.lockout)
+            .unwrap_or_default()
+    }
+
+    /// Seconds to wait before `key` may try again, if any.
+    pub fn retry_after(&self, key: &str) -> Option<i64> {
+        self.retry_after_at(key, Utc::now().timestamp())
+    }
+
+    fn retry_after_at(&self, key: &str, now: i64) -> Option<i64> {
+        let attempts = self.current(self.store.get(key), now);
+
+        if attempts.locked_until > now {
+            return Some(attempts.locked_until - now);
+        }
+        if attempts.failures == 0 {
+            return None;
+        }
+
+        let delay = (BASE_DELAY << (attempts.failures - 1).min(16)).min(MAX_DELAY);
+        let next = attempts.last_failure + delay;
+        (next > now).then_some(next - now)
+    }
+
+    pub fn failure(&self, key: &str) {
+        self.failure_at(key, Utc::now().timestamp())
+    }
+
+    fn failure_at(&self, key: &str, now: i64) {
+        self.store.prune(now - self.lockout);
+        // Read and written under the store lock, concurrent failures all count
+        self.store.update(key, &mut |attempts| {
+            let mut attempts = self.current(attempts, now);
+            attempts.failures += 1;
+            attempts.last_failure = now;
+            if attempts.failures >= self.max_failures {
+                attempts.failures = 0;
+                attempts.locked_until = now + self.lockout;
+            }
+            attempts
+        });
+    }
+
+    pub fn clear(&self, key: &str) {
+        self.store.remove(key);
+    }
+}
+
+pub struct LoginThrottle {
+    pub users: Throttle,
+    pub ips: Throttle,
+}
+
+impl LoginThrottle {
+    pub fn new(max_failures: u32, max_failures_per_ip: u32, lockout: i64) -> Self {
+        Self {
+            users: Throttle::new(Box::new(MemoryAttemptStore::new()), max_failures, lockout),
+            ips: Throttle::new(Box::new(MemoryAttemptStore::new()), max_failures_per_ip, lockout),
+        }
+    }
+
+    pub fn user_key(username: &str) -> String {
+        username.trim().to_lowercase()
+    }
+
+    /// Second-factor failures are counted apart, a right password does not clear them.
+    pub fn second_factor_key(username: &str) -> String {
+        format!("mfa:{}", Self::user_key(username))
+    }
+
+    pub fn retry_after(&self, username: &str, ip: Option<&str>) -> Option<i64> {
+        let user = self.users.retry_after(&Self::user_key(username));
+        let ip = ip.and_then(|ip| self.ips.retry_after(ip));
+        user.max(ip)
+    }
+
+    pub fn failure(&self, username: &str, ip: Option<&str>) {
+        self.users.failure(&Self::user_key(username));
+        if let Some(ip) = ip {
+            self.ips.failure(ip);
+        }
+    }
+
+    pub fn success(&self, username: &str) {
+        self.users.clear(&Self::user_key(username));
+    }
+}
+
+/// At most `max` requests per key in any `window` seconds.
+pub struct RateLimit {
+    max: usize,
+    window: i64,
+    requests: Mutex<HashMap<String, Vec<i64>>>,
+}
+
+impl RateLimit {
+    pub fn new(max: usize, window: i64) -> Self {
+        Self {
+            max,
+            window,
+            requests: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn retry_after_at(&self, key: &str, now: i64) -> Option<i64> {
+        let mut requests = self.requests.lock().unwrap();
+        requests.retain(|_, times| {
+            times.retain(|time| *time > now - self.window);
+            !times.is_empty()
+        });
+
+        let times = requests.get(key)?;
+        (times.len() >= self.max).then(|| times[0] + self.window - now)
+    }
+
+    fn hit_at(&self, key: &str, now: i64) {
+        self.requests.lock().unwrap().entry(key.to_string()).or_default().push(now);
+    }
+}
+
+const RESET_WINDOW: i64 = 3600;
+
+/// Password reset requests, per username and per client IP.
+pub struct ResetThrottle {
+    users: RateLimit,
+    ips: RateLimit,
+}
+
+impl ResetThrottle {
+    pub fn new(max_per_user: usize, max_per_ip: usize) -> Self {
+        Self {
+            users: RateLimit::new(max_per_user, RESET_WINDOW),
+            ips: RateLimit::new(max_per_ip, RESET_WINDOW),
+        }
+    }
+
+    /// Counts a request, unless one of the limits is reached: the seconds to wait are returned instead.
+    pub fn request(&self, username: &str, ip: Option<&str>) -> Option<i64> {
+        self.request_at(&LoginThrottle::user_key(username), ip, Utc::now().timestamp())
+    }
+
+    fn request_at(&self, user: &str, ip: Option<&str>, now: i64) -> Option<i64> {
+        let retry_after = self
+            .users
+            .retry_after_at(user, now)
+            .max(ip.and_then(|ip| self.ips.retry_after_at(ip, now)));
+        if retry_after.is_some() {
+            return retry_after;
+        }
+
+        self.users.hit_at(user, now);
+        if let Some(ip) = ip {
+            self.ips.hit_at(ip, now);
+        }
+        None
+    }
+}
+
+/// Client address in a header that every trusted proxy appends to, such as
+/// `X-Forwarded-For`: the entries on the left are set by the client, only the
+/// one added by the outermost of the `proxies` trusted proxies can be relied on.
+pub fn forwarded_client_ip(header: &str, proxies: usize) -> Option<String> {
+    let entries: Vec<&str> = header.split(',').map(|entry| entry.trim()).collect();
+    let entry = entries.get(entries.len().saturating_sub(proxies.max(1)))?;
+    (!entry.is_empty()).then(|| entry.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{forwarded_client_ip, LoginThrottle, MemoryAttemptStore, ResetThrottle, Throttle};
+
+    fn throttle() -> Throttle {
+        Throttle::new(Box::new(MemoryAttemptStore::new()), 4, 900)
+    }
+
+    #[test]
+    fn every_failure_doubles_the_delay() {
+        let throttle = throttle();
+        assert_eq!(throttle.retry_after_at("alice", 1000), None);
+
+        throttle.failure_at("alice", 1000);
+        assert_eq!(throttle.retry_after_at("alice", 1000), Some(1));
+        assert_eq!(throttle.retry_after_at("alice", 1001), None);
+
+        throttle.failure_at("alice", 1001);
+        throttle.failure_at("alice", 1001);
+        assert_eq!(throttle.retry_after_at("alice", 1001), Some(4));
+        assert_eq!(throttle.retry_after_at("alice", 1004), Some(1));
+        assert_eq!(throttle.retry_after_at("bob", 1001), None);
+    }
+
+    #[test]
+    fn too_many_failures_lock_the_key() {
+        let throttle = throttle();
+        for now in 1000..1004 {
+            throttle.failure_at("alice", now);
+        }
+
+        assert_eq!(throttle.retry_after_at("alice", 1003), Some(900));
+        assert_eq!(throttle.retry_after_at("alice", 1902), Some(1));
+        assert_eq!(throttle.retry_after_at("alice", 1903), None);
+
+        throttle.failure_at("alice", 1000);
+        throttle.clear("alice");
+        assert_eq!(throttle.retry_after_at("alice", 1000), None);
+    }
+
+    #[test]
+    fn failures_are_forgotten_after_the_lockout_duration() {
+        let throttle = throttle();
+        for now in [1000, 1010, 1020] {
+            throttle.failure_at("alice", now);
+        }
+        throttle.failure_at("alice", 1020 + 900);
+
+        assert_eq!(throttle.retry_after_at("alice", 1920), Some(1));
+    }
+
+    #[test]
+    fn concurrent_failures_are_all_counted() {
+        let throttle = Throttle::new(Box::new(MemoryAttemptStore::new()), 400, 900);
+        std::thread::scope(|scope| {
+            for _ in 0..8 {
+                scope.spawn(|| {
+                    for _ in 0..40 {
+                        throttle.failure_at("alice", 1000);
+                    }
+                });
+            }
+        });
+
+        assert_eq!(throttle.store.get("alice").unwrap().failures, 320);
+    }
+
+    #[test]
+    fn an_address_is_locked_whatever_the_username() {
+        let throttle = LoginThrottle::new(100, 3, 900);
+        for username in ["alice", "bob", "carole"] {
+            throttle.failure(username, Some("203.0.113.7"));
+        }
+
+        assert!(throttle.retry_after("dave", Some("203.0.113.7")).is_some_and(|s| s > 1));
+        assert!(throttle.retry_after("dave", Some("203.0.113.8")).is_none());
+        assert!(throttle.retry_after(&LoginThrottle::second_factor_key("alice"), None).is_none());
+    }
+
+    #[test]
+    fn reset_requests_are_limited_per_user_and_address() {
+        let throttle = ResetThrottle::new(2, 3);
+        let ip = Some("203.0.113.7");
+
+        assert_eq!(throttle.request_at("alice", ip, 1000), None);
+        assert_eq!(throttle.request_at("alice", ip, 1100), None);
+        assert_eq!(throttle.request_at("alice", None, 1200), Some(3400));
+        assert_eq!(throttle.request_at("bob", ip, 1200), None);
+        assert_eq!(throttle.request_at("carole", ip, 1300), Some(3300));
+        assert_eq!(throttle.request_at("carole", None, 1300), None);
+        assert_eq!(throttle.request_at("alice", None, 4600), None);
+    }
+
+    #[test]
+    fn the_client_cannot_choose_its_forwarded_address() {
+        assert_eq!(forwarded_client_ip("203.0.113.7", 1).as_deref(), Some("203.0.113.7"));
+        assert_eq!(forwarded_client_ip("1.2.3.4, 203.0.113.7", 1).as_deref(), Some("203.0.113.7"));
+        assert_eq!(forwarded_client_ip("1.2.3.4, 203.0.113.7, 10.0.0.2", 2).as_deref(), Some("203.0.113.7"));
+        assert_eq!(forwarded_client_ip("203.0.113.7", 2).as_deref(), Some("203.0.113.7"));
+        assert_eq!(forwarded_client_ip("", 1), None);
+    }
+}
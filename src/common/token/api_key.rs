@@ -0,0 +1,194 @@
+use std::{collections::HashMap, fs, io, path::PathBuf, sync::Mutex};
+
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::common::role::Role;
+
+use super::{hash_token, write_private};
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ApiKey {
+    pub id: String,
+    pub name: String,
+    pub scopes: Vec<Role>,
+    pub group: Option<String>,
+    pub created_by: String,
+    pub created_at: i64,
+}
+
+impl ApiKey {
+    /// Uid of the service account acting with this key, names are not unique.
+    pub fn uid(&self) -> String {
+        format!("service:{}", self.id)
+    }
+}
+
+pub trait ApiKeyStore: Send + Sync {
+    fn save(&self, hash: String, key: ApiKey) -> io::Result<()>;
+    fn find(&self, hash: &str) -> Option<ApiKey>;
+    fn list(&self) -> Vec<ApiKey>;
+    fn remove(&self, id: &str) -> io::Result<bool>;
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct ApiKeys {
+    keys: HashMap<String, ApiKey>,
+}
+
+impl ApiKeys {
+    fn list(&self) -> Vec<ApiKey> {
+        let mut keys: Vec<ApiKey> = self.keys.values().cloned().collect();
+        keys.sort_by_key(|k| k.created_at);
+        keys
+    }
+
+    fn remove(&mut self, id: &str) -> bool {
+        let count = self.keys.len();
+        self.keys.retain(|_, k| k.id != id);
+        self.keys.len() != count
+    }
+}
+
+#[derive(Default)]
+pub struct MemoryApiKeyStore {
+    keys: Mutex<ApiKeys>,
+}
+
+impl MemoryApiKeyStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl ApiKeyStore for MemoryApiKeyStore {
+    fn save(&self, hash: String, key: ApiKey) -> io::Result<()> {
+        self.keys.lock().unwrap().keys.insert(hash, key);
+        Ok(())
+    }
+
+    fn find(&self, hash: &str) -> Option<ApiKey> {
+        self.keys.lock().unwrap().keys.get(hash).cloned()
+    }
+
+    fn list(&self) -> Vec<ApiKey> {
+        self.keys.lock().unwrap().list()
+    }
+
+    fn remove(&self, id: &str) -> io::Result<bool> {
+        Ok(self.keys.lock().unwrap().remove(id))
+    }
+}
+
+pub struct FileApiKeyStore {
+    path: PathBuf,
+    keys: Mutex<ApiKeys>,
+}
+
+impl FileApiKeyStore {
+    pub fn new(path: PathBuf) -> Self {
+        let keys = match fs::read_to_string(&path) {
+            Ok(content) => serde_json::from_str(&content).expect("API_KEY_STORE must be a JSON file written by this server"),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => ApiKeys::default(),
+            Err(e) => panic!("API_KEY_STORE must be readable: {}", e),
+        };
+
+        Self {
+            path,
+            keys: Mutex::new(keys),
+        }
+    }
+
+    fn persist(&self, keys: &ApiKeys) -> io::Result<()> {
+        write_private(&self.path, &serde_json::to_string(keys)?)
+    }
+}
+
+impl ApiKeyStore for FileApiKeyStore {
+    /// A key that cannot be written is forgotten, it is never handed out.
+    fn save(&self, hash: String, key: ApiKey) -> io::Result<()> {
+        let mut keys = self.keys.lock().unwrap();
+        keys.keys.insert(hash.clone(), key);
+        self.persist(&keys).inspect_err(|_| drop(keys.keys.remove(&hash)))
+    }
+
+    fn find(&self, hash: &str) -> Option<ApiKey> {
+        self.keys.lock().unwrap().keys.get(hash).cloned()
+    }
+
+    fn list(&self) -> Vec<ApiKey> {
+        self.keys.lock().unwrap().list()
+    }
+
+    /// The key stays revoked in memory when the file cannot be written.
+    fn remove(&self, id: &str) -> io::Result<bool> {
+        let mut keys = self.keys.lock().unwrap();
+        let removed = keys.remove(id);
+        self.persist(&keys)?;
+        Ok(removed)
+    }
+}
+
+pub fn find_api_key(store: &dyn ApiKeyStore, key: &str) -> Option<ApiKey> {
+    store.find(&hash_token(key))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs};
+
+    use crate::common::{role::Role, token::{generate_token, hash_token}};
+
+    use super::{find_api_key, ApiKey, ApiKeyStore, FileApiKeyStore, MemoryApiKeyStore};
+
+    fn api_key(id: &str) -> ApiKey {
+        ApiKey {
+            id: id.to_string(),
+            name: "wiki".to_string(),
+            scopes: vec![Role::Member],
+            group: None,
+            created_by: "alice".to_string(),
+            created_at: 0,
+        }
+    }
+
+    #[test]
+    fn keys_are_found_by_their_hash_only() {
+        let store = MemoryApiKeyStore::new();
+        let key = generate_token();
+        store.save(hash_token(&key), api_key("a")).unwrap();
+
+        assert_eq!(find_api_key(&store, &key).unwrap().id, "a");
+        assert!(find_api_key(&store, &hash_token(&key)).is_none());
+        assert!(find_api_key(&store, &generate_token()).is_none());
+    }
+
+    #[test]
+    fn keys_with_the_same_name_act_as_different_accounts() {
+        let store = MemoryApiKeyStore::new();
+        let (first, second) = (generate_token(), generate_token());
+        store.save(hash_token(&first), api_key("a")).unwrap();
+        store.save(hash_token(&second), api_key("b")).unwrap();
+
+        assert_ne!(find_api_key(&store, &first).unwrap().uid(), find_api_key(&store, &second).unwrap().uid());
+        assert!(store.remove("a").unwrap());
+        assert!(find_api_key(&store, &first).is_none());
+        assert!(find_api_key(&store, &second).is_some());
+    }
+
+    #[test]
+    fn keys_survive_a_restart_and_write_errors_are_returned() {
+        let dir = env::temp_dir().join(format!("api-keys-{}", std::process::id()));
+        fs::create_dir(&dir).unwrap();
+        let store = FileApiKeyStore::new(dir.join("keys.json"));
+        let (first, second) = (generate_token(), generate_token());
+        store.save(hash_token(&first), api_key("a")).unwrap();
+        assert!(find_api_key(&FileApiKeyStore::new(dir.join("keys.json")), &first).is_some());
+
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(store.save(hash_token(&second), api_key("b")).is_err());
+        assert!(find_api_key(&store, &second).is_none());
+        assert!(store.remove("a").is_err());
+        assert!(find_api_key(&store, &first).is_none());
+    }
+}
@@ -0,0 +1,136 @@
+use std::{collections::HashMap, fs, io, path::PathBuf, sync::Mutex};
+
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+
+use super::write_private;
+
+#[derive(Default, Serialize, Deserialize)]
+struct Revoked {
+    /// Expiration of each revoked token, in seconds.
+    tokens: HashMap<String, usize>,
+    /// When every token of a user was revoked, in milliseconds.
+    users: HashMap<String, i64>,
+}
+
+pub struct Denylist {
+    revoked: Mutex<Revoked>,
+    maxage: usize,
+    path: Option<PathBuf>,
+}
+
+impl Denylist {
+    /// `maxage` is the lifetime of an access token in seconds, after which a
+    /// revoked user entry no longer matches any valid token. Revocations are
+    /// written to `path` when it is set, so a restart does not forget them.
+    pub fn new(maxage: usize, path: Option<PathBuf>) -> Self {
+        let revoked = match &path {
+            Some(path) => match fs::read_to_string(path) {
+                Ok(content) => serde_json::from_str(&content).expect("DENYLIST_STORE must be a JSON file written by this server"),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => Revoked::default(),
+                Err(e) => panic!("DENYLIST_STORE must be readable: {}", e),
+            },
+            None => Revoked::default(),
+        };
+
+        Self {
+            revoked: Mutex::new(revoked),
+            maxage,
+            path,
+        }
+    }
+
+    pub fn revoke_token(&self, jti: String, exp: usize) -> io::Result<()> {
+        let mut revoked = self.revoked.lock().unwrap();
+        self.prune(&mut revoked, Utc::now().timestamp_millis());
+        revoked.tokens.insert(jti, exp);
+        self.persist(&revoked)
+    }
+
+    /// Revoke every token issued to `username` before now.
+    pub fn revoke_user(&self, username: String) -> io::Result<()> {
+        self.revoke_user_at(username, Utc::now().timestamp_millis())
+    }
+
+    fn revoke_user_at(&self, username: String, now: i64) -> io::Result<()> {
+        let mut revoked = self.revoked.lock().unwrap();
+        self.prune(&mut revoked, now);
+        revoked.users.insert(username, now);
+        self.persist(&revoked)
+    }
+
+    /// `issued_at` is in milliseconds: a token issued in the same second as
+    /// the revocation of its user, but after it, stays valid.
+    pub fn is_revoked(&self, jti: &str, username: &str, issued_at: i64) -> bool {
+        let revoked = self.revoked.lock().unwrap();
+        if revoked.tokens.contains_key(jti) {
+            return true;
+        }
+
+        match revoked.users.get(username) {
+            Some(revoked_at) => issued_at <= *revoked_at,
+            None => false,
+        }
+    }
+
+    fn prune(&self, revoked: &mut Revoked, now: i64) {
+        let maxage = self.maxage as i64 * 1000;
+        revoked.tokens.retain(|_, exp| *exp as i64 * 1000 >= now);
+        revoked.users.retain(|_, revoked_at| *revoked_at + maxage >= now);
+    }
+
+    fn persist(&self, revoked: &Revoked) -> io::Result<()> {
+        match &self.path {
+            Some(path) => write_private(path, &serde_json::to_string(revoked)?),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use chrono::Utc;
+
+    use super::Denylist;
+
+    #[test]
+    fn tokens_issued_up_to_the_revocation_are_refused() {
+        let denylist = Denylist::new(900, None);
+        let now = Utc::now().timestamp_millis();
+        denylist.revoke_user_at("alice".to_string(), now).unwrap();
+
+        assert!(denylist.is_revoked("a", "alice", now - 1500));
+        assert!(denylist.is_revoked("a", "alice", now));
+        assert!(!denylist.is_revoked("a", "alice", now + 1));
+        assert!(!denylist.is_revoked("a", "bob", now - 1500));
+    }
+
+    #[test]
+    fn a_revoked_token_is_refused_until_it_expires() {
+        let denylist = Denylist::new(900, None);
+        let now = Utc::now().timestamp() as usize;
+        denylist.revoke_token("a".to_string(), now + 60).unwrap();
+        denylist.revoke_token("b".to_string(), now - 60).unwrap();
+        // Pruned on the next revocation
+        denylist.revoke_token("c".to_string(), now + 60).unwrap();
+
+        assert!(denylist.is_revoked("a", "alice", 0));
+        assert!(!denylist.revoked.lock().unwrap().tokens.contains_key("b"));
+    }
+
+    #[test]
+    fn revocations_survive_a_restart() {
+        let path = env::temp_dir().join(format!("denylist-{}.json", std::process::id()));
+        let now = Utc::now();
+        let denylist = Denylist::new(900, Some(path.clone()));
+        denylist.revoke_token("a".to_string(), now.timestamp() as usize + 60).unwrap();
+        denylist.revoke_user("alice".to_string()).unwrap();
+
+        let denylist = Denylist::new(900, Some(path.clone()));
+        std::fs::remove_file(&path).unwrap();
+        assert!(denylist.is_revoked("a", "bob", now.timestamp_millis()));
+        assert!(denylist.is_revoked("b", "alice", now.timestamp_millis()));
+    }
+}
@@ -0,0 +1,277 @@
+use std::fs;
+
+use base64::prelude::*;
+use chrono::Utc;
+use jsonwebtoken::{
+    decode, decode_header, encode,
+    jwk::{AlgorithmParameters, CommonParameters, EllipticCurve, Jwk, JwkSet, KeyAlgorithm, OctetKeyPairParameters, OctetKeyPairType, PublicKeyUse, RSAKeyParameters, RSAKeyType},
+    Algorithm, DecodingKey, EncodingKey, Header, TokenData, Validation,
+};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::{json, Value};
+use simple_asn1::ASN1Block;
+
+use crate::common::Config;
+
+struct VerificationKey {
+    kid: Option<String>,
+    algorithm: Algorithm,
+    key: DecodingKey,
+}
+
+pub struct JwtKeys {
+    algorithm: Algorithm,
+    kid: Option<String>,
+    encoding: EncodingKey,
+    verification: Vec<VerificationKey>,
+    jwks: JwkSet,
+}
+
+impl JwtKeys {
+    pub fn new(config: &Config) -> Self {
+        let algorithm = match config.jwt_algorithm.as_str() {
+            "HS256" => Algorithm::HS256,
+            "RS256" => Algorithm::RS256,
+            "EdDSA" => Algorithm::EdDSA,
+            _ => panic!("JWT_ALGORITHM must be one of HS256, RS256 or EdDSA"),
+        };
+
+        if algorithm == Algorithm::HS256 {
+            if config.jwt_secret.is_empty() {
+                panic!("JWT_SECRET must be set");
+            }
+            return Self {
+                algorithm,
+                kid: None,
+                encoding: EncodingKey::from_secret(config.jwt_secret.as_ref()),
+                verification: vec![VerificationKey {
+                    kid: None,
+                    algorithm,
+                    key: DecodingKey::from_secret(config.jwt_secret.as_ref()),
+                }],
+                jwks: JwkSet { keys: vec![] },
+            };
+        }
+
+        let private_key = config.jwt_private_key.as_ref().expect("JWT_PRIVATE_KEY must be set");
+        let private_key = fs::read(private_key).expect("JWT_PRIVATE_KEY must be readable");
+        let kid = config.jwt_kid.clone().expect("JWT_KID must be set");
+        let public_key = config.jwt_public_key.as_ref().expect("JWT_PUBLIC_KEY must be set");
+
+        let mut keys = vec![(kid.clone(), public_key.clone())];
+        keys.extend(config.jwt_verification_keys.iter().cloned());
+        let public_keys = keys
+            .into_iter()
+            .map(|(kid, path)| {
+                let pem = fs::read(&path).unwrap_or_else(|_| panic!("Public key {} must be readable", path));
+                (kid, path, pem)
+            })
+            .collect();
+
+        Self::with_keys(algorithm, &private_key, kid, public_keys)
+    }
+
+    /// `public_keys` are `(kid, path, pem)`, the first one belongs to the signing key.
+    fn with_keys(algorithm: Algorithm, private_key: &[u8], kid: String, public_keys: Vec<(String, String, Vec<u8>)>) -> Self {
+        let encoding = match algorithm {
+            Algorithm::RS256 => EncodingKey::from_rsa_pem(private_key),
+            _ => EncodingKey::from_ed_pem(private_key),
+        }
+        .expect("JWT_PRIVATE_KEY must be a valid PEM private key");
+
+        let mut verification = vec![];
+        let mut jwks = JwkSet { keys: vec![] };
+        for (kid, path, pem) in public_keys {
+            let (key, jwk) = public_key_from_pem(&kid, &pem).unwrap_or_else(|| panic!("Public key {} must be an RSA or Ed25519 PEM public key", path));
+            let algorithm = match jwk.algorithm {
+                AlgorithmParameters::RSA(_) => Algorithm::RS256,
+                _ => Algorithm::EdDSA,
+            };
+            verification.push(VerificationKey {
+                kid: Some(kid),
+                algorithm,
+                key,
+            });
+            jwks.keys.push(jwk);
+        }
+
+        let keys = Self {
+            algorithm,
+            kid: Some(kid),
+            encoding,
+            verification,
+            jwks,
+        };
+
+        // A private key that does not match its public key issues tokens no verifier accepts
+        let probe = json!({ "exp": Utc::now().timestamp() + 60 });
+        let token = keys.encode(&probe).expect("JWT_PRIVATE_KEY must be able to sign with JWT_ALGORITHM");
+        if keys.decode::<Value>(&token).is_err() {
+            panic!("JWT_PRIVATE_KEY must match JWT_PUBLIC_KEY");
+        }
+
+        keys
+    }
+
+    pub fn encode<T: Serialize>(&self, claims: &T) -> jsonwebtoken::errors::Result<String> {
+        let mut header = Header::new(self.algorithm);
+        header.kid = self.kid.clone();
+        encode(&header, claims, &self.encoding)
+    }
+
+    pub fn decode<T: DeserializeOwned>(&self, token: &str) -> jsonwebtoken::errors::Result<TokenData<T>> {
+        let header = decode_header(token)?;
+        let key = self
+            .verification
+            .iter()
+            .find(|k| k.algorithm == header.alg && (header.kid.is_none() || k.kid == header.kid))
+            .ok_or(jsonwebtoken::errors::ErrorKind::InvalidAlgorithm)?;
+
+        decode(token, &key.key, &Validation::new(key.algorithm))
+    }
+
+    pub fn jwks(&self) -> &JwkSet {
+        &self.jwks
+    }
+}
+
+/// Read a SubjectPublicKeyInfo PEM and build both the decoding key and its JWK.
+fn public_key_from_pem(kid: &str, pem: &[u8]) -> Option<(DecodingKey, Jwk)> {
+    let pem = pem::parse(pem).ok()?;
+    let spki = simple_asn1::from_der(pem.contents()).ok()?;
+
+    let (algorithm, bits) = match spki.first()? {
+        ASN1Block::Sequence(_, fields) => match fields.as_slice() {
+            [ASN1Block::Sequence(_, algorithm), ASN1Block::BitString(_, _, bits)] => match algorithm.first()? {
+                ASN1Block::ObjectIdentifier(_, oid) => (oid.clone(), bits),
+                _ => return None,
+            },
+            _ => return None,
+        },
+        _ => return None,
+    };
+
+    let mut common = CommonParameters {
+        public_key_use: Some(PublicKeyUse::Signature),
+        key_id: Some(kid.to_string()),
+        ..Default::default()
+    };
+
+    if algorithm == simple_asn1::oid!(1, 2, 840, 113549, 1, 1, 1) {
+        let rsa = simple_asn1::from_der(bits).ok()?;
+        let (n, e) = match rsa.first()? {
+            ASN1Block::Sequence(_, fields) => match fields.as_slice() {
+                [ASN1Block::Integer(_, n), ASN1Block::Integer(_, e)] => (n.to_bytes_be().1, e.to_bytes_be().1),
+                _ => return None,
+            },
+            _ => return None,
+        };
+        common.key_algorithm = Some(KeyAlgorithm::RS256);
+        let jwk = Jwk {
+            common,
+            algorithm: AlgorithmParameters::RSA(RSAKeyParameters {
+                key_type: RSAKeyType::RSA,
+                n: BASE64_URL_SAFE_NO_PAD.encode(&n),
+                e: BASE64_URL_SAFE_NO_PAD.encode(&e),
+            }),
+        };
+        return Some((DecodingKey::from_rsa_raw_components(&n, &e), jwk));
+    }
+
+    if algorithm == simple_asn1::oid!(1, 3, 101, 112) && bits.len() == 32 {
+        common.key_algorithm = Some(KeyAlgorithm::EdDSA);
+        let jwk = Jwk {
+            common,
+            algorithm: AlgorithmParameters::OctetKeyPair(OctetKeyPairParameters {
+                key_type: OctetKeyPairType::OctetKeyPair,
+                curve: EllipticCurve::Ed25519,
+                x: BASE64_URL_SAFE_NO_PAD.encode(bits),
+            }),
+        };
+        return Some((DecodingKey::from_ed_der(bits), jwk));
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use jsonwebtoken::{jwk::{AlgorithmParameters, KeyAlgorithm}, Algorithm};
+    use serde::{Deserialize, Serialize};
+
+    use super::{public_key_from_pem, JwtKeys};
+
+    const RSA_PRIVATE: &[u8] = include_bytes!("testdata/rsa.pem");
+    const RSA_PUBLIC: &[u8] = include_bytes!("testdata/rsa.pub");
+    const RSA_OLD_PUBLIC: &[u8] = include_bytes!("testdata/rsa_old.pub");
+    const ED25519_PRIVATE: &[u8] = include_bytes!("testdata/ed25519.pem");
+    const ED25519_PUBLIC: &[u8] = include_bytes!("testdata/ed25519.pub");
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Claims {
+        sub: String,
+        exp: usize,
+    }
+
+    fn claims() -> Claims {
+        Claims { sub: "alice".to_string(), exp: chrono::Utc::now().timestamp() as usize + 60 }
+    }
+
+    fn jwt_keys(algorithm: Algorithm, private_key: &[u8], kid: &str, public_keys: &[(&str, &[u8])]) -> JwtKeys {
+        let public_keys = public_keys.iter().map(|(kid, pem)| (kid.to_string(), kid.to_string(), pem.to_vec())).collect();
+        JwtKeys::with_keys(algorithm, private_key, kid.to_string(), public_keys)
+    }
+
+    #[test]
+    fn public_keys_are_read_by_their_algorithm() {
+        let (_, jwk) = public_key_from_pem("rsa", RSA_PUBLIC).unwrap();
+        assert_eq!(jwk.common.key_id.as_deref(), Some("rsa"));
+        assert_eq!(jwk.common.key_algorithm, Some(KeyAlgorithm::RS256));
+        assert!(matches!(jwk.algorithm, AlgorithmParameters::RSA(ref rsa) if rsa.e == "AQAB"));
+
+        let (_, jwk) = public_key_from_pem("ed", ED25519_PUBLIC).unwrap();
+        assert_eq!(jwk.common.key_algorithm, Some(KeyAlgorithm::EdDSA));
+        assert!(matches!(jwk.algorithm, AlgorithmParameters::OctetKeyPair(ref okp) if okp.x == "_joNmstlQzcyNa0ImeA3OV-cJvwhgqGS6DCDYvp0Cjo"));
+
+        // X25519 keys are 32 bytes too, but they cannot sign
+        assert!(public_key_from_pem("x", include_bytes!("testdata/x25519.pub")).is_none());
+        assert!(public_key_from_pem("ec", include_bytes!("testdata/p256.pub")).is_none());
+        assert!(public_key_from_pem("pem", RSA_PRIVATE).is_none());
+    }
+
+    #[test]
+    fn every_public_key_is_published() {
+        let keys = jwt_keys(Algorithm::RS256, RSA_PRIVATE, "2024", &[("2024", RSA_PUBLIC), ("2023", RSA_OLD_PUBLIC), ("ed", ED25519_PUBLIC)]);
+        let kids: Vec<_> = keys.jwks().keys.iter().map(|k| k.common.key_id.clone().unwrap()).collect();
+        assert_eq!(kids, vec!["2024", "2023", "ed"]);
+    }
+
+    #[test]
+    fn tokens_are_verified_with_the_key_of_their_kid_and_algorithm() {
+        let keys = jwt_keys(Algorithm::RS256, RSA_PRIVATE, "2024", &[("2024", RSA_PUBLIC), ("2023", RSA_OLD_PUBLIC)]);
+        let token = keys.encode(&claims()).unwrap();
+        assert_eq!(jsonwebtoken::decode_header(&token).unwrap().kid.as_deref(), Some("2024"));
+        assert_eq!(keys.decode::<Claims>(&token).unwrap().claims.sub, "alice");
+
+        // Signed by the current key but announced as the old one
+        let mislabelled = jwt_keys(Algorithm::RS256, RSA_PRIVATE, "2023", &[("2023", RSA_PUBLIC)]).encode(&claims()).unwrap();
+        assert!(keys.decode::<Claims>(&mislabelled).is_err());
+
+        let eddsa = jwt_keys(Algorithm::EdDSA, ED25519_PRIVATE, "ed", &[("ed", ED25519_PUBLIC)]);
+        let token = eddsa.encode(&claims()).unwrap();
+        assert_eq!(eddsa.decode::<Claims>(&token).unwrap().claims.sub, "alice");
+        assert!(keys.decode::<Claims>(&token).is_err());
+    }
+
+    #[test]
+    #[should_panic(expected = "JWT_PRIVATE_KEY must match JWT_PUBLIC_KEY")]
+    fn a_private_key_must_match_its_public_key() {
+        jwt_keys(Algorithm::RS256, RSA_PRIVATE, "2024", &[("2024", RSA_OLD_PUBLIC), ("2023", RSA_PUBLIC)]);
+    }
+
+    #[test]
+    #[should_panic(expected = "JWT_PRIVATE_KEY must match JWT_PUBLIC_KEY")]
+    fn the_public_key_must_be_of_the_algorithm() {
+        jwt_keys(Algorithm::EdDSA, ED25519_PRIVATE, "ed", &[("ed", RSA_PUBLIC)]);
+    }
+}
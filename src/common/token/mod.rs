@@ -0,0 +1,63 @@
+mod refresh;
+mod denylist;
+mod keys;
+mod api_key;
+mod reset;
+
+use std::{fs::{OpenOptions, Permissions}, io::{self, Write}, os::unix::fs::{OpenOptionsExt, PermissionsExt}, path::Path};
+
+use base64::prelude::*;
+use sha2::{Digest, Sha256};
+
+pub use refresh::{consume_refresh_token, issue_refresh_token, FileRefreshStore, MemoryRefreshStore, RefreshError, RefreshRecord, RefreshStore};
+pub use denylist::Denylist;
+pub use keys::JwtKeys;
+pub use api_key::{find_api_key, ApiKey, ApiKeyStore, FileApiKeyStore, MemoryApiKeyStore};
+pub use reset::ResetTokens;
+
+pub fn generate_token() -> String {
+    BASE64_URL_SAFE_NO_PAD.encode(rand::random::<[u8; 32]>())
+}
+
+pub fn hash_token(token: &str) -> String {
+    BASE64_URL_SAFE_NO_PAD.encode(Sha256::digest(token.as_bytes()))
+}
+
+/// Write a store holding token hashes, only the server may read it.
+fn write_private(path: &Path, content: &str) -> io::Result<()> {
+    let mut file = OpenOptions::new().write(true).create(true).truncate(true).mode(0o600).open(path)?;
+    // `mode` only applies to a new file
+    file.set_permissions(Permissions::from_mode(0o600))?;
+    file.write_all(content.as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, os::unix::fs::PermissionsExt};
+
+    use super::{generate_token, hash_token, write_private};
+
+    #[test]
+    fn tokens_are_hashed_with_sha256() {
+        // SHA-256 of "abc", FIPS 180-2
+        assert_eq!(hash_token("abc"), "ungWv48Bz-pBQUDeXa4iI7ADYaOWF3qctBD_YfIAFa0");
+
+        let token = generate_token();
+        assert_eq!(token.len(), 43);
+        assert_ne!(token, generate_token());
+        assert_eq!(hash_token(&token), hash_token(&token));
+    }
+
+    #[test]
+    fn stores_are_only_readable_by_the_server() {
+        let path = env::temp_dir().join(format!("private-{}.json", std::process::id()));
+        fs::write(&path, "{}").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o644)).unwrap();
+
+        write_private(&path, "[]").unwrap();
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "[]");
+        fs::remove_file(&path).unwrap();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+}
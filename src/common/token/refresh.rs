@@ -0,0 +1,232 @@
+use std::{collections::HashMap, fs, io, path::PathBuf, sync::Mutex};
+
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+
+use super::{generate_token, hash_token, write_private};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RefreshRecord {
+    pub family: String,
+    pub username: String,
+    pub expires_at: i64,
+    pub used: bool,
+}
+
+/// A file-backed store still applies a change it failed to write, but
+/// returns the error: it would be lost on the next restart.
+pub trait RefreshStore: Send + Sync {
+    fn save(&self, hash: String, record: RefreshRecord) -> io::Result<()>;
+    /// Mark the token as used and return it as it was before, so a record
+    /// with `used == true` means the token had already been rotated.
+    fn take(&self, hash: &str) -> io::Result<Option<RefreshRecord>>;
+    fn revoke_family(&self, family: &str) -> io::Result<()>;
+    fn revoke_user(&self, username: &str) -> io::Result<()>;
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct RefreshTokens {
+    tokens: HashMap<String, RefreshRecord>,
+}
+
+impl RefreshTokens {
+    fn save(&mut self, hash: String, record: RefreshRecord) {
+        let now = Utc::now().timestamp();
+        self.tokens.retain(|_, r| r.expires_at > now);
+        self.tokens.insert(hash, record);
+    }
+
+    fn take(&mut self, hash: &str) -> Option<RefreshRecord> {
+        let record = self.tokens.get_mut(hash)?;
+        let previous = record.clone();
+        record.used = true;
+        Some(previous)
+    }
+
+    fn revoke_family(&mut self, family: &str) {
+        self.tokens.retain(|_, r| r.family != family);
+    }
+
+    fn revoke_user(&mut self, username: &str) {
+        self.tokens.retain(|_, r| r.username != username);
+    }
+}
+
+#[derive(Default)]
+pub struct MemoryRefreshStore {
+    tokens: Mutex<RefreshTokens>,
+}
+
+impl MemoryRefreshStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl RefreshStore for MemoryRefreshStore {
+    fn save(&self, hash: String, record: RefreshRecord) -> io::Result<()> {
+        self.tokens.lock().unwrap().save(hash, record);
+        Ok(())
+    }
+
+    fn take(&self, hash: &str) -> io::Result<Option<RefreshRecord>> {
+        Ok(self.tokens.lock().unwrap().take(hash))
+    }
+
+    fn revoke_family(&self, family: &str) -> io::Result<()> {
+        self.tokens.lock().unwrap().revoke_family(family);
+        Ok(())
+    }
+
+    fn revoke_user(&self, username: &str) -> io::Result<()> {
+        self.tokens.lock().unwrap().revoke_user(username);
+        Ok(())
+    }
+}
+
+pub struct FileRefreshStore {
+    path: PathBuf,
+    tokens: Mutex<RefreshTokens>,
+}
+
+impl FileRefreshStore {
+    pub fn new(path: PathBuf) -> Self {
+        let tokens = match fs::read_to_string(&path) {
+            Ok(content) => serde_json::from_str(&content).expect("REFRESH_STORE must be a JSON file written by this server"),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => RefreshTokens::default(),
+            Err(e) => panic!("REFRESH_STORE must be readable: {}", e),
+        };
+
+        Self {
+            path,
+            tokens: Mutex::new(tokens),
+        }
+    }
+
+    fn persist(&self, tokens: &RefreshTokens) -> io::Result<()> {
+        write_private(&self.path, &serde_json::to_string(tokens)?)
+    }
+}
+
+impl RefreshStore for FileRefreshStore {
+    fn save(&self, hash: String, record: RefreshRecord) -> io::Result<()> {
+        let mut tokens = self.tokens.lock().unwrap();
+        tokens.save(hash, record);
+        self.persist(&tokens)
+    }
+
+    fn take(&self, hash: &str) -> io::Result<Option<RefreshRecord>> {
+        let mut tokens = self.tokens.lock().unwrap();
+        let record = tokens.take(hash);
+        self.persist(&tokens)?;
+        Ok(record)
+    }
+
+    fn revoke_family(&self, family: &str) -> io::Result<()> {
+        let mut tokens = self.tokens.lock().unwrap();
+        tokens.revoke_family(family);
+        self.persist(&tokens)
+    }
+
+    fn revoke_user(&self, username: &str) -> io::Result<()> {
+        let mut tokens = self.tokens.lock().unwrap();
+        tokens.revoke_user(username);
+        self.persist(&tokens)
+    }
+}
+
+#[derive(Debug)]
+pub enum RefreshError {
+    Invalid,
+    Expired,
+    Reused,
+    Store(io::Error),
+}
+
+impl From<io::Error> for RefreshError {
+    fn from(e: io::Error) -> Self {
+        Self::Store(e)
+    }
+}
+
+/// Issue a refresh token, starting a new family when none is given.
+pub fn issue_refresh_token(store: &dyn RefreshStore, username: String, family: Option<String>, maxage: i64) -> io::Result<String> {
+    let token = generate_token();
+    let record = RefreshRecord {
+        family: family.unwrap_or_else(generate_token),
+        username,
+        expires_at: Utc::now().timestamp() + maxage * 60,
+        used: false,
+    };
+    store.save(hash_token(&token), record)?;
+    Ok(token)
+}
+
+/// Consume a refresh token, revoking its whole family if it had already been used.
+pub fn consume_refresh_token(store: &dyn RefreshStore, token: &str) -> Result<RefreshRecord, RefreshError> {
+    let record = store.take(&hash_token(token))?.ok_or(RefreshError::Invalid)?;
+
+    if record.used {
+        store.revoke_family(&record.family)?;
+        return Err(RefreshError::Reused);
+    }
+
+    if record.expires_at < Utc::now().timestamp() {
+        return Err(RefreshError::Expired);
+    }
+
+    Ok(record)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::{consume_refresh_token, issue_refresh_token, FileRefreshStore, MemoryRefreshStore, RefreshError, RefreshStore};
+
+    #[test]
+    fn a_token_is_rotated_within_its_family() {
+        let store = MemoryRefreshStore::new();
+        let first = issue_refresh_token(&store, "alice".to_string(), None, 60).unwrap();
+        let record = consume_refresh_token(&store, &first).unwrap();
+        assert_eq!(record.username, "alice");
+
+        let second = issue_refresh_token(&store, record.username, Some(record.family.clone()), 60).unwrap();
+        assert_eq!(consume_refresh_token(&store, &second).unwrap().family, record.family);
+        assert!(matches!(consume_refresh_token(&store, "unknown"), Err(RefreshError::Invalid)));
+    }
+
+    #[test]
+    fn reusing_a_token_revokes_its_family() {
+        let store = MemoryRefreshStore::new();
+        let first = issue_refresh_token(&store, "alice".to_string(), None, 60).unwrap();
+        let family = consume_refresh_token(&store, &first).unwrap().family;
+        let second = issue_refresh_token(&store, "alice".to_string(), Some(family), 60).unwrap();
+        let other = issue_refresh_token(&store, "alice".to_string(), None, 60).unwrap();
+
+        assert!(matches!(consume_refresh_token(&store, &first), Err(RefreshError::Reused)));
+        assert!(matches!(consume_refresh_token(&store, &second), Err(RefreshError::Invalid)));
+        // Another session of the same user is left alone
+        assert!(consume_refresh_token(&store, &other).is_ok());
+    }
+
+    #[test]
+    fn an_expired_token_is_refused() {
+        let store = MemoryRefreshStore::new();
+        let token = issue_refresh_token(&store, "alice".to_string(), None, -1).unwrap();
+        assert!(matches!(consume_refresh_token(&store, &token), Err(RefreshError::Expired)));
+    }
+
+    #[test]
+    fn write_errors_are_returned() {
+        let dir = env::temp_dir().join(format!("refresh-{}", std::process::id()));
+        std::fs::create_dir(&dir).unwrap();
+        let store = FileRefreshStore::new(dir.join("tokens.json"));
+        let token = issue_refresh_token(&store, "alice".to_string(), None, 60).unwrap();
+        assert!(FileRefreshStore::new(dir.join("tokens.json")).take(&super::hash_token(&token)).unwrap().is_some());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(issue_refresh_token(&store, "alice".to_string(), None, 60).is_err());
+        assert!(store.revoke_user("alice").is_err());
+    }
+}
@@ -0,0 +1,77 @@
+use std::{collections::HashMap, sync::Mutex};
+
+use chrono::Utc;
+
+use super::{generate_token, hash_token};
+
+struct ResetToken {
+    username: String,
+    expires_at: i64,
+}
+
+/// Password reset tokens, only their hash is kept.
+pub struct ResetTokens {
+    maxage: i64,
+    tokens: Mutex<HashMap<String, ResetToken>>,
+}
+
+impl ResetTokens {
+    pub fn new(maxage: i64) -> Self {
+        Self {
+            maxage,
+            tokens: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// The previous tokens of the user stay valid, so that anyone asking for a
+    /// reset cannot invalidate the link a member just received.
+    pub fn issue(&self, username: String) -> String {
+        let now = Utc::now().timestamp();
+        let token = generate_token();
+
+        let mut tokens = self.tokens.lock().unwrap();
+        tokens.retain(|_, t| t.expires_at > now);
+        tokens.insert(hash_token(&token), ResetToken {
+            username,
+            expires_at: now + self.maxage,
+        });
+
+        token
+    }
+
+    pub fn username(&self, token: &str) -> Option<String> {
+        let tokens = self.tokens.lock().unwrap();
+        let token = tokens.get(&hash_token(token))?;
+        (token.expires_at >= Utc::now().timestamp()).then(|| token.username.clone())
+    }
+
+    /// Using a token invalidates every other token of the user.
+    pub fn take(&self, token: &str) -> Option<String> {
+        let mut tokens = self.tokens.lock().unwrap();
+        let token = tokens.remove(&hash_token(token))?;
+        if token.expires_at < Utc::now().timestamp() {
+            return None;
+        }
+        tokens.retain(|_, t| t.username != token.username);
+        Some(token.username)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ResetTokens;
+
+    #[test]
+    fn a_new_request_keeps_the_previous_link_until_one_is_used() {
+        let tokens = ResetTokens::new(60);
+        let first = tokens.issue("alice".to_string());
+        let second = tokens.issue("alice".to_string());
+        let other = tokens.issue("bob".to_string());
+
+        assert_eq!(tokens.username(&first).as_deref(), Some("alice"));
+        assert_eq!(tokens.take(&second).as_deref(), Some("alice"));
+        assert_eq!(tokens.username(&first), None);
+        assert_eq!(tokens.take(&second), None);
+        assert_eq!(tokens.username(&other).as_deref(), Some("bob"));
+    }
+}
@@ -0,0 +1,186 @@
+use std::{collections::HashMap, sync::Mutex};
+
+use base32::Alphabet;
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use sha1::Sha1;
+use subtle::ConstantTimeEq;
+
+use super::token::hash_token;
+
+const PERIOD: i64 = 30;
+const DIGITS: u32 = 6;
+const SECRET_SIZE: usize = 20;
+const ALPHABET: Alphabet = Alphabet::Rfc4648 { padding: false };
+
+pub struct Totp {
+    secret: Vec<u8>,
+}
+
+impl Totp {
+    pub fn generate() -> Self {
+        Self {
+            secret: rand::random::<[u8; SECRET_SIZE]>().to_vec(),
+        }
+    }
+
+    pub fn from_base32(secret: &str) -> Option<Self> {
+        base32::decode(ALPHABET, secret).map(|secret| Self { secret })
+    }
+
+    pub fn to_base32(&self) -> String {
+        base32::encode(ALPHABET, &self.secret)
+    }
+
+    pub fn uri(&self, issuer: &str, account: &str) -> String {
+        format!(
+            "otpauth://totp/{}:{}?secret={}&issuer={}&algorithm=SHA1&digits={}&period={}",
+            urlencoding::encode(issuer),
+            urlencoding::encode(account),
+            self.to_base32(),
+            urlencoding::encode(issuer),
+            DIGITS,
+            PERIOD
+        )
+    }
+
+    fn code_at(&self, step: i64) -> String {
+        let mut mac = Hmac::<Sha1>::new_from_slice(&self.secret).unwrap();
+        mac.update(&step.to_be_bytes());
+        let hash = mac.finalize().into_bytes();
+
+        let offset = (hash[hash.len() - 1] & 0x0f) as usize;
+        let value = u32::from_be_bytes([hash[offset] & 0x7f, hash[offset + 1], hash[offset + 2], hash[offset + 3]]);
+        format!("{:0width$}", value % 10u32.pow(DIGITS), width = DIGITS as usize)
+    }
+
+    /// Return the time step matching `code`, allowing one step of clock drift.
+    pub fn verify(&self, code: &str) -> Option<i64> {
+        let step = Utc::now().timestamp() / PERIOD;
+        let code = code.trim().as_bytes();
+        (step - 1..=step + 1).find(|s| bool::from(self.code_at(*s).as_bytes().ct_eq(code)))
+    }
+}
+
+pub fn generate_recovery_codes(count: usize) -> Vec<String> {
+    (0..count)
+        .map(|_| {
+            let code = base32::encode(ALPHABET, &rand::random::<[u8; 7]>());
+            format!("{}-{}", &code[..5], &code[5..10])
+        })
+        .collect()
+}
+
+pub fn normalize_recovery_code(code: &str) -> String {
+    code.trim().replace('-', "").to_uppercase()
+}
+
+/// The stored hash of `code`, if it is one of `hashes`.
+pub fn find_recovery_code<'a>(hashes: &'a [String], code: &str) -> Option<&'a String> {
+    let hash = hash_token(&normalize_recovery_code(code));
+    hashes.iter().find(|h| bool::from(h.as_bytes().ct_eq(hash.as_bytes())))
+}
+
+/// Secrets waiting for their first code, the last step used by each user so
+/// that a code cannot be replayed, and the wrong codes sent with each second-step token.
+#[derive(Default)]
+pub struct TotpState {
+    pending: Mutex<HashMap<String, String>>,
+    last_steps: Mutex<HashMap<String, i64>>,
+    failures: Mutex<HashMap<String, (u32, usize)>>,
+}
+
+impl TotpState {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set_pending(&self, uid: String, secret: String) {
+        self.pending.lock().unwrap().insert(uid, secret);
+    }
+
+    pub fn pending(&self, uid: &str) -> Option<String> {
+        self.pending.lock().unwrap().get(uid).cloned()
+    }
+
+    pub fn remove_pending(&self, uid: &str) {
+        self.pending.lock().unwrap().remove(uid);
+    }
+
+    pub fn use_step(&self, uid: &str, step: i64) -> bool {
+        let mut last_steps = self.last_steps.lock().unwrap();
+        match last_steps.get(uid) {
+            Some(last) if *last >= step => false,
+            _ => {
+                last_steps.insert(uid.to_string(), step);
+                true
+            }
+        }
+    }
+
+    /// Counts a wrong code sent with the token `jti`, expiring at `exp`, and returns the total.
+    pub fn failure(&self, jti: &str, exp: usize) -> u32 {
+        let now = Utc::now().timestamp() as usize;
+        let mut failures = self.failures.lock().unwrap();
+        failures.retain(|_, (_, expires)| *expires >= now);
+
+        let (count, _) = failures.entry(jti.to_string()).or_insert((0, exp));
+        *count += 1;
+        *count
+    }
+
+    pub fn clear_failures(&self, jti: &str) {
+        self.failures.lock().unwrap().remove(jti);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::common::token::hash_token;
+
+    use super::{find_recovery_code, generate_recovery_codes, normalize_recovery_code, Totp, TotpState};
+
+    #[test]
+    fn codes_match_the_rfc_6238_vectors() {
+        // SHA-1 secret of RFC 6238 appendix B, the last 6 of its 8 digits
+        let totp = Totp { secret: b"12345678901234567890".to_vec() };
+        let vectors = [
+            (59, "287082"),
+            (1111111109, "081804"),
+            (1111111111, "050471"),
+            (1234567890, "005924"),
+            (2000000000, "279037"),
+            (20000000000, "353130"),
+        ];
+
+        for (time, code) in vectors {
+            assert_eq!(totp.code_at(time / 30), code, "T = {}", time);
+        }
+        assert_eq!(Totp::from_base32(&totp.to_base32()).unwrap().code_at(1), totp.code_at(1));
+    }
+
+    #[test]
+    fn recovery_codes_are_found_by_their_hash() {
+        let codes = generate_recovery_codes(3);
+        let hashes: Vec<String> = codes.iter().map(|c| hash_token(&normalize_recovery_code(c))).collect();
+
+        assert_eq!(find_recovery_code(&hashes, &codes[1].to_lowercase().replace('-', "")), Some(&hashes[1]));
+        assert_eq!(find_recovery_code(&hashes[..1], &codes[1]), None);
+        assert_eq!(find_recovery_code(&hashes, "AAAAA-AAAAA"), None);
+    }
+
+    #[test]
+    fn steps_and_failures_are_tracked() {
+        let state = TotpState::new();
+        assert!(state.use_step("alice", 10));
+        assert!(!state.use_step("alice", 10));
+        assert!(state.use_step("alice", 11));
+
+        let exp = usize::MAX;
+        assert_eq!(state.failure("jti", exp), 1);
+        assert_eq!(state.failure("jti", exp), 2);
+        assert_eq!(state.failure("other", exp), 1);
+        state.clear_failures("jti");
+        assert_eq!(state.failure("jti", exp), 1);
+    }
+}
@@ -0,0 +1,150 @@
+use ldap3::{asn1::{parse_tag, PL}, controls::{Control, RawControl}, LdapConnAsync, LdapResult};
+
+/// OID of the LDAP password policy control (draft-behera-ldap-password-policy).
+pub const PPOLICY_OID: &str = "1.3.6.1.4.1.42.2.27.8.5.1";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BindStatus {
+    Success,
+    InvalidCredentials,
+    AccountLocked,
+    PasswordExpired,
+    MustChangePassword,
+    Failed(u32),
+}
+
+impl BindStatus {
+    pub fn request_control() -> RawControl {
+        RawControl {
+            ctype: PPOLICY_OID.to_string(),
+            crit: false,
+            val: None,
+        }
+    }
+
+    pub fn from_result(result: &LdapResult) -> Self {
+        let ppolicy_error = result.ctrls.iter().find_map(|Control(_, raw)| {
+            if raw.ctype != PPOLICY_OID {
+                return None;
+            }
+            raw.val.as_deref().and_then(ppolicy_error)
+        });
+
+        match (result.rc, ppolicy_error) {
+            (0, Some(2)) => Self::MustChangePassword,
+            (0, _) => Self::Success,
+            (49, Some(0)) => Self::PasswordExpired,
+            (49, Some(1)) => Self::AccountLocked,
+            (49, Some(2)) => Self::MustChangePassword,
+            // Active Directory reports the reason in the diagnostic message
+            (49, None) if result.text.contains("data 775") => Self::AccountLocked,
+            (49, None) if result.text.contains("data 532") || result.text.contains("data 701") => Self::PasswordExpired,
+            (49, None) if result.text.contains("data 773") => Self::MustChangePassword,
+            (49, _) => Self::InvalidCredentials,
+            (rc, _) => Self::Failed(rc),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Binder {
+    ldap_url: String,
+    users_base_dn: String,
+}
+
+impl Binder {
+    pub fn new(ldap_url: String, users_base_dn: String) -> Self {
+        Self { ldap_url, users_base_dn }
+    }
+
+    pub async fn bind(&self, id: &str, password: &str) -> ldap3::result::Result<BindStatus> {
+        if password.is_empty() {
+            // an empty password would be an unauthenticated bind, which always succeeds
+            return Ok(BindStatus::InvalidCredentials);
+        }
+
+        let (conn, mut ldap) = LdapConnAsync::new(self.ldap_url.as_str()).await?;
+        ldap3::drive!(conn);
+
+        let dn = format!("uid={},{}", ldap3::dn_escape(id), self.users_base_dn);
+
+        let result = ldap
+            .with_controls(BindStatus::request_control())
+            .simple_bind(dn.as_str(), password)
+            .await?;
+
+        ldap.unbind().await?;
+
+        Ok(BindStatus::from_result(&result))
+    }
+}
+
+/// Extract the `error` field of a PasswordPolicyResponseValue.
+fn ppolicy_error(value: &[u8]) -> Option<u64> {
+    let (_, tag) = parse_tag(value).ok()?;
+    let PL::C(fields) = tag.payload else {
+        return None;
+    };
+
+    fields.into_iter().find(|f| f.id == 1).and_then(|f| match f.payload {
+        PL::P(bytes) => Some(bytes.iter().fold(0, |acc, &b| (acc << 8) | b as u64)),
+        PL::C(_) => None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use ldap3::{controls::{Control, RawControl}, LdapResult};
+
+    use super::{ppolicy_error, BindStatus, PPOLICY_OID};
+
+    fn result(rc: u32, text: &str, ppolicy: Option<&[u8]>) -> LdapResult {
+        let ctrls = ppolicy
+            .map(|val| Control(None, RawControl { ctype: PPOLICY_OID.to_string(), crit: false, val: Some(val.to_vec()) }))
+            .into_iter()
+            .collect();
+        LdapResult { rc, matched: String::new(), text: text.to_string(), refs: vec![], ctrls }
+    }
+
+    // PasswordPolicyResponseValue ::= SEQUENCE { warning [0] OPTIONAL, error [1] ENUMERATED OPTIONAL }
+    const EXPIRED: &[u8] = &[0x30, 0x03, 0x81, 0x01, 0x00];
+    const LOCKED: &[u8] = &[0x30, 0x03, 0x81, 0x01, 0x01];
+    const RESET: &[u8] = &[0x30, 0x03, 0x81, 0x01, 0x02];
+    // timeBeforeExpiration of 300 seconds, then the error
+    const WARNING_AND_LOCKED: &[u8] = &[0x30, 0x09, 0xa0, 0x04, 0x80, 0x02, 0x01, 0x2c, 0x81, 0x01, 0x01];
+    const WARNING: &[u8] = &[0x30, 0x06, 0xa0, 0x04, 0x80, 0x02, 0x01, 0x2c];
+
+    #[test]
+    fn the_ppolicy_error_is_read_from_the_response_value() {
+        assert_eq!(ppolicy_error(EXPIRED), Some(0));
+        assert_eq!(ppolicy_error(RESET), Some(2));
+        assert_eq!(ppolicy_error(WARNING_AND_LOCKED), Some(1));
+        assert_eq!(ppolicy_error(WARNING), None);
+        assert_eq!(ppolicy_error(&[0x04, 0x01, 0x02]), None);
+        assert_eq!(ppolicy_error(&[0x30, 0x05]), None);
+    }
+
+    #[test]
+    fn the_bind_status_follows_ppolicy() {
+        assert_eq!(BindStatus::from_result(&result(0, "", None)), BindStatus::Success);
+        assert_eq!(BindStatus::from_result(&result(0, "", Some(WARNING))), BindStatus::Success);
+        assert_eq!(BindStatus::from_result(&result(0, "", Some(RESET))), BindStatus::MustChangePassword);
+        assert_eq!(BindStatus::from_result(&result(49, "", None)), BindStatus::InvalidCredentials);
+        assert_eq!(BindStatus::from_result(&result(49, "", Some(EXPIRED))), BindStatus::PasswordExpired);
+        assert_eq!(BindStatus::from_result(&result(49, "", Some(LOCKED))), BindStatus::AccountLocked);
+        assert_eq!(BindStatus::from_result(&result(49, "", Some(WARNING_AND_LOCKED))), BindStatus::AccountLocked);
+        assert_eq!(BindStatus::from_result(&result(49, "", Some(RESET))), BindStatus::MustChangePassword);
+        assert_eq!(BindStatus::from_result(&result(53, "", None)), BindStatus::Failed(53));
+    }
+
+    #[test]
+    fn active_directory_reasons_are_read_from_the_message() {
+        let ad = |data: &str| result(49, &format!("80090308: LdapErr: DSID-0C09042F, comment: AcceptSecurityContext error, data {}, v4563", data), None);
+
+        assert_eq!(BindStatus::from_result(&ad("52e")), BindStatus::InvalidCredentials);
+        assert_eq!(BindStatus::from_result(&ad("775")), BindStatus::AccountLocked);
+        assert_eq!(BindStatus::from_result(&ad("532")), BindStatus::PasswordExpired);
+        assert_eq!(BindStatus::from_result(&ad("701")), BindStatus::PasswordExpired);
+        assert_eq!(BindStatus::from_result(&ad("773")), BindStatus::MustChangePassword);
+    }
+}
@@ -0,0 +1,27 @@
+use crate::common::password::Hash;
+
+use super::{User, UserBuilder};
+
+pub fn user(uid: &str, first_name: &str, last_name: &str, school: &str, groups: &[&str]) -> User {
+    let mut user = UserBuilder::new()
+        .uid(uid.to_string())
+        .password_hash("password".to_string(), Hash::SSHA)
+        .mail(format!("{}@polyorbite.com", uid))
+        .first_name(first_name.to_string())
+        .last_name(last_name.to_string())
+        .name(format!("{} {}", first_name, last_name))
+        .school(school.to_string())
+        .build()
+        .unwrap();
+    user.member = Some(groups.iter().map(|g| g.to_string()).collect());
+    user
+}
+
+pub fn directory() -> Vec<User> {
+    vec![
+        user("alice", "Alice", "Tremblay", "Polytechnique", &["avionique"]),
+        user("bob", "Bob", "Gagnon", "ETS", &["structure"]),
+        user("carole", "Carole", "De La Salle", "Polytechnique", &["avionique", "structure"]),
+        user("alain", "Alain", "Gagnon", "McGill", &[]),
+    ]
+}
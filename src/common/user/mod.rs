@@ -3,9 +3,17 @@ mod user_attribute;
 mod modify_user;
 mod users;
 mod user_builder;
+mod bind;
+mod query;
+mod permission;
+#[cfg(test)]
+pub(crate) mod fixture;
 
-use user_attribute::UserAttribute;
+pub use user_attribute::{AttributeMap, UserAttribute};
 pub use user::User;
 pub use modify_user::ModifyUser;
 pub use users::Users;
-pub use user_builder::UserBuilder;
\ No newline at end of file
+pub use user_builder::{FieldError, UserBuilder};
+pub use bind::{Binder, BindStatus};
+pub use permission::{FieldPermission, FieldPermissions, PICTURE_FIELD};
+pub use query::{UserCursor, UserField, UserPage, UserQuery, UserSort};
\ No newline at end of file
@@ -1,23 +1,26 @@
-use std::{collections::HashSet, vec};
+use std::{collections::{HashMap, HashSet}, vec};
 
 use ldap3::Mod;
 
-use crate::common::password::{DEFAULT_HASH, Password};
+use crate::common::password::{Hash, Password, DEFAULT_HASH};
 
-use super::{User, UserAttribute};
+use super::{user_builder::{invalid_values, FieldError}, AttributeMap, User, UserAttribute};
 
 
 pub struct ModifyUser {
     password: Option<String>,
-    mail: Option<String>,
+    mail: Option<Vec<String>>,
     first_name: Option<String>,
     last_name: Option<String>,
     name: Option<String>,
     school: Option<String>,
     genie: Option<String>,
     matricule: Option<String>,
-    number: Option<String>,
+    number: Option<Vec<String>>,
     picture: Option<Vec<u8>>,
+    extra: HashMap<String, Vec<String>>,
+    added: HashMap<String, Vec<String>>,
+    removed: HashMap<String, Vec<String>>,
 }
 
 
@@ -34,16 +37,28 @@ impl ModifyUser {
             matricule: None,
             number: None,
             picture: None,
+            extra: HashMap::new(),
+            added: HashMap::new(),
+            removed: HashMap::new(),
         }
     }
 
-    pub fn password(mut self, password: String) -> Self {
-        self.password = Some(Password::hash(password.as_str(), DEFAULT_HASH));
+    pub fn password(self, password: String) -> Self {
+        self.password_hash(password, DEFAULT_HASH)
+    }
+
+    pub fn password_hash(mut self, password: String, hash: Hash) -> Self {
+        self.password = Some(Password::hash(password.as_str(), hash));
         self
     }
 
-    pub fn mail(mut self, mail: String) -> Self {
-        self.mail = Some(mail);
+    pub fn mail(self, mail: String) -> Self {
+        self.mails(vec![mail])
+    }
+
+    /// Replaces every address, the first one is the primary one.
+    pub fn mails(mut self, mails: Vec<String>) -> Self {
+        self.mail = Some(mails.into_iter().filter(|m| !m.is_empty()).collect());
         self
     }
 
@@ -77,16 +92,102 @@ impl ModifyUser {
         self
     }
 
-    pub fn number(mut self, number: String) -> Self {
-        self.number = Some(number);
+    pub fn number(self, number: String) -> Self {
+        self.numbers(vec![number])
+    }
+
+    pub fn numbers(mut self, numbers: Vec<String>) -> Self {
+        self.number = Some(numbers.into_iter().filter(|n| !n.is_empty()).collect());
         self
     }
 
+    /// An empty picture removes it.
     pub fn picture(mut self, picture: Vec<u8>) -> Self {
         self.picture = Some(picture);
         self
     }
 
+    /// Set an attribute that has no dedicated field, an empty list removes it.
+    pub fn attribute(mut self, name: String, values: Vec<String>) -> Self {
+        self.extra.insert(name, values);
+        self
+    }
+
+    /// Adds values to a multi-valued attribute, the ones it already has are ignored.
+    pub fn add_values(mut self, name: String, values: Vec<String>) -> Self {
+        self.added.entry(name).or_default().extend(values.into_iter().filter(|v| !v.is_empty()));
+        self
+    }
+
+    /// Removes values from a multi-valued attribute, the ones it does not have are ignored.
+    pub fn remove_values(mut self, name: String, values: Vec<String>) -> Self {
+        self.removed.entry(name).or_default().extend(values.into_iter().filter(|v| !v.is_empty()));
+        self
+    }
+
+    /// Same rules as `UserBuilder::build` for the fields being changed.
+    pub fn validate(&self, attributes: &AttributeMap) -> Result<(), Vec<FieldError>> {
+        let mut errors: Vec<FieldError> = [
+            ("first_name", &self.first_name),
+            ("last_name", &self.last_name),
+            ("name", &self.name),
+        ]
+        .into_iter()
+        .filter(|(_, value)| value.as_ref().is_some_and(|v| v.is_empty()))
+        .map(|(field, _)| FieldError::required(field))
+        .collect();
+
+        if self.mail.as_ref().is_some_and(|mail| mail.is_empty()) {
+            errors.push(FieldError::required("mail"));
+        }
+
+        let added = |attribute: UserAttribute| {
+            self.added
+                .iter()
+                .filter(move |(name, _)| attributes.attribute(name) == attribute)
+                .flat_map(|(_, values)| values)
+        };
+        let mails = self.mail.iter().flatten().chain(added(UserAttribute::Mail));
+        let numbers = self.number.iter().flatten().chain(added(UserAttribute::Number));
+        errors.extend(invalid_values(mails, numbers));
+
+        match errors.is_empty() {
+            true => Ok(()),
+            false => Err(errors),
+        }
+    }
+
+    /// Addresses the user is left with once the change is made.
+    pub fn remaining_mails(&self, user: &User, attributes: &AttributeMap) -> Vec<String> {
+        let name = attributes.name(UserAttribute::Mail);
+        let changes = |values: &HashMap<String, Vec<String>>| -> Vec<String> {
+            values.iter().filter(|(key, _)| key.eq_ignore_ascii_case(name)).flat_map(|(_, v)| v.clone()).collect()
+        };
+        let (added, removed) = (changes(&self.added), changes(&self.removed));
+
+        let mut mails = self.mail.clone().unwrap_or_else(|| user.mail.clone());
+        for mail in added {
+            if !mails.contains(&mail) {
+                mails.push(mail);
+            }
+        }
+        mails.retain(|mail| !removed.contains(mail));
+        mails
+    }
+
+    /// The values `User::to_ldif` leaves out of a new entry: the other values of
+    /// multi-valued attributes, in order, and the picture.
+    pub fn completing(user: &User) -> Self {
+        let mut modification = Self::new().mails(user.mail.clone()).numbers(user.number.clone());
+        for (name, values) in &user.extra {
+            modification = modification.attribute(name.clone(), values.clone());
+        }
+        if let Some(picture) = &user.picture {
+            modification = modification.picture(picture.clone());
+        }
+        modification
+    }
+
     pub fn set_all(mut self, user: User) -> Self {
         self.password = Some(user.password);
         self.mail = Some(user.mail);
@@ -101,99 +202,283 @@ impl ModifyUser {
         self
     }
 
-    pub fn to_ldif(&self, user: User) -> (Vec<Mod<&str>>, Vec<Mod<&[u8]>>) {
+    pub fn to_ldif<'a>(&'a self, user: User, attributes: &'a AttributeMap) -> (Vec<Mod<&'a str>>, Vec<Mod<&'a [u8]>>) {
         let mut ldif = Vec::new();
 
         if let Some(password) = &self.password {
-            ldif.push(Mod::Replace(UserAttribute::Password.as_str(), HashSet::from([password.as_str()])));
+            ldif.push(Mod::Replace(attributes.name(UserAttribute::Password), HashSet::from([password.as_str()])));
         }
 
         if let Some(mail) = &self.mail {
-            if user.mail == "" && mail != ""{
-                ldif.push(Mod::Add(UserAttribute::Mail.as_str(), HashSet::from([mail.as_str()])));
-            } else if mail == "" && user.mail != ""{
-                ldif.push(Mod::Delete(UserAttribute::Mail.as_str(), HashSet::new()));
-            } else if user.mail != *mail {
-                ldif.push(Mod::Replace(UserAttribute::Mail.as_str(), HashSet::from([mail.as_str()])));
-            }
+            ldif.extend(replace(attributes.name(UserAttribute::Mail), &user.mail, mail));
         }
 
         if let Some(first_name) = &self.first_name {
             if user.first_name == "" && first_name != "" {
-                ldif.push(Mod::Add(UserAttribute::FirstName.as_str(), HashSet::from([first_name.as_str()])));
+                ldif.push(Mod::Add(attributes.name(UserAttribute::FirstName), HashSet::from([first_name.as_str()])));
             } else if first_name == "" && user.first_name != "" {
-                ldif.push(Mod::Delete(UserAttribute::FirstName.as_str(), HashSet::new()));
+                ldif.push(Mod::Delete(attributes.name(UserAttribute::FirstName), HashSet::new()));
             } else if user.first_name != *first_name {
-                ldif.push(Mod::Replace(UserAttribute::FirstName.as_str(), HashSet::from([first_name.as_str()])));
+                ldif.push(Mod::Replace(attributes.name(UserAttribute::FirstName), HashSet::from([first_name.as_str()])));
             }
         }
 
         if let Some(last_name) = &self.last_name {
             if user.last_name == "" && last_name != "" {
-                ldif.push(Mod::Add(UserAttribute::LastName.as_str(), HashSet::from([last_name.as_str()])));
+                ldif.push(Mod::Add(attributes.name(UserAttribute::LastName), HashSet::from([last_name.as_str()])));
             } else if last_name == "" && user.last_name != "" {
-                ldif.push(Mod::Delete(UserAttribute::LastName.as_str(), HashSet::new()));
+                ldif.push(Mod::Delete(attributes.name(UserAttribute::LastName), HashSet::new()));
             } else if user.last_name != *last_name {
-                ldif.push(Mod::Replace(UserAttribute::LastName.as_str(), HashSet::from([last_name.as_str()])));
+                ldif.push(Mod::Replace(attributes.name(UserAttribute::LastName), HashSet::from([last_name.as_str()])));
             }
         }
 
         if let Some(school) = &self.school {
             if user.school == "" && school != "" {
-                ldif.push(Mod::Add(UserAttribute::School.as_str(), HashSet::from([school.as_str()])));
+                ldif.push(Mod::Add(attributes.name(UserAttribute::School), HashSet::from([school.as_str()])));
             } else if school == "" && user.school != "" {
-                ldif.push(Mod::Delete(UserAttribute::School.as_str(), HashSet::new()));
+                ldif.push(Mod::Delete(attributes.name(UserAttribute::School), HashSet::new()));
             } else if user.school != *school {
-                ldif.push(Mod::Replace(UserAttribute::School.as_str(), HashSet::from([school.as_str()])));
+                ldif.push(Mod::Replace(attributes.name(UserAttribute::School), HashSet::from([school.as_str()])));
             }
         }
 
         if let Some(genie) = &self.genie {
             if user.genie == "" && genie != "" {
-                ldif.push(Mod::Add(UserAttribute::Genie.as_str(), HashSet::from([genie.as_str()])));
+                ldif.push(Mod::Add(attributes.name(UserAttribute::Genie), HashSet::from([genie.as_str()])));
             } else if genie == "" && user.genie != "" {
-                ldif.push(Mod::Delete(UserAttribute::Genie.as_str(), HashSet::new()));
+                ldif.push(Mod::Delete(attributes.name(UserAttribute::Genie), HashSet::new()));
             } else if user.genie != *genie {
-                ldif.push(Mod::Replace(UserAttribute::Genie.as_str(), HashSet::from([genie.as_str()])));
+                ldif.push(Mod::Replace(attributes.name(UserAttribute::Genie), HashSet::from([genie.as_str()])));
             }
         }
 
         if let Some(matricule) = &self.matricule {
             if user.matricule == "" && matricule != "" {
-                ldif.push(Mod::Add(UserAttribute::Matricule.as_str(), HashSet::from([matricule.as_str()])));
+                ldif.push(Mod::Add(attributes.name(UserAttribute::Matricule), HashSet::from([matricule.as_str()])));
             } else if matricule == "" && user.matricule != "" {
-                ldif.push(Mod::Delete(UserAttribute::Matricule.as_str(), HashSet::new()));
+                ldif.push(Mod::Delete(attributes.name(UserAttribute::Matricule), HashSet::new()));
             } else if user.matricule != *matricule {
-                ldif.push(Mod::Replace(UserAttribute::Matricule.as_str(), HashSet::from([matricule.as_str()])));
+                ldif.push(Mod::Replace(attributes.name(UserAttribute::Matricule), HashSet::from([matricule.as_str()])));
             }
         }
 
         if let Some(number) = &self.number {
-            if user.number == "" && number != "" {
-                ldif.push(Mod::Add(UserAttribute::Number.as_str(), HashSet::from([number.as_str()])));
-            } else if number == "" && user.number != "" {
-                ldif.push(Mod::Delete(UserAttribute::Number.as_str(), HashSet::new()));
-            } else if user.number != *number {
-                ldif.push(Mod::Replace(UserAttribute::Number.as_str(), HashSet::from([number.as_str()])));
-            }
+            ldif.extend(replace(attributes.name(UserAttribute::Number), &user.number, number));
         }
 
         if let Some(name) = &self.name {
             if user.name == "" && name != "" {
-                ldif.push(Mod::Add(UserAttribute::Name.as_str(), HashSet::from([name.as_str()])));
+                ldif.push(Mod::Add(attributes.name(UserAttribute::Name), HashSet::from([name.as_str()])));
             } else if name == "" && user.name != "" {
-                ldif.push(Mod::Delete(UserAttribute::Name.as_str(), HashSet::new()));
+                ldif.push(Mod::Delete(attributes.name(UserAttribute::Name), HashSet::new()));
             } else if user.name != *name {
-                ldif.push(Mod::Replace(UserAttribute::Name.as_str(), HashSet::from([name.as_str()])));
+                ldif.push(Mod::Replace(attributes.name(UserAttribute::Name), HashSet::from([name.as_str()])));
+            }
+        }
+
+        for (name, values) in &self.extra {
+            ldif.extend(replace(name, current(&user, attributes, name), values));
+        }
+
+        // Only the values that change are sent, LDAP refuses to add a value twice or remove a missing one.
+        for (name, values) in &self.added {
+            let current = current(&user, attributes, name);
+            let mut added: Vec<&str> = Vec::new();
+            for value in values.iter().filter(|v| !current.contains(v)) {
+                if !added.contains(&value.as_str()) {
+                    added.push(value);
+                }
+            }
+            ldif.extend(added.into_iter().map(|value| Mod::Add(name.as_str(), HashSet::from([value]))));
+        }
+
+        for (name, values) in &self.removed {
+            let current = current(&user, attributes, name);
+            let removed: HashSet<&str> = values.iter().filter(|v| current.contains(v)).map(|v| v.as_str()).collect();
+            if !removed.is_empty() {
+                ldif.push(Mod::Delete(name.as_str(), removed));
             }
         }
 
         let mut ldif2:Vec<Mod<&[u8]>> = vec![];
 
         if let Some(picture) = &self.picture {
-            ldif2.push(Mod::Replace(UserAttribute::Picture.as_bytes(), HashSet::from([picture.as_slice()])));
+            if !picture.is_empty() {
+                ldif2.push(Mod::Replace(attributes.name(UserAttribute::Picture).as_bytes(), HashSet::from([picture.as_slice()])));
+            } else if user.picture.is_some() {
+                ldif2.push(Mod::Delete(attributes.name(UserAttribute::Picture).as_bytes(), HashSet::new()));
+            }
         }
 
         (ldif, ldif2)
     }
-}
\ No newline at end of file
+}
+
+/// Values of an attribute, whether the user has a field for it or not.
+fn current<'a>(user: &'a User, attributes: &AttributeMap, name: &str) -> &'a [String] {
+    match attributes.attribute(name) {
+        UserAttribute::Mail => &user.mail,
+        UserAttribute::Number => &user.number,
+        _ => user
+            .extra
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, values)| values.as_slice())
+            .unwrap_or_default(),
+    }
+}
+
+/// Changes that give a multi-valued attribute `values`, in this order. A
+/// `Mod` holds a set, so the values are added one at a time: the directory
+/// keeps them in the order they were added, and the first mail is the primary one.
+fn replace<'a>(name: &'a str, current: &[String], values: &'a [String]) -> Vec<Mod<&'a str>> {
+    let mut new: Vec<&str> = Vec::new();
+    for value in values {
+        if !new.contains(&value.as_str()) {
+            new.push(value);
+        }
+    }
+    let current: Vec<&str> = current.iter().map(|v| v.as_str()).collect();
+    if current == new {
+        return vec![];
+    }
+
+    let mut ldif = Vec::new();
+    let kept = match new.starts_with(&current) {
+        true => current.len(),
+        false => {
+            ldif.push(Mod::Delete(name, HashSet::new()));
+            0
+        }
+    };
+    ldif.extend(new[kept..].iter().map(|value| Mod::Add(name, HashSet::from([*value]))));
+    ldif
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{HashMap, HashSet};
+
+    use ldap3::{Mod, SearchEntry};
+
+    use crate::common::{
+        password::Hash,
+        user::{AttributeMap, User, UserBuilder},
+    };
+
+    use super::ModifyUser;
+
+    fn user() -> User {
+        let attrs = HashMap::from([
+            ("uid".to_string(), vec!["alice".to_string()]),
+            ("mail".to_string(), vec!["alice@polyorbite.com".to_string(), "alice@polymtl.ca".to_string()]),
+            ("telephoneNumber".to_string(), vec!["514 555 0100".to_string(), "514 555 0101".to_string()]),
+        ]);
+        User::new(SearchEntry { dn: String::new(), attrs, bin_attrs: HashMap::new() }, &AttributeMap::default())
+    }
+
+    #[test]
+    fn every_value_of_an_attribute_is_kept() {
+        let user = user();
+
+        assert_eq!(user.mail, vec!["alice@polyorbite.com", "alice@polymtl.ca"]);
+        assert_eq!(user.primary_mail(), "alice@polyorbite.com");
+        assert_eq!(user.number.len(), 2);
+    }
+
+    #[test]
+    fn values_are_added_and_removed_one_by_one() {
+        let attributes = AttributeMap::default();
+        let modification = ModifyUser::new()
+            .add_values("mail".to_string(), vec!["alice@polyorbite.com".to_string(), "alice@gmail.com".to_string()])
+            .remove_values("telephoneNumber".to_string(), vec!["514 555 0101".to_string(), "514 555 0199".to_string()]);
+        assert!(modification.validate(&attributes).is_ok());
+
+        let (ldif, _) = modification.to_ldif(user(), &attributes);
+        assert_eq!(ldif.len(), 2);
+        assert!(ldif.contains(&Mod::Add("mail", HashSet::from(["alice@gmail.com"]))));
+        assert!(ldif.contains(&Mod::Delete("telephoneNumber", HashSet::from(["514 555 0101"]))));
+    }
+
+    #[test]
+    fn removing_every_address_is_detected() {
+        let attributes = AttributeMap::default();
+        let user = user();
+        let modification = ModifyUser::new().remove_values("mail".to_string(), user.mail.clone());
+        assert!(modification.remaining_mails(&user, &attributes).is_empty());
+
+        let invalid = ModifyUser::new().add_values("mail".to_string(), vec!["alice".to_string()]);
+        assert_eq!(invalid.validate(&attributes).unwrap_err()[0].code, "invalid");
+    }
+
+    /// Applies changes the way the directory does, values are kept in the order they are added.
+    fn apply(user: &User, ldif: Vec<Mod<&str>>) -> User {
+        let mut attrs = HashMap::from([
+            ("uid".to_string(), vec![user.uid.clone()]),
+            ("mail".to_string(), user.mail.clone()),
+            ("telephoneNumber".to_string(), user.number.clone()),
+        ]);
+        for change in ldif {
+            match change {
+                Mod::Add(name, values) => attrs.entry(name.to_string()).or_default().extend(values.into_iter().map(String::from)),
+                Mod::Delete(name, values) if values.is_empty() => drop(attrs.remove(name)),
+                Mod::Delete(name, values) => attrs.entry(name.to_string()).or_default().retain(|v| !values.contains(v.as_str())),
+                _ => panic!("unexpected change {:?}", change),
+            }
+        }
+        User::new(SearchEntry { dn: String::new(), attrs, bin_attrs: HashMap::new() }, &AttributeMap::default())
+    }
+
+    #[test]
+    fn the_order_of_the_addresses_survives_a_round_trip() {
+        let attributes = AttributeMap::default();
+        let mails = vec!["alice@gmail.com".to_string(), "alice@polyorbite.com".to_string()];
+        let modification = ModifyUser::new().mails(mails.clone());
+        let (ldif, _) = modification.to_ldif(user(), &attributes);
+
+        let user = apply(&user(), ldif);
+        assert_eq!(user.mail, mails);
+        assert_eq!(user.primary_mail(), "alice@gmail.com");
+
+        // Appending an address keeps the others where they are.
+        let mails = vec!["alice@gmail.com".to_string(), "alice@polyorbite.com".to_string(), "alice@polymtl.ca".to_string()];
+        let modification = ModifyUser::new().mails(mails.clone());
+        let (ldif, _) = modification.to_ldif(user.clone(), &attributes);
+        assert_eq!(ldif, vec![Mod::Add("mail", HashSet::from(["alice@polymtl.ca"]))]);
+        let user = apply(&user, ldif);
+        assert_eq!(user.mail, mails);
+
+        let (ldif, _) = modification.to_ldif(user, &attributes);
+        assert!(ldif.is_empty());
+    }
+
+    #[test]
+    fn a_new_entry_is_completed_with_the_values_it_left_out() {
+        let attributes = AttributeMap::default();
+        let user = UserBuilder::new()
+            .uid("alice".to_string())
+            .password_hash("password".to_string(), Hash::SSHA)
+            .mails(vec!["alice@polyorbite.com".to_string(), "alice@polymtl.ca".to_string(), "alice@gmail.com".to_string()])
+            .numbers(vec!["514 555 0101".to_string(), "514 555 0100".to_string()])
+            .first_name("Alice".to_string())
+            .last_name("Tremblay".to_string())
+            .name("Alice Tremblay".to_string())
+            .build()
+            .unwrap();
+
+        let attrs = user
+            .to_ldif(&attributes)
+            .into_iter()
+            .map(|(name, values)| (name.to_string(), values.into_iter().map(String::from).collect()))
+            .collect();
+        let created = User::new(SearchEntry { dn: String::new(), attrs, bin_attrs: HashMap::new() }, &attributes);
+        assert_eq!(created.mail, vec!["alice@polyorbite.com"]);
+
+        let modification = ModifyUser::completing(&user);
+        let (ldif, _) = modification.to_ldif(created.clone(), &attributes);
+        let created = apply(&created, ldif);
+        assert_eq!(created.mail, user.mail);
+        assert_eq!(created.number, user.number);
+    }
+}
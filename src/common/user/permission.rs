@@ -0,0 +1,118 @@
+use std::collections::HashMap;
+
+use crate::common::Config;
+
+use super::{AttributeMap, FieldError, UserField};
+
+/// Not a `UserField`, the picture still has its own permission.
+pub const PICTURE_FIELD: &str = "picture";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FieldPermission {
+    /// Members edit their own value, admins everyone's.
+    SelfEditable,
+    AdminOnly,
+    ReadOnly,
+}
+
+impl FieldPermission {
+    pub fn new(permission: &str) -> Option<Self> {
+        match permission.to_lowercase().as_str() {
+            "self" => Some(Self::SelfEditable),
+            "admin" => Some(Self::AdminOnly),
+            "readonly" | "read-only" => Some(Self::ReadOnly),
+            _ => None,
+        }
+    }
+}
+
+/// Who can change which field, fields that are not listed are admin-only.
+pub struct FieldPermissions {
+    fields: HashMap<String, FieldPermission>,
+}
+
+impl FieldPermissions {
+    pub fn new(config: &Config, attributes: &AttributeMap) -> Self {
+        let fields = config
+            .user_field_permissions
+            .iter()
+            .filter_map(|entry| {
+                let parsed = entry.split_once(':').and_then(|(field, permission)| {
+                    let known = UserField::new(field).is_some() || field == PICTURE_FIELD || attributes.custom().contains_key(field);
+                    Some((field.to_string(), FieldPermission::new(permission).filter(|_| known)?))
+                });
+                if parsed.is_none() {
+                    tracing::debug!("🔥 Invalid user field permission: {}", entry);
+                }
+                parsed
+            })
+            .collect();
+
+        Self { fields }
+    }
+
+    pub fn permission(&self, field: &str) -> FieldPermission {
+        match field {
+            "uid" => FieldPermission::ReadOnly,
+            _ => self.fields.get(field).copied().unwrap_or(FieldPermission::AdminOnly),
+        }
+    }
+
+    pub fn can_edit(&self, field: &str, own: bool, admin: bool) -> bool {
+        match self.permission(field) {
+            FieldPermission::SelfEditable => own || admin,
+            FieldPermission::AdminOnly => admin,
+            FieldPermission::ReadOnly => false,
+        }
+    }
+
+    /// One error for every field the user is not allowed to change.
+    pub fn check(&self, fields: &[&str], own: bool, admin: bool) -> Vec<FieldError> {
+        fields
+            .iter()
+            .filter(|field| !self.can_edit(field, own, admin))
+            .map(|field| match self.permission(field) {
+                FieldPermission::ReadOnly => FieldError::new(field, "read_only", &format!("{} cannot be changed", field)),
+                _ => FieldError::new(field, "admin_only", &format!("{} can only be changed by an administrator", field)),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::{FieldPermission, FieldPermissions};
+
+    fn permissions() -> FieldPermissions {
+        FieldPermissions {
+            fields: HashMap::from([
+                ("number".to_string(), FieldPermission::SelfEditable),
+                ("matricule".to_string(), FieldPermission::ReadOnly),
+                ("uid".to_string(), FieldPermission::SelfEditable),
+            ]),
+        }
+    }
+
+    #[test]
+    fn members_only_change_their_self_editable_fields() {
+        let permissions = permissions();
+
+        assert!(permissions.can_edit("number", true, false));
+        assert!(!permissions.can_edit("number", false, false));
+        assert!(permissions.can_edit("number", false, true));
+        assert!(!permissions.can_edit("mail", true, false));
+        assert!(permissions.can_edit("mail", false, true));
+        assert!(!permissions.can_edit("matricule", true, true));
+        assert!(!permissions.can_edit("uid", true, true));
+    }
+
+    #[test]
+    fn errors_name_the_refused_fields() {
+        let errors = permissions().check(&["number", "mail", "matricule"], true, false);
+        let codes: Vec<(&str, &str)> = errors.iter().map(|e| (e.field.as_str(), e.code)).collect();
+
+        assert_eq!(codes, vec![("mail", "admin_only"), ("matricule", "read_only")]);
+    }
+}
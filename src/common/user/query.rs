@@ -0,0 +1,373 @@
+use std::cmp::Ordering;
+
+use base64::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use super::User;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UserField {
+    Uid,
+    Mail,
+    FirstName,
+    LastName,
+    Name,
+    School,
+    Genie,
+    Matricule,
+    Number,
+}
+
+impl UserField {
+    pub const ALL: [UserField; 9] = [
+        Self::Uid,
+        Self::Mail,
+        Self::FirstName,
+        Self::LastName,
+        Self::Name,
+        Self::School,
+        Self::Genie,
+        Self::Matricule,
+        Self::Number,
+    ];
+
+    pub fn new(field: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|f| f.as_str() == field)
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Uid => "uid",
+            Self::Mail => "mail",
+            Self::FirstName => "first_name",
+            Self::LastName => "last_name",
+            Self::Name => "name",
+            Self::School => "school",
+            Self::Genie => "genie",
+            Self::Matricule => "matricule",
+            Self::Number => "number",
+        }
+    }
+
+    /// First value of the field, the one users are sorted by.
+    pub fn value<'a>(&self, user: &'a User) -> &'a str {
+        self.values(user).first().copied().unwrap_or_default()
+    }
+
+    pub fn values<'a>(&self, user: &'a User) -> Vec<&'a str> {
+        match self {
+            Self::Mail => user.mail.iter().map(|m| m.as_str()).collect(),
+            Self::Number => user.number.iter().map(|n| n.as_str()).collect(),
+            field => vec![field.single(user)],
+        }
+    }
+
+    fn single<'a>(&self, user: &'a User) -> &'a str {
+        match self {
+            Self::Uid => &user.uid,
+            Self::Mail | Self::Number => "",
+            Self::FirstName => &user.first_name,
+            Self::LastName => &user.last_name,
+            Self::Name => &user.name,
+            Self::School => &user.school,
+            Self::Genie => &user.genie,
+            Self::Matricule => &user.matricule,
+        }
+    }
+
+    /// Free text fields are searched, the other ones compared as a whole.
+    fn is_text(&self) -> bool {
+        matches!(self, Self::Uid | Self::Mail | Self::FirstName | Self::LastName | Self::Name)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Filter {
+    Text(String),
+    Contains(UserField, String),
+    Prefix(UserField, String),
+    Equals(UserField, String),
+    Group(String),
+}
+
+impl Filter {
+    /// Multi-valued fields match when any of their values does.
+    fn matches(&self, user: &User) -> bool {
+        let any = |field: &UserField, test: &dyn Fn(&str) -> bool| field.values(user).iter().any(|v| test(&v.to_lowercase()));
+        match self {
+            Self::Text(text) => UserField::ALL.iter().filter(|f| f.is_text()).any(|f| any(f, &|v| v.contains(text))),
+            Self::Contains(field, text) => any(field, &|v| v.contains(text)),
+            Self::Prefix(field, text) => any(field, &|v| v.starts_with(text)),
+            Self::Equals(field, text) => any(field, &|v| v == text),
+            Self::Group(cn) => user.member.as_ref().is_some_and(|groups| groups.iter().any(|g| g.to_lowercase() == *cn)),
+        }
+    }
+}
+
+/// Space separated terms, all of them have to match:
+/// - `ali` searches uid, mail and names,
+/// - `name:ali` searches one text field, `name:ali*` matches its beginning,
+/// - `school:polytechnique` and the other fields compare the whole value,
+/// - `group:avionique` keeps the members of a group.
+///
+/// Values are case insensitive and can be quoted: `last_name:"de la salle"`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct UserQuery {
+    filters: Vec<Filter>,
+}
+
+impl UserQuery {
+    pub fn parse(query: &str) -> Result<Self, String> {
+        let filters = Self::terms(query)?
+            .into_iter()
+            .map(|term| {
+                let (field, value) = match term.split_once(':') {
+                    Some((field, value)) => (Some(field), value.to_lowercase()),
+                    None => (None, term.to_lowercase()),
+                };
+
+                match field {
+                    None => Ok(Filter::Text(value)),
+                    Some("group") => Ok(Filter::Group(value)),
+                    Some(name) => match UserField::new(name) {
+                        Some(field) if field.is_text() => match value.strip_suffix('*') {
+                            Some(prefix) => Ok(Filter::Prefix(field, prefix.to_string())),
+                            None => Ok(Filter::Contains(field, value)),
+                        },
+                        Some(field) => Ok(Filter::Equals(field, value)),
+                        None => Err(format!("Unknown field: {}", name)),
+                    },
+                }
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self { filters })
+    }
+
+    fn terms(query: &str) -> Result<Vec<String>, String> {
+        let mut terms = Vec::new();
+        let mut term = String::new();
+        let mut quoted = false;
+
+        for c in query.chars() {
+            match c {
+                '"' => quoted = !quoted,
+                c if c.is_whitespace() && !quoted => {
+                    if !term.is_empty() {
+                        terms.push(std::mem::take(&mut term));
+                    }
+                }
+                c => term.push(c),
+            }
+        }
+        if quoted {
+            return Err("Unterminated quote".to_string());
+        }
+        if !term.is_empty() {
+            terms.push(term);
+        }
+
+        Ok(terms)
+    }
+
+    pub fn matches(&self, user: &User) -> bool {
+        self.filters.iter().all(|filter| filter.matches(user))
+    }
+
+    /// The fields the terms look at, free text searches all the text fields.
+    pub fn fields(&self) -> Vec<UserField> {
+        self.filters
+            .iter()
+            .flat_map(|filter| match filter {
+                Filter::Text(_) => UserField::ALL.into_iter().filter(|f| f.is_text()).collect(),
+                Filter::Contains(field, _) | Filter::Prefix(field, _) | Filter::Equals(field, _) => vec![*field],
+                Filter::Group(_) => vec![],
+            })
+            .collect()
+    }
+}
+
+/// `last_name` sorts ascending, `-last_name` descending, ties are broken by uid.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct UserSort {
+    field: UserField,
+    descending: bool,
+}
+
+impl Default for UserSort {
+    fn default() -> Self {
+        Self {
+            field: UserField::Uid,
+            descending: false,
+        }
+    }
+}
+
+impl UserSort {
+    pub fn parse(sort: &str) -> Result<Self, String> {
+        let (name, descending) = match sort.strip_prefix('-') {
+            Some(name) => (name, true),
+            None => (sort, false),
+        };
+        let field = UserField::new(name).ok_or_else(|| format!("Unknown sort field: {}", name))?;
+
+        Ok(Self { field, descending })
+    }
+
+    pub fn field(&self) -> UserField {
+        self.field
+    }
+
+    pub fn as_string(&self) -> String {
+        format!("{}{}", if self.descending { "-" } else { "" }, self.field.as_str())
+    }
+
+    fn key(&self, user: &User) -> (String, String) {
+        (self.field.value(user).to_lowercase(), user.uid.clone())
+    }
+
+    fn order(&self, ordering: Ordering) -> Ordering {
+        match self.descending {
+            true => ordering.reverse(),
+            false => ordering,
+        }
+    }
+
+    pub fn compare(&self, a: &User, b: &User) -> Ordering {
+        self.order(self.key(a).cmp(&self.key(b)))
+    }
+
+    pub fn is_after(&self, user: &User, cursor: &UserCursor) -> bool {
+        self.order(self.key(user).cmp(&(cursor.value.clone(), cursor.uid.clone()))) == Ordering::Greater
+    }
+}
+
+/// Position of the last user of a page, only valid with the same sort.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UserCursor {
+    sort: String,
+    value: String,
+    uid: String,
+}
+
+impl UserCursor {
+    pub fn new(sort: &UserSort, user: &User) -> Self {
+        let (value, uid) = sort.key(user);
+        Self { sort: sort.as_string(), value, uid }
+    }
+
+    pub fn encode(&self) -> String {
+        BASE64_URL_SAFE_NO_PAD.encode(serde_json::to_vec(self).unwrap())
+    }
+
+    pub fn decode(cursor: &str, sort: &UserSort) -> Option<Self> {
+        let bytes = BASE64_URL_SAFE_NO_PAD.decode(cursor).ok()?;
+        let cursor: Self = serde_json::from_slice(&bytes).ok()?;
+        (cursor.sort == sort.as_string()).then_some(cursor)
+    }
+}
+
+/// One page of the users matching a query, the users are borrowed until the
+/// caller clones the ones it returns.
+pub struct UserPage<'a> {
+    pub users: Vec<&'a User>,
+    pub total: usize,
+    pub next_cursor: Option<UserCursor>,
+}
+
+impl<'a> UserPage<'a> {
+    pub fn new(users: impl Iterator<Item = &'a User>, query: &UserQuery, sort: &UserSort, cursor: Option<&UserCursor>, limit: usize) -> Self {
+        let mut users: Vec<&User> = users.filter(|u| query.matches(u)).collect();
+        users.sort_by(|a, b| sort.compare(a, b));
+        let total = users.len();
+
+        let mut users: Vec<&User> = users
+            .into_iter()
+            .filter(|u| cursor.is_none_or(|cursor| sort.is_after(u, cursor)))
+            .take(limit + 1)
+            .collect();
+        let next_cursor = match users.len() > limit {
+            true => {
+                users.truncate(limit);
+                users.last().map(|u| UserCursor::new(sort, u))
+            }
+            false => None,
+        };
+
+        Self { users, total, next_cursor }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::common::user::fixture::directory;
+
+    use super::{UserCursor, UserField, UserPage, UserQuery, UserSort};
+
+    fn search(query: &str) -> Vec<String> {
+        let query = UserQuery::parse(query).unwrap();
+        directory().into_iter().filter(|u| query.matches(u)).map(|u| u.uid).collect()
+    }
+
+    #[test]
+    fn terms_combine_text_prefix_equality_and_groups() {
+        assert_eq!(search("gagnon"), vec!["bob", "alain"]);
+        assert_eq!(search("first_name:al*"), vec!["alice", "alain"]);
+        assert_eq!(search("first_name:li"), vec!["alice"]);
+        assert_eq!(search("school:polytechnique group:structure"), vec!["carole"]);
+        assert_eq!(search("school:poly"), Vec::<String>::new());
+        assert_eq!(search("last_name:\"de la\""), vec!["carole"]);
+    }
+
+    #[test]
+    fn invalid_queries_are_rejected() {
+        assert!(UserQuery::parse("password:secret").is_err());
+        assert!(UserQuery::parse("name:\"open").is_err());
+        assert!(UserSort::parse("-password").is_err());
+    }
+
+    #[test]
+    fn cursor_resumes_after_the_last_user_of_the_page() {
+        let sort = UserSort::parse("-last_name").unwrap();
+        let mut users = directory();
+        users.sort_by(|a, b| sort.compare(a, b));
+        let uids: Vec<&str> = users.iter().map(|u| u.uid.as_str()).collect();
+        assert_eq!(uids, vec!["alice", "bob", "alain", "carole"]);
+
+        let cursor = UserCursor::new(&sort, &users[1]).encode();
+        let cursor = UserCursor::decode(&cursor, &sort).unwrap();
+        let next: Vec<&str> = users.iter().filter(|u| sort.is_after(u, &cursor)).map(|u| u.uid.as_str()).collect();
+        assert_eq!(next, vec!["alain", "carole"]);
+
+        assert!(UserCursor::decode(&UserCursor::new(&sort, &users[1]).encode(), &UserSort::default()).is_none());
+    }
+
+    #[test]
+    fn pages_follow_each_other() {
+        let users = directory();
+        let query = UserQuery::parse("").unwrap();
+        let sort = UserSort::default();
+
+        let first = UserPage::new(users.iter(), &query, &sort, None, 3);
+        let uids: Vec<&str> = first.users.iter().map(|u| u.uid.as_str()).collect();
+        assert_eq!((uids, first.total), (vec!["alain", "alice", "bob"], 4));
+
+        let cursor = first.next_cursor.unwrap();
+        let second = UserPage::new(users.iter(), &query, &sort, Some(&cursor), 3);
+        let uids: Vec<&str> = second.users.iter().map(|u| u.uid.as_str()).collect();
+        assert_eq!((uids, second.total), (vec!["carole"], 4));
+        assert!(second.next_cursor.is_none());
+
+        let filtered = UserPage::new(users.iter(), &UserQuery::parse("group:structure").unwrap(), &sort, None, 3);
+        assert_eq!(filtered.total, 2);
+        assert!(filtered.next_cursor.is_none());
+    }
+
+    #[test]
+    fn the_fields_a_query_looks_at_are_known() {
+        let query = UserQuery::parse("school:ets group:avionique first_name:al*").unwrap();
+        assert_eq!(query.fields(), vec![UserField::School, UserField::FirstName]);
+        assert!(UserQuery::parse("ali").unwrap().fields().contains(&UserField::Mail));
+        assert_eq!(UserSort::parse("-matricule").unwrap().field(), UserField::Matricule);
+    }
+}
@@ -1,45 +1,47 @@
-use std::collections::HashSet;
+use std::collections::{BTreeMap, HashMap, HashSet};
 
 use ldap3::SearchEntry;
-use crate::common::password::Password;
+use crate::common::password::{Password, PasswordError, PlaintextPolicy};
 
-use super::UserAttribute;
+use super::{AttributeMap, UserAttribute};
 
 #[derive(Debug, Clone)]
 pub struct User {
     pub uid: String,
     pub password: String,
-    pub mail: String,
+    pub mail: Vec<String>,
     pub first_name: String,
     pub last_name: String,
     pub name: String,
     pub school: String,
     pub genie: String,
     pub matricule: String,
-    pub number: String,
+    pub number: Vec<String>,
     pub picture: Option<Vec<u8>>,
     pub member: Option<HashSet<String>>,
+    pub extra: HashMap<String, Vec<String>>,
 }
 
 impl User {
-    pub fn new(entry: SearchEntry) -> Self {
+    pub fn new(entry: SearchEntry, attributes: &AttributeMap) -> Self {
         let mut password = String::new();
         let mut uid = String::new();
-        let mut mail = String::new();
+        let mut mail = Vec::new();
         let mut first_name = String::new();
         let mut last_name = String::new();
         let mut school = String::new();
         let mut genie = String::new();
         let mut matricule = String::new();
-        let mut number = String::new();
+        let mut number = Vec::new();
         let mut name = String::new();
         let mut picture = None;
         let mut member = None;
+        let mut extra = HashMap::new();
 
         for (key, value) in entry.attrs {
-            match UserAttribute::from_str(key.as_str()) {
+            match attributes.attribute(key.as_str()) {
                 UserAttribute::Password => password = value[0].clone(),
-                UserAttribute::Mail => mail = value[0].clone(),
+                UserAttribute::Mail => mail = value,
                 UserAttribute::FirstName => first_name = value[0].clone(),
                 UserAttribute::Name => name = value[0].clone(),
                 UserAttribute::LastName => last_name = value[0].clone(),
@@ -47,21 +49,22 @@ impl User {
                 UserAttribute::Genie => genie = value[0].clone(),
                 UserAttribute::Uid => uid = value[0].clone(),
                 UserAttribute::Matricule => matricule = value[0].clone(),
-                UserAttribute::Number => number = value[0].clone(),
+                UserAttribute::Number => number = value,
                 UserAttribute::MemberOf => {
                     let reg = regex::Regex::new( r"cn=([^,]+)").unwrap();
                     let value: HashSet<String> = value.iter().map(|v|  reg.captures_iter(v).map(|c| c[1].to_string()).collect::<Vec<String>>()).flatten().collect();
                     member = Some(value.clone())
                 },
                 UserAttribute::Picture => picture = Some(value[0].clone().into_bytes()),
-                _ => {}
+                UserAttribute::None => {
+                    extra.insert(key, value);
+                }
             }
         }
 
         for (key, value) in entry.bin_attrs {
-            match UserAttribute::from_str(key.as_str()) {
-                UserAttribute::Picture => picture = Some(value[0].clone()),
-                _ => {}
+            if attributes.attribute(key.as_str()) == UserAttribute::Picture {
+                picture = Some(value[0].clone());
             }
         }
 
@@ -78,28 +81,75 @@ impl User {
             number,
             picture,
             member,
+            extra,
         }
     }
 
-    pub fn verify_password(&self, password: &str) -> bool {
-        Password::verify(password, self.password.as_str())
+    /// Identity attached to the requests authenticated with an API key.
+    pub fn service_account(uid: String, groups: HashSet<String>) -> Self {
+        Self {
+            uid,
+            password: String::new(),
+            mail: Vec::new(),
+            first_name: String::new(),
+            last_name: String::new(),
+            name: String::new(),
+            school: String::new(),
+            genie: String::new(),
+            matricule: String::new(),
+            number: Vec::new(),
+            picture: None,
+            member: Some(groups),
+            extra: HashMap::new(),
+        }
+    }
+
+    /// First address, the one used for the mails sent to the user.
+    pub fn primary_mail(&self) -> &str {
+        self.mail.first().map(|m| m.as_str()).unwrap_or_default()
+    }
+
+    pub fn attribute(&self, name: &str) -> Option<&Vec<String>> {
+        self.extra.get(name).filter(|v| !v.is_empty())
+    }
+
+    pub fn verify_password(&self, password: &str, plaintext: PlaintextPolicy) -> Result<bool, PasswordError> {
+        Password::verify_with(password, self.password.as_str(), plaintext)
     }
 
-    pub fn to_ldif(&self) -> Vec<(&str, HashSet<&str>)> {
+    /// Values of the custom fields the user has, by API name.
+    pub fn custom(&self, attributes: &AttributeMap) -> BTreeMap<String, Vec<String>> {
+        attributes
+            .custom()
+            .iter()
+            .filter_map(|(field, name)| {
+                let values = self.extra.iter().find(|(key, _)| key.eq_ignore_ascii_case(name))?.1;
+                Some((field.clone(), values.clone())).filter(|(_, values)| !values.is_empty())
+            })
+            .collect()
+    }
+
+    /// Attributes of a new entry. Only the first value of a multi-valued attribute is
+    /// sent, a set would lose their order: `Users::new_user` adds the others afterwards
+    /// with `ModifyUser::completing`.
+    pub fn to_ldif<'a>(&'a self, attributes: &'a AttributeMap) -> Vec<(&'a str, HashSet<&'a str>)> {
         let mut ldif = Vec::new();
 
-        ldif.push((UserAttribute::Password.as_str(), HashSet::from([self.password.as_str()])));
-        ldif.push((UserAttribute::Mail.as_str(), HashSet::from([self.mail.as_str()])));
-        ldif.push((UserAttribute::FirstName.as_str(), HashSet::from([self.first_name.as_str()])));
-        ldif.push((UserAttribute::LastName.as_str(), HashSet::from([self.last_name.as_str()])));
-        ldif.push((UserAttribute::Name.as_str(), HashSet::from([self.name.as_str()])));
-        ldif.push((UserAttribute::School.as_str(), HashSet::from([self.school.as_str()])));
-        ldif.push((UserAttribute::Genie.as_str(), HashSet::from([self.genie.as_str()])));
-        ldif.push((UserAttribute::Uid.as_str(), HashSet::from([self.uid.as_str()])));
-        ldif.push((UserAttribute::Matricule.as_str(), HashSet::from([self.matricule.as_str()])));
-        ldif.push((UserAttribute::Number.as_str(), HashSet::from([self.number.as_str()])));
-        ldif.push(("objectClass", HashSet::from(["inetOrgPerson"])));
-        let ldif = ldif.into_iter().filter(|(_, v)| !v.contains("")).collect();
+        ldif.push((attributes.name(UserAttribute::Password), HashSet::from([self.password.as_str()])));
+        ldif.push((attributes.name(UserAttribute::Mail), self.mail.iter().take(1).map(|m| m.as_str()).collect()));
+        ldif.push((attributes.name(UserAttribute::FirstName), HashSet::from([self.first_name.as_str()])));
+        ldif.push((attributes.name(UserAttribute::LastName), HashSet::from([self.last_name.as_str()])));
+        ldif.push((attributes.name(UserAttribute::Name), HashSet::from([self.name.as_str()])));
+        ldif.push((attributes.name(UserAttribute::School), HashSet::from([self.school.as_str()])));
+        ldif.push((attributes.name(UserAttribute::Genie), HashSet::from([self.genie.as_str()])));
+        ldif.push((attributes.name(UserAttribute::Uid), HashSet::from([self.uid.as_str()])));
+        ldif.push((attributes.name(UserAttribute::Matricule), HashSet::from([self.matricule.as_str()])));
+        ldif.push((attributes.name(UserAttribute::Number), self.number.iter().take(1).map(|n| n.as_str()).collect()));
+        for (name, values) in &self.extra {
+            ldif.push((name.as_str(), values.iter().take(1).map(|v| v.as_str()).collect()));
+        }
+        ldif.push(("objectClass", attributes.object_classes().iter().map(|c| c.as_str()).collect()));
+        let ldif = ldif.into_iter().filter(|(_, v)| !v.is_empty() && !v.contains("")).collect();
 
         ldif
     }
@@ -1,3 +1,8 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use crate::common::Config;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum UserAttribute {
     Password,
     Mail,
@@ -15,25 +20,40 @@ pub enum UserAttribute {
 }
 
 impl UserAttribute {
-    pub fn from_str(s: &str) -> Self {
-        match s {
-            "userPassword" => Self::Password,
-            "mail" => Self::Mail,
-            "givenName" => Self::FirstName,
-            "cn" => Self::Name,
-            "sn" => Self::LastName,
-            "departmentNumber" => Self::School,
-            "roomNumber" => Self::Genie,
-            "uid" => Self::Uid,
-            "employeeNumber" => Self::Matricule,
-            "telephoneNumber" => Self::Number,
-            "memberOf" => Self::MemberOf,
-            "jpegPhoto" => Self::Picture,
-            _ => Self::None,
-        }
-    }
-
-    pub fn as_str(&self) -> &str {
+    /// Attributes that `USER_ATTRIBUTES` can map, the other ones are fixed.
+    pub const MAPPED: [UserAttribute; 9] = [
+        Self::Mail,
+        Self::FirstName,
+        Self::LastName,
+        Self::Name,
+        Self::School,
+        Self::Genie,
+        Self::Matricule,
+        Self::Number,
+        Self::Picture,
+    ];
+
+    /// Name of the field in the API.
+    pub fn field(&self) -> &'static str {
+        match self {
+            Self::Password => "password",
+            Self::Mail => "mail",
+            Self::FirstName => "first_name",
+            Self::LastName => "last_name",
+            Self::Name => "name",
+            Self::School => "school",
+            Self::Genie => "genie",
+            Self::Matricule => "matricule",
+            Self::Number => "number",
+            Self::Picture => "picture",
+            Self::Uid => "uid",
+            Self::MemberOf => "groups",
+            Self::None => "",
+        }
+    }
+
+    /// LDAP attribute used when the configuration does not map it elsewhere.
+    pub fn default_name(&self) -> &'static str {
         match self {
             Self::Password => "userPassword",
             Self::Mail => "mail",
@@ -49,9 +69,182 @@ impl UserAttribute {
             Self::Picture => "jpegPhoto",
             Self::None => "",
         }
-    }   
+    }
+}
+
+/// Mapping between the API fields and the LDAP attributes of a deployment.
+#[derive(Debug, Clone)]
+pub struct AttributeMap {
+    names: HashMap<UserAttribute, String>,
+    custom: BTreeMap<String, String>,
+    object_classes: Vec<String>,
+    password_changed: String,
+    must_change_password: String,
+}
+
+impl Default for AttributeMap {
+    fn default() -> Self {
+        Self {
+            names: HashMap::new(),
+            custom: BTreeMap::new(),
+            object_classes: vec!["inetOrgPerson".to_string()],
+            password_changed: "pwdChangedTime".to_string(),
+            must_change_password: "pwdReset".to_string(),
+        }
+    }
+}
+
+impl AttributeMap {
+    pub fn new(config: &Config) -> Self {
+        let map = Self {
+            object_classes: config.user_object_classes.clone(),
+            password_changed: config.password_changed_attribute.clone(),
+            must_change_password: config.password_must_change_attribute.clone(),
+            ..Self::default()
+        };
+
+        map.with_entries(&config.user_attributes, &config.user_custom_attributes)
+            .unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Applies the `field:attribute` entries of `USER_ATTRIBUTES` and `USER_CUSTOM_ATTRIBUTES`.
+    fn with_entries(mut self, mapped: &[String], custom: &[String]) -> Result<Self, String> {
+        for entry in mapped {
+            let (attribute, name) = entry
+                .split_once(':')
+                .filter(|(_, name)| !name.is_empty())
+                .and_then(|(field, name)| Some((UserAttribute::MAPPED.into_iter().find(|a| a.field() == field)?, name)))
+                .ok_or_else(|| format!("USER_ATTRIBUTES must list field:attribute with a field that can be mapped, not {}", entry))?;
+            if self.names.insert(attribute, name.to_string()).is_some() {
+                return Err(format!("USER_ATTRIBUTES maps {} twice", attribute.field()));
+            }
+        }
+
+        for entry in custom {
+            let (field, name) = entry
+                .split_once(':')
+                .filter(|(field, name)| !field.is_empty() && !name.is_empty() && self.attribute_by_field(field).is_none())
+                .ok_or_else(|| format!("USER_CUSTOM_ATTRIBUTES must list field:attribute with a new field name, not {}", entry))?;
+            if self.custom.insert(field.to_string(), name.to_string()).is_some() {
+                return Err(format!("USER_CUSTOM_ATTRIBUTES lists {} twice", field));
+            }
+        }
+
+        // Two fields held by one attribute would overwrite each other
+        let mut targets = HashSet::new();
+        let fixed = [UserAttribute::Password, UserAttribute::Uid, UserAttribute::MemberOf];
+        let names = fixed.into_iter().chain(UserAttribute::MAPPED).map(|a| self.name(a)).chain(self.custom.values().map(|n| n.as_str()));
+        for name in names {
+            if !targets.insert(name.to_lowercase()) {
+                return Err(format!("USER_ATTRIBUTES and USER_CUSTOM_ATTRIBUTES map two fields to {}", name));
+            }
+        }
+
+        Ok(self)
+    }
+
+    fn attribute_by_field(&self, field: &str) -> Option<UserAttribute> {
+        [UserAttribute::Password, UserAttribute::Uid, UserAttribute::MemberOf]
+            .into_iter()
+            .chain(UserAttribute::MAPPED)
+            .find(|a| a.field() == field)
+    }
+
+    /// LDAP attribute holding a field.
+    pub fn name(&self, attribute: UserAttribute) -> &str {
+        self.names.get(&attribute).map(|n| n.as_str()).unwrap_or(attribute.default_name())
+    }
+
+    /// Field held by an LDAP attribute, attribute names are case insensitive.
+    pub fn attribute(&self, name: &str) -> UserAttribute {
+        [UserAttribute::Password, UserAttribute::Uid, UserAttribute::MemberOf]
+            .into_iter()
+            .chain(UserAttribute::MAPPED)
+            .find(|a| self.name(*a).eq_ignore_ascii_case(name))
+            .unwrap_or(UserAttribute::None)
+    }
+
+    /// Custom fields of the deployment, by API name.
+    pub fn custom(&self) -> &BTreeMap<String, String> {
+        &self.custom
+    }
+
+    pub fn object_classes(&self) -> &[String] {
+        &self.object_classes
+    }
+
+    pub fn password_changed(&self) -> &str {
+        &self.password_changed
+    }
+
+    pub fn must_change_password(&self) -> &str {
+        &self.must_change_password
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{BTreeMap, HashMap};
+
+    use ldap3::SearchEntry;
+
+    use crate::common::user::User;
+
+    use super::{AttributeMap, UserAttribute};
+
+    fn attributes() -> AttributeMap {
+        AttributeMap {
+            names: HashMap::from([(UserAttribute::School, "ou".to_string())]),
+            custom: BTreeMap::from([("team".to_string(), "polyorbiteTeam".to_string())]),
+            ..AttributeMap::default()
+        }
+    }
+
+    fn entry(attrs: &[(&str, &str)]) -> SearchEntry {
+        SearchEntry {
+            dn: "uid=alice,ou=people,dc=polyorbite,dc=com".to_string(),
+            attrs: attrs.iter().map(|(k, v)| (k.to_string(), vec![v.to_string()])).collect(),
+            bin_attrs: HashMap::new(),
+        }
+    }
+
+    #[test]
+    fn fields_are_read_from_the_mapped_attributes() {
+        let attributes = attributes();
+        let user = User::new(entry(&[("uid", "alice"), ("ou", "Polytechnique"), ("departmentNumber", "42"), ("polyorbiteteam", "avionique")]), &attributes);
 
-    pub fn as_bytes(&self) -> &[u8] {
-        self.as_str().as_bytes()
+        assert_eq!(user.school, "Polytechnique");
+        assert_eq!(user.custom(&attributes), BTreeMap::from([("team".to_string(), vec!["avionique".to_string()])]));
+        assert_eq!(attributes.attribute("departmentNumber"), UserAttribute::None);
+        assert_eq!(attributes.attribute("GIVENNAME"), UserAttribute::FirstName);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn invalid_or_conflicting_entries_are_refused() {
+        let entries = |list: &[&str]| list.iter().map(|e| e.to_string()).collect::<Vec<String>>();
+        let map = |mapped: &[&str], custom: &[&str]| AttributeMap::default().with_entries(&entries(mapped), &entries(custom));
+
+        assert!(map(&["school:ou", "genie:roomNumber"], &["team:polyorbiteTeam"]).is_ok());
+        assert!(map(&["school"], &[]).is_err());
+        assert!(map(&["uid:login"], &[]).is_err());
+        assert!(map(&["school:"], &[]).is_err());
+        assert!(map(&["school:ou", "school:o"], &[]).is_err());
+        assert!(map(&[], &["mail:otherMail"]).is_err());
+        assert!(map(&[], &["team:a", "team:b"]).is_err());
+        // genie still uses roomNumber
+        assert!(map(&["school:roomNumber"], &[]).is_err());
+        assert!(map(&[], &["team:CN"]).is_err());
+    }
+
+    #[test]
+    fn new_entries_use_the_mapped_attributes() {
+        let attributes = attributes();
+        let mut user = User::new(entry(&[("uid", "alice"), ("ou", "Polytechnique")]), &attributes);
+        user.extra.insert("polyorbiteTeam".to_string(), vec!["avionique".to_string()]);
+
+        let ldif = user.to_ldif(&attributes);
+        let names: Vec<&str> = ldif.iter().map(|(name, _)| *name).collect();
+        assert!(names.contains(&"ou") && names.contains(&"polyorbiteTeam"));
+        assert!(!names.contains(&"departmentNumber"));
+    }
+}
@@ -1,4 +1,8 @@
-use crate::common::password::{Password, DEFAULT_HASH};
+use std::collections::HashMap;
+
+use serde::Serialize;
+
+use crate::common::password::{Hash, Password, DEFAULT_HASH};
 
 use super::User;
 
@@ -15,14 +19,15 @@ impl UserBuilder {
                     name: String::new(),
                     first_name: String::new(),
                     last_name: String::new(),
-                    mail: String::new(),
+                    mail: Vec::new(),
                     school: String::new(),
                     genie: String::new(),
                     matricule: String::new(),
-                    number: String::new(),
+                    number: Vec::new(),
                     password: String::new(),
                     picture: None,
                     member: None,
+                    extra: HashMap::new(),
                 }
             }
         }
@@ -33,13 +38,22 @@ impl UserBuilder {
         self
     }
 
-    pub fn password(mut self, password: String) -> Self {
-        self.user.password = Password::hash(password.as_str(), DEFAULT_HASH);
+    pub fn password(self, password: String) -> Self {
+        self.password_hash(password, DEFAULT_HASH)
+    }
+
+    pub fn password_hash(mut self, password: String, hash: Hash) -> Self {
+        self.user.password = Password::hash(password.as_str(), hash);
         self
     }
 
-    pub fn mail(mut self, mail: String) -> Self {
-        self.user.mail = mail;
+    pub fn mail(self, mail: String) -> Self {
+        self.mails(vec![mail])
+    }
+
+    /// The first address is the primary one.
+    pub fn mails(mut self, mails: Vec<String>) -> Self {
+        self.user.mail = mails.into_iter().filter(|m| !m.is_empty()).collect();
         self
     }
 
@@ -73,8 +87,12 @@ impl UserBuilder {
         self
     }
 
-    pub fn number(mut self, number: String) -> Self {
-        self.user.number = number;
+    pub fn number(self, number: String) -> Self {
+        self.numbers(vec![number])
+    }
+
+    pub fn numbers(mut self, numbers: Vec<String>) -> Self {
+        self.user.number = numbers.into_iter().filter(|n| !n.is_empty()).collect();
         self
     }
 
@@ -83,25 +101,137 @@ impl UserBuilder {
         self
     }
 
-    pub fn build(self) -> Result<User, &'static str> {
-        if self.user.uid.is_empty() {
-            return Err("uid is required");
-        }
-        if self.user.password.is_empty() {
-            return Err("password is required");
-        }
-        if self.user.mail.is_empty() {
-            return Err("mail is required");
+    /// Set an attribute that has no dedicated field.
+    pub fn attribute(mut self, name: String, values: Vec<String>) -> Self {
+        self.user.extra.insert(name, values);
+        self
+    }
+
+    pub fn build(self) -> Result<User, Vec<FieldError>> {
+        let user = &self.user;
+        let mut errors: Vec<FieldError> = [
+            ("uid", user.uid.as_str()),
+            ("password", &user.password),
+            ("mail", user.primary_mail()),
+            ("first_name", &user.first_name),
+            ("last_name", &user.last_name),
+            ("name", &user.name),
+        ]
+        .into_iter()
+        .filter(|(_, value)| value.is_empty())
+        .map(|(field, _)| FieldError::required(field))
+        .collect();
+
+        if !user.uid.is_empty() && !valid_uid(&user.uid) {
+            errors.push(FieldError::new("uid", "invalid", "uid can only contain lowercase letters, digits, '.', '_' and '-'"));
         }
-        if self.user.first_name.is_empty() {
-            return Err("first_name is required");
+        errors.extend(invalid_values(&user.mail, &user.number));
+
+        match errors.is_empty() {
+            true => Ok(self.user),
+            false => Err(errors),
         }
-        if self.user.last_name.is_empty() {
-            return Err("last_name is required");
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FieldError {
+    #[serde(skip)]
+    pub field: String,
+    pub code: &'static str,
+    pub message: String,
+}
+
+impl FieldError {
+    pub fn new(field: &str, code: &'static str, message: &str) -> Self {
+        Self { field: field.to_string(), code, message: message.to_string() }
+    }
+
+    pub fn required(field: &str) -> Self {
+        Self::new(field, "required", &format!("{} is required", field))
+    }
+}
+
+/// Errors for the addresses and phone numbers that are not well formed.
+pub(super) fn invalid_values<'a>(
+    mails: impl IntoIterator<Item = &'a String>,
+    numbers: impl IntoIterator<Item = &'a String>,
+) -> Vec<FieldError> {
+    let mails = mails
+        .into_iter()
+        .filter(|mail| !valid_mail(mail))
+        .map(|mail| FieldError::new("mail", "invalid", &format!("{} is not a valid email address", mail)));
+    let numbers = numbers
+        .into_iter()
+        .filter(|number| !valid_phone(number))
+        .map(|number| FieldError::new("number", "invalid", &format!("{} is not a valid phone number", number)));
+
+    mails.chain(numbers).collect()
+}
+
+pub(super) fn valid_uid(uid: &str) -> bool {
+    uid.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || matches!(c, '.' | '_' | '-'))
+}
+
+fn valid_phone(number: &str) -> bool {
+    number.chars().any(|c| c.is_ascii_digit())
+        && number.chars().all(|c| c.is_ascii_digit() || matches!(c, ' ' | '+' | '-' | '.' | '(' | ')'))
+}
+
+/// A local part and a domain of at least two non-empty labels, `a@.` or `a@b.` are refused.
+fn valid_mail(mail: &str) -> bool {
+    match mail.split_once('@') {
+        Some((local, domain)) => {
+            !local.is_empty()
+                && !domain.contains('@')
+                && !mail.contains(char::is_whitespace)
+                && domain.split('.').count() >= 2
+                && domain.split('.').all(|label| !label.is_empty())
         }
-        if self.user.name.is_empty() {
-            return Err("name is required");
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{valid_mail, valid_uid, UserBuilder};
+
+    #[test]
+    fn addresses_need_a_local_part_and_a_dotted_domain() {
+        assert!(valid_mail("alice@polyorbite.com"));
+        assert!(valid_mail("alice.t+club@etud.polymtl.ca"));
+        for mail in ["alice", "@polyorbite.com", "alice@polyorbite", "a@.", "a@b.", "a@.b", "a@b..c", "a@b@c.d", "al ice@polyorbite.com"] {
+            assert!(!valid_mail(mail), "{}", mail);
         }
-        Ok(self.user)
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn uids_are_lowercase_letters_digits_and_separators() {
+        assert!(valid_uid("alice.tremblay-2_b"));
+        assert!(!valid_uid("Alice"));
+        assert!(!valid_uid("alice tremblay"));
+        assert!(!valid_uid("alice,ou=admins"));
+    }
+
+    #[test]
+    fn build_reports_every_field_error() {
+        let errors = UserBuilder::new()
+            .uid("Alice".to_string())
+            .mails(vec!["alice@polyorbite.com".to_string(), "a@.".to_string()])
+            .number("call me".to_string())
+            .first_name("Alice".to_string())
+            .build()
+            .unwrap_err();
+        let codes: Vec<(&str, &str)> = errors.iter().map(|e| (e.field.as_str(), e.code)).collect();
+
+        assert_eq!(codes, vec![
+            ("password", "required"),
+            ("last_name", "required"),
+            ("name", "required"),
+            ("uid", "invalid"),
+            ("mail", "invalid"),
+            ("number", "invalid"),
+        ]);
+        assert_eq!(errors[4].message, "a@. is not a valid email address");
+    }
+}
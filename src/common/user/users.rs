@@ -1,9 +1,9 @@
-use std::{collections::HashMap, sync::Arc};
+use std::{collections::HashMap, io, sync::Arc};
 
-use ldap3::{LdapConnAsync, Scope};
+use ldap3::{LdapConnAsync, LdapError, Scope};
 use tokio::sync::Mutex;
 
-use super::{ModifyUser, User};
+use super::{AttributeMap, Binder, ModifyUser, User, UserCursor, UserPage, UserQuery, UserSort};
 
 #[derive(Debug)]
 pub struct Users {
@@ -13,11 +13,12 @@ pub struct Users {
     ldap_password: String,
     users_base_dn: String,
     base_dn: String,
+    attribute_map: Arc<AttributeMap>,
 }
 
 
 impl Users {
-    pub fn new(ldap_url: String, ldap_user: String, ldap_password: String, users_base_dn: String, base_dn: String) -> Self {
+    pub fn new(ldap_url: String, ldap_user: String, ldap_password: String, users_base_dn: String, base_dn: String, attribute_map: Arc<AttributeMap>) -> Self {
         Self {
             users: Arc::new(Mutex::new(HashMap::new())),
             ldap_url,
@@ -25,10 +26,20 @@ impl Users {
             ldap_password,
             users_base_dn,
             base_dn,
+            attribute_map,
         }
     }
 
-    async fn update_user(&mut self, id: &str) -> ldap3::result::Result<()> {
+    pub fn attribute_map(&self) -> Arc<AttributeMap> {
+        self.attribute_map.clone()
+    }
+
+    /// The password attributes are operational with ppolicy, so they must be asked for.
+    fn attributes(&self) -> Vec<&str> {
+        vec!["*", "memberOf", self.attribute_map.password_changed(), self.attribute_map.must_change_password()]
+    }
+
+    pub async fn update_user(&mut self, id: &str) -> ldap3::result::Result<()> {
         let (conn, mut ldap) = LdapConnAsync::new(self.ldap_url.as_str()).await?;
         ldap3::drive!(conn);
 
@@ -36,10 +47,10 @@ impl Users {
             .await?
             .success()?;
 
-        let filter = format!("(&(objectClass=inetOrgPerson)(uid={}))", id);
+        let filter = format!("(&(objectClass=inetOrgPerson)(uid={}))", ldap3::ldap_escape(id));
 
         let (rs, _res) = ldap
-            .search(self.base_dn.as_str(), Scope::Subtree, filter.as_str(), vec!["*", "memberOf"])
+            .search(self.base_dn.as_str(), Scope::Subtree, filter.as_str(), self.attributes())
             .await?
             .success()?;
 
@@ -51,7 +62,7 @@ impl Users {
         }
 
         let entry = rs.first().unwrap();
-        let user = User::new(ldap3::SearchEntry::construct(entry.clone()));
+        let user = User::new(ldap3::SearchEntry::construct(entry.clone()), &self.attribute_map);
         self.users.lock().await.insert(user.uid.clone(), user);
         Ok(())
     }
@@ -67,7 +78,7 @@ impl Users {
         let filter = "(objectClass=inetOrgPerson)";
 
         let (rs, _res) = ldap
-            .search(self.base_dn.as_str(), Scope::Subtree, filter, vec!["*", "memberOf"])
+            .search(self.base_dn.as_str(), Scope::Subtree, filter, self.attributes())
             .await?
             .success()?;
 
@@ -76,16 +87,38 @@ impl Users {
         let mut users = self.users.lock().await;
         users.clear();
         for entry in rs {
-            let user = User::new(ldap3::SearchEntry::construct(entry));
+            let user = User::new(ldap3::SearchEntry::construct(entry), &self.attribute_map);
             users.insert(user.uid.clone(), user);
         }
         Ok(())
     }
 
+    /// Binds as a user on a connection of its own, so the directory does not
+    /// have to stay locked while the server checks the password.
+    pub fn binder(&self) -> Binder {
+        Binder::new(self.ldap_url.clone(), self.users_base_dn.clone())
+    }
+
     pub async fn user(&self, id: &str) -> Option<User> {
         self.users.lock().await.get(id).map(|u| u.clone())
     }
 
+    /// The user whose uid matches `id` whatever its case, as the directory matches it on bind.
+    pub async fn user_ignore_case(&self, id: &str) -> Option<User> {
+        let users = self.users.lock().await;
+        users
+            .get(id)
+            .or_else(|| users.values().find(|u| u.uid.eq_ignore_ascii_case(id)))
+            .cloned()
+    }
+
+    /// Filters and sorts under the lock, only the users of the page are cloned.
+    pub async fn page(&self, query: &UserQuery, sort: &UserSort, cursor: Option<&UserCursor>, limit: usize) -> (Vec<User>, usize, Option<UserCursor>) {
+        let users = self.users.lock().await;
+        let page = UserPage::new(users.values(), query, sort, cursor, limit);
+        (page.users.into_iter().cloned().collect(), page.total, page.next_cursor)
+    }
+
     pub async fn to_vec(&self) -> Vec<User> {
         self.users.lock().await.values().map(|u| u.clone()).collect()
     }
@@ -106,7 +139,7 @@ impl Users {
             return Ok(false);
         }
     
-        let (changes1, changes2) = modification.to_ldif(user.unwrap());
+        let (changes1, changes2) = modification.to_ldif(user.unwrap(), &self.attribute_map);
 
         let dn = format!("uid={},{}", id, self.users_base_dn);
 
@@ -175,7 +208,9 @@ impl Users {
         Ok(true)
     }
 
-    pub async fn new_user(&mut self, user: User) -> ldap3::result::Result<bool> {
+    /// `Ok(false)` when the uid is already taken. The entry is deleted again when the
+    /// values added after it are refused, a user is never left half created.
+    pub async fn new_user(&mut self, user: User, must_change_password: bool) -> ldap3::result::Result<bool> {
         if self.user(user.uid.as_str()).await.is_some() {
             return Ok(false);
         }
@@ -190,27 +225,87 @@ impl Users {
         let dn = format!("uid={},{}", user.uid, self.users_base_dn);
 
         let result = ldap
-            .add(dn.as_str(), user.to_ldif())
+            .add(dn.as_str(), user.to_ldif(&self.attribute_map))
             .await?
             .success();
 
         ldap.unbind().await?;
 
-        if result.is_err() {
-            return Ok(false);
+        match result {
+            Ok(_) => (),
+            // entryAlreadyExists
+            Err(LdapError::LdapResult { result }) if result.rc == 68 => return Ok(false),
+            Err(e) => return Err(e),
         }
 
         self.update_user(user.uid.as_str()).await?;
 
-        if user.picture.is_some() {
-            let modification = ModifyUser::new().picture(user.picture.unwrap());
-            self.modify_user(user.uid.as_str(), modification).await?;
+        let mut modification = ModifyUser::completing(&user);
+        if must_change_password {
+            modification = modification.attribute(self.attribute_map.must_change_password().to_string(), vec!["TRUE".to_string()]);
+        }
+        if let Err(e) = self.apply(user.uid.as_str(), modification).await {
+            self.delete_user(user.uid.as_str()).await?;
+            return Err(e);
         }
 
         Ok(true)
     }
 
+    /// Same as `modify_user`, but a change the directory refuses is an error.
+    async fn apply(&mut self, id: &str, modification: ModifyUser) -> ldap3::result::Result<()> {
+        let user = self
+            .user(id)
+            .await
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("{} is not in the directory", id)))?;
+        let (changes1, changes2) = modification.to_ldif(user, &self.attribute_map);
+        if changes1.is_empty() && changes2.is_empty() {
+            return Ok(());
+        }
+
+        let (conn, mut ldap) = LdapConnAsync::new(self.ldap_url.as_str()).await?;
+        ldap3::drive!(conn);
+
+        ldap.simple_bind(self.ldap_user.as_str(), self.ldap_password.as_str())
+            .await?
+            .success()?;
+
+        let dn = format!("uid={},{}", id, self.users_base_dn);
+        if !changes1.is_empty() {
+            ldap.modify(dn.as_str(), changes1).await?.success()?;
+        }
+        if !changes2.is_empty() {
+            ldap.modify(dn.as_str(), changes2).await?.success()?;
+        }
+
+        ldap.unbind().await?;
+        self.update_user(id).await
+    }
+
     pub async fn member_of(&self, cn: &str) -> Vec<User> {
         self.users.lock().await.values().filter(|u| u.member.is_some() && u.member.as_ref().unwrap().contains(cn)).map(|u| u.clone()).collect()
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::common::user::{fixture::user, AttributeMap};
+
+    use super::Users;
+
+    #[tokio::test]
+    async fn a_uid_is_found_whatever_its_case() {
+        let users = Users::new(String::new(), String::new(), String::new(), String::new(), String::new(), Arc::new(AttributeMap::default()));
+        // An entry made outside of this API, new uids are lowercase
+        let mut alice = user("alice.tremblay", "Alice", "Tremblay", "Polytechnique", &[]);
+        alice.uid = "Alice.Tremblay".to_string();
+        users.users.lock().await.insert(alice.uid.clone(), alice);
+
+        assert!(users.user("alice.tremblay").await.is_none());
+        assert_eq!(users.user_ignore_case("alice.tremblay").await.unwrap().uid, "Alice.Tremblay");
+        assert_eq!(users.user_ignore_case("ALICE.TREMBLAY").await.unwrap().uid, "Alice.Tremblay");
+        assert!(users.user_ignore_case("alice").await.is_none());
+    }
+}
@@ -1,7 +1,7 @@
 mod api_doc;
 use api_doc::ApiDoc;
 use tracing::Span;
-use std::time::Duration;
+use std::{net::SocketAddr, time::Duration};
 use utoipa::OpenApi;
 use utoipa_swagger_ui::SwaggerUi;
 
@@ -11,9 +11,9 @@ use tower_http::{
     trace::TraceLayer,
 };
 
-use api_polyorbite::{common::{Config, Ldap}, route::{create_router, AppState}};
+use api_polyorbite::{common::{Config, Ldap}, route::{auth, create_router, AppState}};
 use axum::http::{
-        header::{ACCEPT, AUTHORIZATION, CONTENT_TYPE, ORIGIN},
+        header::{HeaderName, ACCEPT, AUTHORIZATION, CONTENT_TYPE, ORIGIN},
         Method, Request,
     };
 use dotenv::dotenv;
@@ -50,7 +50,7 @@ async fn main() {
             Method::PUT,
         ])
         .allow_origin(Any)
-        .allow_headers([CONTENT_TYPE, ORIGIN, ACCEPT, AUTHORIZATION]);
+        .allow_headers([CONTENT_TYPE, ORIGIN, ACCEPT, AUTHORIZATION, HeaderName::from_static(auth::API_KEY_HEADER)]);
     let state = AppState::new(ldap, config);
 
     let app = create_router(state.clone())
@@ -67,5 +67,5 @@ async fn main() {
         .await
         .unwrap();
     tracing::debug!("🚀 Server started successfully on {}", listener.local_addr().unwrap());
-    axum::serve(listener, app).await.unwrap();
+    axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>()).await.unwrap();
 }
\ No newline at end of file
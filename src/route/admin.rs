@@ -0,0 +1,209 @@
+use axum::{
+    extract::{Json, Path, State}, http::StatusCode, Extension
+};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::common::{password::Password, role::Role, throttle::LoginThrottle, token::{generate_token, hash_token, ApiKey}, user::User};
+
+use super::{auth::{self, AuthError}, AppState};
+
+#[derive(Serialize, ToSchema)]
+pub struct PasswordReportEntry {
+    uid: String,
+    problem: &'static str,
+    message: String,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct CreateApiKeyData {
+    pub name: String,
+    pub scopes: Vec<Role>,
+    pub group: Option<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct CreatedApiKey {
+    key: String,
+    #[serde(flatten)]
+    api_key: ApiKey,
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/protected/admin/users/{uid}/revoke",
+    params(
+        ("uid" = String, Path, description = "User whose sessions are revoked")
+    ),
+    responses(
+        (status = 204, description = "Success"),
+        (status = 401, description = "Unauthorized"),
+        (status = 403, description = "Missing role: admin"),
+        (status = 440, description = "Token has expired")
+    ),
+    security(
+        ("jwt" = [])
+    )
+)]
+pub async fn revoke_sessions(
+    State(data): State<AppState>,
+    Path(uid): Path<String>
+) -> Result<StatusCode, AuthError> {
+    data.denylist.revoke_user(uid.clone()).map_err(auth::revocation_failed)?;
+    data.refresh.revoke_user(&uid).map_err(auth::revocation_failed)?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/protected/admin/lockouts/{username}",
+    params(
+        ("username" = String, Path, description = "User whose failed sign-in attempts are cleared")
+    ),
+    responses(
+        (status = 204, description = "Success"),
+        (status = 401, description = "Unauthorized"),
+        (status = 403, description = "Missing role: admin")
+    ),
+    security(
+        ("jwt" = []),
+        ("api_key" = [])
+    )
+)]
+pub async fn clear_lockout(
+    State(data): State<AppState>,
+    Path(username): Path<String>
+) -> StatusCode {
+    data.throttle.users.clear(&LoginThrottle::user_key(&username));
+    data.throttle.users.clear(&LoginThrottle::second_factor_key(&username));
+
+    StatusCode::NO_CONTENT
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/protected/admin/password-report",
+    responses(
+        (status = 200, description = "Accounts whose password is stored in plaintext or cannot be parsed", body = [PasswordReportEntry]),
+        (status = 401, description = "Unauthorized"),
+        (status = 403, description = "Missing role: admin")
+    ),
+    security(
+        ("jwt" = []),
+        ("api_key" = [])
+    )
+)]
+pub async fn password_report(State(data): State<AppState>) -> Json<Vec<PasswordReportEntry>> {
+    let users = data.ldap.lock().await.users.to_vec().await;
+
+    let mut report: Vec<PasswordReportEntry> = users
+        .into_iter()
+        .filter(|user| !user.password.is_empty())
+        .filter_map(|user| {
+            let e = Password::check(&user.password).err()?;
+            Some(PasswordReportEntry {
+                uid: user.uid,
+                problem: e.code(),
+                message: e.to_string(),
+            })
+        })
+        .collect();
+    report.sort_by(|a, b| a.uid.cmp(&b.uid));
+
+    Json(report)
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/protected/admin/api-keys",
+    request_body = CreateApiKeyData,
+    responses(
+        (status = 201, description = "API key created, the key is only shown once", body = CreatedApiKey),
+        (status = 400, description = "Missing name or scopes"),
+        (status = 403, description = "Missing role: admin"),
+        (status = 404, description = "Group not found"),
+        (status = 500, description = "The key could not be written to API_KEY_STORE")
+    ),
+    security(
+        ("jwt" = []),
+        ("api_key" = [])
+    )
+)]
+pub async fn create_api_key(
+    State(data): State<AppState>,
+    Extension(user): Extension<User>,
+    Json(api_key_data): Json<CreateApiKeyData>
+) -> Result<(StatusCode, Json<CreatedApiKey>), AuthError> {
+    let name = api_key_data.name.trim();
+    if name.is_empty() {
+        return Err(AuthError::new(StatusCode::BAD_REQUEST, "The API key needs a name"));
+    }
+    if api_key_data.scopes.is_empty() {
+        return Err(AuthError::new(StatusCode::BAD_REQUEST, "The API key needs at least one scope"));
+    }
+
+    if let Some(group) = &api_key_data.group {
+        if data.ldap.lock().await.groups.group(group).await.is_none() {
+            return Err(AuthError::new(StatusCode::NOT_FOUND, "Group not found"));
+        }
+    }
+
+    let key = generate_token();
+    let api_key = ApiKey {
+        id: generate_token()[..16].to_string(),
+        name: name.to_string(),
+        scopes: api_key_data.scopes,
+        group: api_key_data.group,
+        created_by: user.uid,
+        created_at: Utc::now().timestamp(),
+    };
+    data.api_keys.save(hash_token(&key), api_key.clone()).map_err(|e| {
+        tracing::debug!("🔥 Failed to write the API keys: {:?}", e);
+        AuthError::new(StatusCode::INTERNAL_SERVER_ERROR, "Unable to save the API key")
+    })?;
+
+    Ok((StatusCode::CREATED, Json(CreatedApiKey { key, api_key })))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/protected/admin/api-keys",
+    responses(
+        (status = 200, description = "Success", body = [ApiKey]),
+        (status = 403, description = "Missing role: admin")
+    ),
+    security(
+        ("jwt" = []),
+        ("api_key" = [])
+    )
+)]
+pub async fn get_api_keys(State(data): State<AppState>) -> Json<Vec<ApiKey>> {
+    Json(data.api_keys.list())
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/protected/admin/api-keys/{id}",
+    params(
+        ("id" = String, Path, description = "API key to revoke")
+    ),
+    responses(
+        (status = 204, description = "Success"),
+        (status = 403, description = "Missing role: admin"),
+        (status = 404, description = "API key not found"),
+        (status = 500, description = "The key is revoked but could not be written to API_KEY_STORE")
+    ),
+    security(
+        ("jwt" = []),
+        ("api_key" = [])
+    )
+)]
+pub async fn revoke_api_key(State(data): State<AppState>, Path(id): Path<String>) -> Result<StatusCode, AuthError> {
+    match data.api_keys.remove(&id) {
+        Ok(true) => Ok(StatusCode::NO_CONTENT),
+        Ok(false) => Err(AuthError::new(StatusCode::NOT_FOUND, "API key not found")),
+        Err(e) => Err(auth::revocation_failed(e)),
+    }
+}
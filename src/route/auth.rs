@@ -1,92 +1,182 @@
-use std::{num::NonZeroU16, sync::Arc};
+use std::{collections::HashSet, future::Future, io, net::SocketAddr, num::NonZeroU16, sync::Arc};
 
 use axum::{
-    body::Body, extract::{Json, Request, State}, http::{self, Response, StatusCode}, middleware::Next, response::IntoResponse
+    body::Body, extract::{ConnectInfo, Json, OriginalUri, Request, State}, http::{self, HeaderMap, HeaderValue, Response, StatusCode}, middleware::Next, response::IntoResponse, Extension
 };
 use chrono::{Duration, Utc};
-use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, TokenData, Validation};
+use jsonwebtoken::{jwk::JwkSet, TokenData};
 use serde::{Deserialize, Serialize};
-use serde_json::json;
+use serde_json::{json, Map, Value};
 use utoipa::{OpenApi, ToSchema};
 
-use crate::common::Config;
+use crate::common::{password::{Password, PasswordError, PlaintextPolicy}, role::{Role, Roles}, throttle::{forwarded_client_ip, LoginThrottle}, token::{consume_refresh_token, find_api_key, generate_token, issue_refresh_token, JwtKeys, RefreshError}, user::{BindStatus, ModifyUser, User}, AuthMode, Config};
 
-use super::AppState;
+use super::{mfa, route, AppState};
+
+const MFA_SCOPE: &str = "mfa";
+const MFA_MAXAGE: i64 = 5;
+/// Wrong codes accepted with one second-step token before it is revoked.
+const MFA_MAX_FAILURES: u32 = 5;
+pub const PASSWORD_CHANGE_SCOPE: &str = "password_change";
+const PASSWORD_CHANGE_MAXAGE: i64 = 10;
 
 #[derive(OpenApi)]
 #[openapi(
     paths(
         sign_in,
+        sign_in_mfa,
+        refresh,
+        logout,
+        jwks,
     ),
     components(schemas(
         SignInData,
+        MfaData,
+        MfaChallenge,
+        RefreshData,
         AuthBody
     ))
 )]
 pub(super) struct authApi;
 
 
-#[derive(Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Cliams {
     pub exp: usize,
     pub iat: usize,
+    /// `iat` in milliseconds, to tell a token issued right after a revocation from one issued before it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub iat_ms: Option<i64>,
+    pub jti: String,
     pub username: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scope: Option<String>,
+    /// The OIDC client the token was given to, such a token only opens the userinfo route.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_id: Option<String>,
 }
 
+#[derive(Default)]
 pub struct AuthError {
     message: String,
     status_code: StatusCode,
+    details: Map<String, Value>,
+    retry_after: Option<i64>,
+}
+
+impl AuthError {
+    pub fn new(status_code: StatusCode, message: &str) -> Self {
+        Self {
+            message: message.to_string(),
+            status_code,
+            details: Map::new(),
+            retry_after: None,
+        }
+    }
+
+    /// Seconds to wait before trying again, sent in the Retry-After header.
+    pub fn retry_after(mut self, seconds: i64) -> Self {
+        self.retry_after = Some(seconds);
+        self
+    }
+
+    /// Add a field to the error body, next to `error`.
+    pub fn detail(mut self, key: &str, value: Value) -> Self {
+        self.details.insert(key.to_string(), value);
+        self
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
 }
 
 impl IntoResponse for AuthError {
     fn into_response(self) -> Response<Body> {
-        let body = Json(json!({
-            "error": self.message,
-        }));
+        let mut body = self.details;
+        body.insert("error".to_string(), json!(self.message));
+        let body = Json(body);
+
+        let mut response = (self.status_code, body).into_response();
+        if let Some(retry_after) = self.retry_after {
+            response.headers_mut().insert(http::header::RETRY_AFTER, HeaderValue::from(retry_after));
+        }
+        response
+    }
+}
 
-        (self.status_code, body).into_response()
+impl Cliams {
+    /// Issue time in milliseconds, a token without `iat_ms` counts as issued at the end of its second.
+    pub fn issued_at(&self) -> i64 {
+        self.iat_ms.unwrap_or(self.iat as i64 * 1000 + 999)
     }
 }
 
-pub fn encode_jwt(username: String, config: Config) -> Result<String, StatusCode> {
+/// The revocation holds in memory, but it could not be written to `DENYLIST_STORE` or `REFRESH_STORE`.
+pub fn revocation_failed(e: io::Error) -> AuthError {
+    tracing::debug!("🔥 Failed to persist a revocation: {:?}", e);
+    AuthError::new(StatusCode::INTERNAL_SERVER_ERROR, "Unable to revoke the session")
+}
+
+pub fn encode_jwt(username: String, config: &Config, keys: &JwtKeys) -> Result<String, StatusCode> {
+    encode_scoped_jwt(username, None, config.jwt_maxage.into(), keys)
+}
+
+/// Encode a token that is only valid for `scope`, such as the second sign-in step.
+pub fn encode_scoped_jwt(username: String, scope: Option<&str>, maxage: i64, keys: &JwtKeys) -> Result<String, StatusCode> {
+    encode_claims(username, scope, None, maxage, keys)
+}
+
+/// Encode the access token of an OIDC client, carrying the scope it was granted.
+pub fn encode_oidc_jwt(username: String, client_id: &str, scope: &str, maxage: i64, keys: &JwtKeys) -> Result<String, StatusCode> {
+    encode_claims(username, Some(scope), Some(client_id.to_string()), maxage, keys)
+}
+
+fn encode_claims(username: String, scope: Option<&str>, client_id: Option<String>, maxage: i64, keys: &JwtKeys) -> Result<String, StatusCode> {
     let now = Utc::now();
-    let expire: chrono::TimeDelta = Duration::minutes(config.jwt_maxage.into());
+    let expire: chrono::TimeDelta = Duration::minutes(maxage);
     let exp: usize = (now + expire).timestamp() as usize;
     let iat: usize = now.timestamp() as usize;
+    let jti = generate_token();
+    let scope = scope.map(|s| s.to_string());
 
-    let claim = Cliams { iat, exp, username };
-    let secret = config.jwt_secret;
+    let iat_ms = Some(now.timestamp_millis());
 
-    encode(
-        &Header::default(),
-        &claim,
-        &EncodingKey::from_secret(secret.as_ref()),
-    )
-    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
-}
+    let claim = Cliams { iat, iat_ms, exp, jti, username, scope, client_id };
 
-pub fn decode_jwt(jwt: String, config: Config) -> Result<TokenData<Cliams>, StatusCode> {
+    keys.encode(&claim)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+}
 
-    let result: Result<TokenData<Cliams>, StatusCode> = decode(
-        &jwt,
-        &DecodingKey::from_secret(config.jwt_secret.as_ref()),
-        &Validation::default(),
-    )
-    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR);
-    result
+pub fn decode_jwt(jwt: String, keys: &JwtKeys) -> Result<TokenData<Cliams>, StatusCode> {
+    keys.decode(&jwt)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
 }
 
+pub const API_KEY_HEADER: &str = "x-api-key";
+
 pub async fn authorize(State(data): State<AppState>, mut req: Request, next: Next) -> Result<Response<Body>, AuthError> {
+    if let Some(key) = req.headers().get(API_KEY_HEADER) {
+        let key = key.to_str().map_err(|_| AuthError::new(StatusCode::FORBIDDEN, "Empty header is not allowed"))?;
+        let (service, roles) = authorize_api_key(&data, key).await?;
+
+        req.extensions_mut().insert(service);
+        req.extensions_mut().insert(roles);
+        return Ok(next.run(req).await);
+    }
+
     let auth_header = req.headers_mut().get(http::header::AUTHORIZATION);
 
     let auth_header = match auth_header {
         Some(header) => header.to_str().map_err(|_| AuthError {
             message: "Empty header is not allowed".to_string(),
-            status_code: StatusCode::FORBIDDEN
+            status_code: StatusCode::FORBIDDEN,
+            ..Default::default()
         })?,
         None => return Err(AuthError {
             message: "Please add the JWT token to the header".to_string(),
-            status_code: StatusCode::FORBIDDEN
+            status_code: StatusCode::FORBIDDEN,
+            ..Default::default()
         }),
     };
 
@@ -96,37 +186,101 @@ pub async fn authorize(State(data): State<AppState>, mut req: Request, next: Nex
     if bearer != Some("Bearer") || token.is_none() {
         return Err(AuthError {
             message: "Invalid token".to_string(),
-            status_code: StatusCode::FORBIDDEN
+            status_code: StatusCode::FORBIDDEN,
+            ..Default::default()
         });
     }
 
-    let token_data = match decode_jwt(token.unwrap().to_string(), data.env) {
+    let token_data = match decode_jwt(token.unwrap().to_string(), &data.keys) {
         Ok(data) => data,
         Err(_) => return Err(AuthError {
             message: "Unable to decode token".to_string(),
-            status_code: StatusCode::UNAUTHORIZED
+            status_code: StatusCode::UNAUTHORIZED,
+            ..Default::default()
         }),
     };
 
     if token_data.claims.exp < Utc::now().timestamp() as usize {
         return Err(AuthError {
             message: "Token has expired".to_string(),
-            status_code: StatusCode::from_u16(440).unwrap()
+            status_code: StatusCode::from_u16(440).unwrap(),
+            ..Default::default()
         });
     }
 
-    let current_user = match data.ldap.lock().await.users.user(&token_data.claims.username).await {
+    let claims = token_data.claims;
+
+    if data.denylist.is_revoked(&claims.jti, &claims.username, claims.issued_at()) {
+        return Err(AuthError::new(StatusCode::UNAUTHORIZED, "Token has been revoked"));
+    }
+
+    let path = req.extensions().get::<OriginalUri>().map(|uri| uri.path()).unwrap_or_default();
+    if !opens_route(&claims, path) {
+        return Err(AuthError::new(StatusCode::FORBIDDEN, "Token is restricted"));
+    }
+
+    let ldap = data.ldap.lock().await;
+
+    let current_user = match ldap.users.user(&claims.username).await {
         Some(user) => user,
         None => return Err(AuthError {
             message: "You are not an authorized user".to_string(),
-            status_code: StatusCode::UNAUTHORIZED
+            status_code: StatusCode::UNAUTHORIZED,
+            ..Default::default()
         }),
     };
 
+    let groups = ldap.groups.transitive_groups(&current_user.member.clone().unwrap_or_default()).await;
+    drop(ldap);
+    let roles = Roles::from_groups(&groups, &data.env);
+
     req.extensions_mut().insert(current_user);
+    req.extensions_mut().insert(claims);
+    req.extensions_mut().insert(roles);
     Ok(next.run(req).await)
 }
 
+/// A restricted token only opens the route it was issued for: the userinfo route
+/// for an OIDC client, the password change for a password change token.
+fn opens_route(claims: &Cliams, path: &str) -> bool {
+    match (&claims.client_id, claims.scope.as_deref()) {
+        (Some(_), _) => path.strip_prefix(route::OIDC_PATH) == Some(route::OIDC_USERINFO_ROUTE),
+        (None, Some(PASSWORD_CHANGE_SCOPE)) => path.strip_prefix(route::PROTECTED_PATH) == Some(route::PASSWORD_CHANGE_ROUTE),
+        (None, Some(_)) => false,
+        (None, None) => true,
+    }
+}
+
+/// An API key carries its own scopes; when bound to a group it never gets more
+/// than what the members of that group are granted.
+async fn authorize_api_key(data: &AppState, key: &str) -> Result<(User, Roles), AuthError> {
+    let api_key = find_api_key(data.api_keys.as_ref(), key)
+        .ok_or(AuthError::new(StatusCode::UNAUTHORIZED, "Invalid API key"))?;
+
+    let mut roles = Roles::from_roles(&api_key.scopes);
+    let mut groups = HashSet::new();
+
+    if let Some(group) = &api_key.group {
+        let ldap = data.ldap.lock().await;
+        if ldap.groups.group(group).await.is_none() {
+            return Err(AuthError::new(StatusCode::UNAUTHORIZED, "Service account group no longer exists"));
+        }
+        groups = ldap.groups.transitive_groups(&HashSet::from([group.clone()])).await;
+        roles = roles.intersection(&Roles::from_groups(&groups, &data.env));
+    }
+
+    Ok((User::service_account(api_key.uid(), groups), roles))
+}
+
+pub async fn require_role(role: Role, req: Request, next: Next) -> Result<Response<Body>, AuthError> {
+    let allowed = req.extensions().get::<Roles>().is_some_and(|roles| roles.has(role));
+
+    if !allowed {
+        return Err(AuthError::new(StatusCode::FORBIDDEN, &format!("Missing role: {}", role.as_str())));
+    }
+
+    Ok(next.run(req).await)
+}
 
 #[derive(Deserialize, ToSchema)]
 pub struct SignInData {
@@ -134,10 +288,181 @@ pub struct SignInData {
     pub password: String,
 }
 
+#[derive(Deserialize, ToSchema)]
+pub struct MfaData {
+    pub mfa_token: String,
+    pub code: String,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct MfaChallenge {
+    mfa_token: String,
+    token_type: String,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct RefreshData {
+    pub refresh_token: String,
+}
+
 #[derive(Debug, Serialize, ToSchema)]
 pub struct AuthBody {
     access_token: String,
     token_type: String,
+    refresh_token: String,
+}
+
+pub fn issue_tokens(data: &AppState, username: String, family: Option<String>) -> Result<AuthBody, AuthError> {
+    let access_token = encode_jwt(username.clone(), &data.env, &data.keys)
+        .map_err(|_| AuthError::new(StatusCode::INTERNAL_SERVER_ERROR, "Token creation error"))?;
+    let refresh_token = issue_refresh_token(data.refresh.as_ref(), username, family, data.env.refresh_maxage)
+        .map_err(|e| {
+            tracing::debug!("🔥 Failed to persist a refresh token: {:?}", e);
+            AuthError::new(StatusCode::INTERNAL_SERVER_ERROR, "Token creation error")
+        })?;
+
+    Ok(AuthBody {
+        access_token,
+        token_type: "Bearer".to_string(),
+        refresh_token,
+    })
+}
+
+pub struct Credentials {
+    pub user: User,
+    pub must_change_password: bool,
+}
+
+/// Check a password, refusing the accounts that have to change it first.
+pub async fn authenticate(data: &AppState, username: &str, password: &str, ip: Option<&str>) -> Result<User, AuthError> {
+    let credentials = verify_credentials(data, username, password, ip).await?;
+    if credentials.must_change_password {
+        return Err(AuthError::new(StatusCode::FORBIDDEN, "Password must be changed"));
+    }
+    Ok(credentials.user)
+}
+
+/// Runs a password or second-factor check, refused while `key` or `ip` has
+/// too many recent failures; wrong credentials count as one more failure.
+pub async fn throttled<T>(
+    throttle: &LoginThrottle,
+    key: &str,
+    ip: Option<&str>,
+    check: impl Future<Output = Result<T, AuthError>>
+) -> Result<T, AuthError> {
+    if let Some(retry_after) = throttle.retry_after(key, ip) {
+        return Err(too_many_attempts(retry_after));
+    }
+
+    let result = check.await;
+    match &result {
+        Ok(_) => throttle.success(key),
+        Err(e) if e.status_code == StatusCode::UNAUTHORIZED => throttle.failure(key, ip),
+        Err(_) => {}
+    }
+    result
+}
+
+pub async fn verify_credentials(data: &AppState, username: &str, password: &str, ip: Option<&str>) -> Result<Credentials, AuthError> {
+    throttled(&data.throttle, username, ip, check_credentials(data, username, password)).await
+}
+
+async fn check_credentials(data: &AppState, username: &str, password: &str) -> Result<Credentials, AuthError> {
+    let binder = data.ldap.lock().await.users.binder();
+
+    // ppolicy only reports an expired or reset password when the password is right
+    let (use_hash, must_change_password) = match data.env.auth_mode {
+        AuthMode::Hash => (true, false),
+        mode => match binder.bind(username, password).await {
+            Ok(BindStatus::Success) => (false, false),
+            Ok(BindStatus::InvalidCredentials) => return Err(AuthError::new(StatusCode::UNAUTHORIZED, "Wrong credentials")),
+            Ok(BindStatus::AccountLocked) => return Err(AuthError::new(StatusCode::LOCKED, "Account is locked")),
+            Ok(BindStatus::PasswordExpired | BindStatus::MustChangePassword) => (false, true),
+            Ok(BindStatus::Failed(rc)) => {
+                tracing::debug!("🔥 LDAP bind for {} failed with result code {}", username, rc);
+                return Err(AuthError::new(StatusCode::INTERNAL_SERVER_ERROR, "Unable to authenticate"));
+            }
+            Err(e) if mode == AuthMode::BindWithHashFallback => {
+                tracing::debug!("🔥 LDAP bind unavailable, falling back to hash comparison: {:?}", e);
+                (true, false)
+            }
+            Err(e) => {
+                tracing::debug!("🔥 LDAP bind unavailable: {:?}", e);
+                return Err(AuthError::new(StatusCode::INTERNAL_SERVER_ERROR, "Unable to authenticate"));
+            }
+        },
+    };
+
+    let mut ldap = data.ldap.lock().await;
+    if !use_hash && ldap.users.user_ignore_case(username).await.is_none() {
+        let _ = ldap.users.update_user(username).await;
+    }
+
+    // The directory matches the uid whatever its case, the cached user holds the canonical one
+    let user = match ldap.users.user_ignore_case(username).await {
+        Some(user) => user,
+        None => return Err(AuthError::new(StatusCode::UNAUTHORIZED, "Wrong credentials")),
+    };
+
+    let plaintext = data.env.plaintext_passwords;
+
+    if use_hash {
+        match user.verify_password(password, plaintext) {
+            Ok(true) if !password.is_empty() => {}
+            Ok(_) => return Err(AuthError::new(StatusCode::UNAUTHORIZED, "Wrong credentials")),
+            Err(e) => {
+                tracing::debug!("🔥 Unable to verify the password of {}: {}", username, e);
+                return Err(AuthError::new(StatusCode::UNAUTHORIZED, "Wrong credentials"));
+            }
+        }
+    } else if plaintext == PlaintextPolicy::Reject && !user.password.is_empty() && Password::check(&user.password) == Err(PasswordError::Plaintext) {
+        tracing::debug!("🔥 Refusing {}, the password is stored in plaintext", username);
+        return Err(AuthError::new(StatusCode::UNAUTHORIZED, "Wrong credentials"));
+    }
+
+    // The password is known to be right here, move the account to the configured scheme
+    let rehash = data.env.password_rehash || Password::scheme(&user.password).is_none();
+    if rehash && Password::needs_rehash(&user.password, data.env.password_hash, plaintext) {
+        let modification = ModifyUser::new().password_hash(password.to_string(), data.env.password_hash);
+        if let Err(e) = ldap.users.modify_user(&user.uid, modification).await {
+            tracing::debug!("🔥 Failed to rehash the password of {}: {:?}", username, e);
+        }
+    }
+
+    Ok(Credentials {
+        must_change_password: must_change_password || data.password_expiry.change_required(&user, Utc::now()),
+        user,
+    })
+}
+
+fn has_scope(claims: &Cliams, scope: &str) -> bool {
+    claims.scope.as_deref().is_some_and(|s| s.split_whitespace().any(|s| s == scope))
+}
+
+/// The only token given to an account that has to change its password.
+fn require_password_change(data: &AppState, username: String) -> Result<Response<Body>, AuthError> {
+    let token = encode_scoped_jwt(username, Some(PASSWORD_CHANGE_SCOPE), PASSWORD_CHANGE_MAXAGE, &data.keys)
+        .map_err(|_| AuthError::new(StatusCode::INTERNAL_SERVER_ERROR, "Token creation error"))?;
+
+    Err(AuthError::new(StatusCode::FORBIDDEN, "Password must be changed")
+        .detail("password_change_token", json!(token))
+        .detail("token_type", json!("Bearer")))
+}
+
+/// The peer address, or the header set by the reverse proxy when configured.
+pub fn client_ip(data: &AppState, headers: &HeaderMap, connect_info: Option<ConnectInfo<SocketAddr>>) -> Option<String> {
+    if let Some(header) = &data.env.client_ip_header {
+        return headers
+            .get(header.as_str())
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| forwarded_client_ip(value, data.env.client_ip_proxies));
+    }
+
+    connect_info.map(|ConnectInfo(addr)| addr.ip().to_string())
+}
+
+fn too_many_attempts(retry_after: i64) -> AuthError {
+    AuthError::new(StatusCode::TOO_MANY_REQUESTS, "Too many failed attempts, try again later").retry_after(retry_after)
 }
 
 #[utoipa::path(
@@ -145,31 +470,277 @@ pub struct AuthBody {
     path = "/api/auth/login",
     request_body = SignInData,
     responses(
-        (status = 401, description = "Unauthorized"),
-        (status = 200, description = "Success", body = AuthBody)
+        (status = 401, description = "Wrong credentials"),
+        (status = 403, description = "Password has expired or must be changed, with a password_change_token only valid on the change-password route"),
+        (status = 423, description = "Account is locked"),
+        (status = 429, description = "Too many failed attempts, see the Retry-After header"),
+        (status = 200, description = "Success", body = AuthBody),
+        (status = 202, description = "Two-factor code required", body = MfaChallenge)
     )
 )]
 pub async fn sign_in(
     State(data): State<AppState>,
+    headers: HeaderMap,
+    connect_info: Option<ConnectInfo<SocketAddr>>,
     Json(user_data): Json<SignInData>
-) -> Result<Json<AuthBody>, StatusCode> {
-    let user = match data.ldap.lock().await.users.user(&user_data.username).await {
+) -> Result<Response<Body>, AuthError> {
+    let ip = client_ip(&data, &headers, connect_info);
+    let credentials = verify_credentials(&data, &user_data.username, &user_data.password, ip.as_deref()).await?;
+    let user = credentials.user;
+
+    if mfa::user_totp(&data, &user).is_some() {
+        // the password change comes after the second factor
+        let scope = match credentials.must_change_password {
+            true => format!("{} {}", MFA_SCOPE, PASSWORD_CHANGE_SCOPE),
+            false => MFA_SCOPE.to_string(),
+        };
+        let mfa_token = encode_scoped_jwt(user.uid, Some(&scope), MFA_MAXAGE, &data.keys)
+            .map_err(|_| AuthError::new(StatusCode::INTERNAL_SERVER_ERROR, "Token creation error"))?;
+
+        let challenge = MfaChallenge {
+            mfa_token,
+            token_type: "MFA".to_string(),
+        };
+        return Ok((StatusCode::ACCEPTED, Json(challenge)).into_response());
+    }
+
+    if credentials.must_change_password {
+        return require_password_change(&data, user.uid);
+    }
+
+    let auth = issue_tokens(&data, user.uid, None)?;
+
+    Ok(Json(auth).into_response())
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/auth/login/mfa",
+    request_body = MfaData,
+    responses(
+        (status = 401, description = "Invalid token or two-factor code"),
+        (status = 403, description = "Password must be changed, with a password_change_token only valid on the change-password route"),
+        (status = 429, description = "Too many failed attempts, see the Retry-After header"),
+        (status = 200, description = "Success", body = AuthBody)
+    )
+)]
+pub async fn sign_in_mfa(
+    State(data): State<AppState>,
+    headers: HeaderMap,
+    connect_info: Option<ConnectInfo<SocketAddr>>,
+    Json(mfa_data): Json<MfaData>
+) -> Result<Response<Body>, AuthError> {
+    let claims = match decode_jwt(mfa_data.mfa_token, &data.keys) {
+        Ok(token_data) => token_data.claims,
+        Err(_) => return Err(AuthError::new(StatusCode::UNAUTHORIZED, "Unable to decode token")),
+    };
+
+    if !has_scope(&claims, MFA_SCOPE) || data.denylist.is_revoked(&claims.jti, &claims.username, claims.issued_at()) {
+        return Err(AuthError::new(StatusCode::UNAUTHORIZED, "Invalid token"));
+    }
+
+    let user = match data.ldap.lock().await.users.user(&claims.username).await {
         Some(user) => user,
-        None => return Err(StatusCode::UNAUTHORIZED),
+        None => return Err(AuthError::new(StatusCode::UNAUTHORIZED, "You are not an authorized user")),
     };
 
-    if !user.verify_password(&user_data.password)
-    {
-        return Err(StatusCode::UNAUTHORIZED);
+    let ip = client_ip(&data, &headers, connect_info);
+    if let Err(e) = mfa::verify_second_factor(&data, &user, &mfa_data.code, ip.as_deref()).await {
+        if e.status_code == StatusCode::UNAUTHORIZED && data.totp.failure(&claims.jti, claims.exp) >= MFA_MAX_FAILURES {
+            data.totp.clear_failures(&claims.jti);
+            data.denylist.revoke_token(claims.jti, claims.exp).map_err(revocation_failed)?;
+            return Err(AuthError::new(StatusCode::UNAUTHORIZED, "Too many invalid codes, sign in again"));
+        }
+        return Err(e);
+    }
+    data.totp.clear_failures(&claims.jti);
+    data.denylist.revoke_token(claims.jti.clone(), claims.exp).map_err(revocation_failed)?;
+
+    if has_scope(&claims, PASSWORD_CHANGE_SCOPE) {
+        return require_password_change(&data, user.uid);
     }
 
-    let token = encode_jwt(user.uid, data.env)
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let auth = issue_tokens(&data, user.uid, None)?;
 
-    let auth = AuthBody {
-        access_token: token,
-        token_type: "Bearer".to_string(),
+    Ok(Json(auth).into_response())
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/auth/refresh",
+    request_body = RefreshData,
+    responses(
+        (status = 401, description = "Invalid, expired or reused refresh token"),
+        (status = 200, description = "Success", body = AuthBody)
+    )
+)]
+pub async fn refresh(
+    State(data): State<AppState>,
+    Json(refresh_data): Json<RefreshData>
+) -> Result<Json<AuthBody>, AuthError> {
+    let record = match consume_refresh_token(data.refresh.as_ref(), &refresh_data.refresh_token) {
+        Ok(record) => record,
+        Err(RefreshError::Invalid) => return Err(AuthError::new(StatusCode::UNAUTHORIZED, "Invalid refresh token")),
+        Err(RefreshError::Expired) => return Err(AuthError::new(StatusCode::UNAUTHORIZED, "Refresh token has expired")),
+        Err(RefreshError::Reused) => {
+            tracing::debug!("🔥 Refresh token reused, revoking the session");
+            return Err(AuthError::new(StatusCode::UNAUTHORIZED, "Refresh token has already been used"));
+        }
+        Err(RefreshError::Store(e)) => return Err(revocation_failed(e)),
     };
 
+    if data.ldap.lock().await.users.user(&record.username).await.is_none() {
+        data.refresh.revoke_family(&record.family).map_err(revocation_failed)?;
+        return Err(AuthError::new(StatusCode::UNAUTHORIZED, "You are not an authorized user"));
+    }
+
+    let auth = issue_tokens(&data, record.username, Some(record.family))?;
+
     Ok(Json(auth))
-}
\ No newline at end of file
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/auth/logout",
+    request_body(content = Option<RefreshData>, description = "Refresh token to revoke along with the access token"),
+    responses(
+        (status = 204, description = "Success"),
+        (status = 401, description = "Unauthorized")
+    ),
+    security(
+        ("jwt" = [])
+    )
+)]
+pub async fn logout(
+    State(data): State<AppState>,
+    Extension(claims): Extension<Cliams>,
+    refresh_data: Option<Json<RefreshData>>
+) -> StatusCode {
+    if data.denylist.revoke_token(claims.jti, claims.exp).map_err(revocation_failed).is_err() {
+        return StatusCode::INTERNAL_SERVER_ERROR;
+    }
+
+    if let Some(Json(refresh_data)) = refresh_data {
+        let revoked = match consume_refresh_token(data.refresh.as_ref(), &refresh_data.refresh_token) {
+            Ok(record) if record.username == claims.username => data.refresh.revoke_family(&record.family),
+            Err(RefreshError::Store(e)) => Err(e),
+            _ => Ok(()),
+        };
+        if revoked.map_err(revocation_failed).is_err() {
+            return StatusCode::INTERNAL_SERVER_ERROR;
+        }
+    }
+
+    StatusCode::NO_CONTENT
+}
+
+#[utoipa::path(
+    get,
+    path = "/.well-known/jwks.json",
+    responses(
+        (status = 200, description = "Public keys used to sign the tokens")
+    )
+)]
+pub async fn jwks(State(data): State<AppState>) -> Json<JwkSet> {
+    Json(data.keys.jwks().clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    use axum::http::StatusCode;
+
+    use crate::common::throttle::LoginThrottle;
+
+    use super::{opens_route, throttled, AuthError, Cliams, PASSWORD_CHANGE_SCOPE};
+
+    async fn check(throttle: &LoginThrottle, username: &str, ip: &str, status: Option<StatusCode>) -> Result<(), AuthError> {
+        throttled(throttle, username, Some(ip), async {
+            match status {
+                Some(status) => Err(AuthError::new(status, "Wrong credentials")),
+                None => Ok(()),
+            }
+        })
+        .await
+    }
+
+    #[tokio::test]
+    async fn wrong_credentials_delay_the_next_attempt() {
+        let throttle = LoginThrottle::new(5, 20, 900);
+        let checked = AtomicBool::new(false);
+
+        let e = check(&throttle, "alice", "10.0.0.1", Some(StatusCode::UNAUTHORIZED)).await.unwrap_err();
+        assert_eq!(e.status_code, StatusCode::UNAUTHORIZED);
+
+        let e = throttled(&throttle, "Alice", Some("10.0.0.2"), async {
+            checked.store(true, Ordering::SeqCst);
+            Ok(())
+        })
+        .await
+        .unwrap_err();
+        assert_eq!(e.status_code, StatusCode::TOO_MANY_REQUESTS);
+        assert!(e.retry_after.is_some_and(|s| s > 0));
+        assert!(!checked.load(Ordering::SeqCst));
+    }
+
+    #[tokio::test]
+    async fn only_wrong_credentials_count_as_failures() {
+        let throttle = LoginThrottle::new(5, 20, 900);
+
+        check(&throttle, "alice", "10.0.0.1", Some(StatusCode::LOCKED)).await.unwrap_err();
+        check(&throttle, "alice", "10.0.0.1", Some(StatusCode::INTERNAL_SERVER_ERROR)).await.unwrap_err();
+        assert!(check(&throttle, "alice", "10.0.0.1", None).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn a_throttled_address_is_refused_for_every_username() {
+        let throttle = LoginThrottle::new(5, 1, 900);
+
+        check(&throttle, "alice", "10.0.0.1", Some(StatusCode::UNAUTHORIZED)).await.unwrap_err();
+        let e = check(&throttle, "bob", "10.0.0.1", None).await.unwrap_err();
+        assert_eq!(e.status_code, StatusCode::TOO_MANY_REQUESTS);
+        assert!(check(&throttle, "bob", "10.0.0.2", None).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn second_factor_failures_are_counted_apart() {
+        let throttle = LoginThrottle::new(5, 20, 900);
+        let mfa = LoginThrottle::second_factor_key("alice");
+
+        check(&throttle, &mfa, "10.0.0.1", Some(StatusCode::UNAUTHORIZED)).await.unwrap_err();
+        assert!(check(&throttle, "alice", "10.0.0.2", None).await.is_ok());
+        let e = check(&throttle, &mfa, "10.0.0.2", None).await.unwrap_err();
+        assert_eq!(e.status_code, StatusCode::TOO_MANY_REQUESTS);
+    }
+
+    fn claims(scope: Option<&str>, client_id: Option<&str>) -> Cliams {
+        Cliams {
+            exp: 0,
+            iat: 0,
+            iat_ms: None,
+            jti: String::new(),
+            username: "alice".to_string(),
+            scope: scope.map(|s| s.to_string()),
+            client_id: client_id.map(|c| c.to_string()),
+        }
+    }
+
+    #[test]
+    fn restricted_tokens_only_open_their_route() {
+        let session = claims(None, None);
+        assert!(opens_route(&session, "/api/users/"));
+        assert!(opens_route(&session, "/api/oidc/userinfo"));
+
+        let oidc = claims(Some("openid profile"), Some("wiki"));
+        assert!(opens_route(&oidc, "/api/oidc/userinfo"));
+        assert!(!opens_route(&oidc, "/api/users/"));
+        assert!(!opens_route(&oidc, "/api/protected/admin/sessions"));
+        assert!(!opens_route(&claims(None, Some("wiki")), "/api/me"));
+
+        let password_change = claims(Some(PASSWORD_CHANGE_SCOPE), None);
+        assert!(opens_route(&password_change, "/api/protected/user/password"));
+        assert!(!opens_route(&password_change, "/api/oidc/userinfo"));
+        assert!(!opens_route(&claims(Some("mfa"), None), "/api/users/"));
+    }
+}
@@ -0,0 +1,273 @@
+use std::collections::HashSet;
+
+use axum::{
+    extract::{Json, Path, State}, http::StatusCode, Extension
+};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::common::{group::Group, role::{Role, Roles}, user::User};
+
+use super::{auth::AuthError, AppState};
+
+#[derive(Serialize, ToSchema)]
+pub struct GroupResponse {
+    cn: String,
+    dn: String,
+    parents: Vec<String>,
+    members: Vec<String>,
+    subgroups: Vec<String>,
+    owner_users: Vec<String>,
+    owner_groups: Vec<String>,
+}
+
+impl From<Group> for GroupResponse {
+    fn from(group: Group) -> Self {
+        let sorted = |set: HashSet<String>| {
+            let mut list: Vec<String> = set.into_iter().collect();
+            list.sort();
+            list
+        };
+
+        Self {
+            cn: group.cn,
+            dn: group.dn,
+            parents: sorted(group.parents),
+            members: sorted(group.user_members),
+            subgroups: sorted(group.group_members),
+            owner_users: sorted(group.owner_user),
+            owner_groups: sorted(group.owner_group),
+        }
+    }
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct MemberData {
+    pub uid: String,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct SubgroupData {
+    pub cn: String,
+}
+
+/// Admins manage every group, team leads only the groups they own.
+async fn require_manager(data: &AppState, user: &User, roles: &Roles, cn: &str) -> Result<(), AuthError> {
+    let ldap = data.ldap.lock().await;
+    if ldap.groups.group(cn).await.is_none() {
+        return Err(AuthError::new(StatusCode::NOT_FOUND, "Group not found"));
+    }
+
+    if roles.has(Role::Admin) || (roles.has(Role::TeamLead) && ldap.groups.can_manage(user, cn).await) {
+        return Ok(());
+    }
+
+    Err(AuthError::new(StatusCode::FORBIDDEN, "You do not manage this group"))
+}
+
+fn user_dn(data: &AppState, uid: &str) -> String {
+    format!("uid={},{}", ldap3::dn_escape(uid), data.env.ldap_users_base_dn)
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/protected/groups",
+    responses(
+        (status = 200, description = "Success", body = [GroupResponse]),
+        (status = 401, description = "Unauthorized")
+    ),
+    security(
+        ("jwt" = [])
+    )
+)]
+pub async fn get_groups(State(data): State<AppState>) -> Json<Vec<GroupResponse>> {
+    let mut groups = data.ldap.lock().await.groups.to_vec().await;
+    groups.sort_by(|a, b| a.cn.cmp(&b.cn));
+    Json(groups.into_iter().map(GroupResponse::from).collect())
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/protected/groups/{cn}",
+    params(
+        ("cn" = String, Path, description = "Group name")
+    ),
+    responses(
+        (status = 200, description = "Success", body = GroupResponse),
+        (status = 401, description = "Unauthorized"),
+        (status = 404, description = "Group not found")
+    ),
+    security(
+        ("jwt" = [])
+    )
+)]
+pub async fn get_group(State(data): State<AppState>, Path(cn): Path<String>) -> Result<Json<GroupResponse>, AuthError> {
+    match data.ldap.lock().await.groups.group(&cn).await {
+        Some(group) => Ok(Json(group.into())),
+        None => Err(AuthError::new(StatusCode::NOT_FOUND, "Group not found")),
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/protected/groups/{cn}/members",
+    params(
+        ("cn" = String, Path, description = "Group name")
+    ),
+    request_body = MemberData,
+    responses(
+        (status = 200, description = "Success", body = GroupResponse),
+        (status = 403, description = "You do not manage this group"),
+        (status = 404, description = "Group or user not found"),
+        (status = 409, description = "Unable to add the member")
+    ),
+    security(
+        ("jwt" = [])
+    )
+)]
+pub async fn add_member(
+    State(data): State<AppState>,
+    Extension(user): Extension<User>,
+    Extension(roles): Extension<Roles>,
+    Path(cn): Path<String>,
+    Json(member): Json<MemberData>
+) -> Result<Json<GroupResponse>, AuthError> {
+    require_manager(&data, &user, &roles, &cn).await?;
+
+    let mut ldap = data.ldap.lock().await;
+    if ldap.users.user(&member.uid).await.is_none() {
+        return Err(AuthError::new(StatusCode::NOT_FOUND, "User not found"));
+    }
+
+    change_members(ldap.groups.add_member(&cn, &user_dn(&data, &member.uid)).await, "Unable to add the member")?;
+    let _ = ldap.users.update_user(&member.uid).await;
+
+    group_response(ldap.groups.group(&cn).await)
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/protected/groups/{cn}/members/{uid}",
+    params(
+        ("cn" = String, Path, description = "Group name"),
+        ("uid" = String, Path, description = "Member to remove")
+    ),
+    responses(
+        (status = 200, description = "Success", body = GroupResponse),
+        (status = 403, description = "You do not manage this group"),
+        (status = 404, description = "Group not found"),
+        (status = 409, description = "Unable to remove the member")
+    ),
+    security(
+        ("jwt" = [])
+    )
+)]
+pub async fn remove_member(
+    State(data): State<AppState>,
+    Extension(user): Extension<User>,
+    Extension(roles): Extension<Roles>,
+    Path((cn, uid)): Path<(String, String)>
+) -> Result<Json<GroupResponse>, AuthError> {
+    require_manager(&data, &user, &roles, &cn).await?;
+
+    let mut ldap = data.ldap.lock().await;
+    change_members(ldap.groups.remove_member(&cn, &user_dn(&data, &uid)).await, "Unable to remove the member")?;
+    let _ = ldap.users.update_user(&uid).await;
+
+    group_response(ldap.groups.group(&cn).await)
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/protected/groups/{cn}/subgroups",
+    params(
+        ("cn" = String, Path, description = "Group name")
+    ),
+    request_body = SubgroupData,
+    responses(
+        (status = 200, description = "Success", body = GroupResponse),
+        (status = 403, description = "You do not manage this group"),
+        (status = 404, description = "Group not found"),
+        (status = 409, description = "Unable to add the sub-group")
+    ),
+    security(
+        ("jwt" = [])
+    )
+)]
+pub async fn add_subgroup(
+    State(data): State<AppState>,
+    Extension(user): Extension<User>,
+    Extension(roles): Extension<Roles>,
+    Path(cn): Path<String>,
+    Json(subgroup): Json<SubgroupData>
+) -> Result<Json<GroupResponse>, AuthError> {
+    require_manager(&data, &user, &roles, &cn).await?;
+
+    let mut ldap = data.ldap.lock().await;
+    let subgroup = ldap
+        .groups
+        .group(&subgroup.cn)
+        .await
+        .ok_or(AuthError::new(StatusCode::NOT_FOUND, "Group not found"))?;
+
+    let ancestors = ldap.groups.transitive_groups(&HashSet::from([cn.clone()])).await;
+    if ancestors.contains(&subgroup.cn) {
+        return Err(AuthError::new(StatusCode::CONFLICT, "A group cannot contain one of its parents"));
+    }
+
+    change_members(ldap.groups.add_member(&cn, &subgroup.dn).await, "Unable to add the sub-group")?;
+
+    group_response(ldap.groups.group(&cn).await)
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/protected/groups/{cn}/subgroups/{subgroup}",
+    params(
+        ("cn" = String, Path, description = "Group name"),
+        ("subgroup" = String, Path, description = "Sub-group to remove")
+    ),
+    responses(
+        (status = 200, description = "Success", body = GroupResponse),
+        (status = 403, description = "You do not manage this group"),
+        (status = 404, description = "Group not found"),
+        (status = 409, description = "Unable to remove the sub-group")
+    ),
+    security(
+        ("jwt" = [])
+    )
+)]
+pub async fn remove_subgroup(
+    State(data): State<AppState>,
+    Extension(user): Extension<User>,
+    Extension(roles): Extension<Roles>,
+    Path((cn, subgroup)): Path<(String, String)>
+) -> Result<Json<GroupResponse>, AuthError> {
+    require_manager(&data, &user, &roles, &cn).await?;
+
+    let mut ldap = data.ldap.lock().await;
+    let subgroup = ldap
+        .groups
+        .group(&subgroup)
+        .await
+        .ok_or(AuthError::new(StatusCode::NOT_FOUND, "Group not found"))?;
+
+    change_members(ldap.groups.remove_member(&cn, &subgroup.dn).await, "Unable to remove the sub-group")?;
+
+    group_response(ldap.groups.group(&cn).await)
+}
+
+fn change_members(result: ldap3::result::Result<bool>, message: &str) -> Result<(), AuthError> {
+    match result {
+        Ok(true) => Ok(()),
+        Ok(false) => Err(AuthError::new(StatusCode::CONFLICT, message)),
+        Err(_) => Err(AuthError::new(StatusCode::INTERNAL_SERVER_ERROR, message)),
+    }
+}
+
+fn group_response(group: Option<Group>) -> Result<Json<GroupResponse>, AuthError> {
+    match group {
+        Some(group) => Ok(Json(group.into())),
+        None => Err(AuthError::new(StatusCode::NOT_FOUND, "Group not found")),
+    }
+}
@@ -0,0 +1,145 @@
+use axum::{
+    extract::{Json, State}, http::StatusCode, Extension
+};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::common::{
+    throttle::LoginThrottle,
+    token::hash_token,
+    totp::{find_recovery_code, generate_recovery_codes, normalize_recovery_code, Totp},
+    user::{ModifyUser, User},
+};
+
+use super::{auth::{self, AuthError}, AppState};
+
+const RECOVERY_CODES: usize = 10;
+
+#[derive(Serialize, ToSchema)]
+pub struct EnrollResponse {
+    secret: String,
+    otpauth_uri: String,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct ConfirmData {
+    pub code: String,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct RecoveryCodes {
+    recovery_codes: Vec<String>,
+}
+
+pub fn user_totp(data: &AppState, user: &User) -> Option<Totp> {
+    user.attribute(&data.env.mfa_secret_attribute)
+        .and_then(|secret| Totp::from_base32(&secret[0]))
+}
+
+/// Check a TOTP code, or consume a recovery code, for a user that enrolled.
+pub async fn verify_second_factor(data: &AppState, user: &User, code: &str, ip: Option<&str>) -> Result<(), AuthError> {
+    let key = LoginThrottle::second_factor_key(&user.uid);
+    auth::throttled(&data.throttle, &key, ip, check_second_factor(data, user, code)).await
+}
+
+async fn check_second_factor(data: &AppState, user: &User, code: &str) -> Result<(), AuthError> {
+    let totp = match user_totp(data, user) {
+        Some(totp) => totp,
+        None => return Ok(()),
+    };
+
+    if let Some(step) = totp.verify(code) {
+        if !data.totp.use_step(&user.uid, step) {
+            return Err(AuthError::new(StatusCode::UNAUTHORIZED, "Two-factor code has already been used"));
+        }
+        return Ok(());
+    }
+
+    let invalid = || AuthError::new(StatusCode::UNAUTHORIZED, "Invalid two-factor code");
+    let codes = user.attribute(&data.env.mfa_recovery_attribute).cloned().unwrap_or_default();
+    let hash = find_recovery_code(&codes, code).ok_or_else(invalid)?;
+
+    // Only this value is deleted, from the entry read again under the lock: the
+    // directory refuses the delete when another request consumed the code first.
+    let modification = ModifyUser::new().remove_values(data.env.mfa_recovery_attribute.clone(), vec![hash.clone()]);
+    match data.ldap.lock().await.users.modify_user(&user.uid, modification).await {
+        Ok(true) => Ok(()),
+        Ok(false) => Err(invalid()),
+        Err(e) => {
+            tracing::debug!("🔥 Unable to consume a recovery code of {}: {:?}", user.uid, e);
+            Err(AuthError::new(StatusCode::INTERNAL_SERVER_ERROR, "Unable to consume the recovery code"))
+        }
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/protected/mfa/enroll",
+    responses(
+        (status = 200, description = "Secret to add to an authenticator app", body = EnrollResponse),
+        (status = 401, description = "Unauthorized"),
+        (status = 409, description = "Two-factor authentication is already enabled")
+    ),
+    security(
+        ("jwt" = [])
+    )
+)]
+pub async fn enroll(State(data): State<AppState>, Extension(user): Extension<User>) -> Result<Json<EnrollResponse>, AuthError> {
+    if user_totp(&data, &user).is_some() {
+        return Err(AuthError::new(StatusCode::CONFLICT, "Two-factor authentication is already enabled"));
+    }
+
+    let totp = Totp::generate();
+    let secret = totp.to_base32();
+    data.totp.set_pending(user.uid.clone(), secret.clone());
+
+    Ok(Json(EnrollResponse {
+        otpauth_uri: totp.uri(&data.env.mfa_issuer, &user.uid),
+        secret,
+    }))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/protected/mfa/confirm",
+    request_body = ConfirmData,
+    responses(
+        (status = 200, description = "Two-factor authentication enabled, the recovery codes are only shown once", body = RecoveryCodes),
+        (status = 400, description = "No enrolment in progress"),
+        (status = 401, description = "Invalid two-factor code")
+    ),
+    security(
+        ("jwt" = [])
+    )
+)]
+pub async fn confirm(
+    State(data): State<AppState>,
+    Extension(user): Extension<User>,
+    Json(confirm_data): Json<ConfirmData>
+) -> Result<Json<RecoveryCodes>, AuthError> {
+    let secret = data
+        .totp
+        .pending(&user.uid)
+        .ok_or(AuthError::new(StatusCode::BAD_REQUEST, "No two-factor enrolment in progress"))?;
+
+    let step = Totp::from_base32(&secret)
+        .and_then(|totp| totp.verify(&confirm_data.code))
+        .ok_or(AuthError::new(StatusCode::UNAUTHORIZED, "Invalid two-factor code"))?;
+
+    let recovery_codes = generate_recovery_codes(RECOVERY_CODES);
+    let hashes = recovery_codes.iter().map(|c| hash_token(&normalize_recovery_code(c))).collect();
+
+    let modification = ModifyUser::new()
+        .attribute(data.env.mfa_secret_attribute.clone(), vec![secret])
+        .attribute(data.env.mfa_recovery_attribute.clone(), hashes);
+
+    match data.ldap.lock().await.users.modify_user(&user.uid, modification).await {
+        Ok(true) => {}
+        _ => return Err(AuthError::new(StatusCode::INTERNAL_SERVER_ERROR, "Unable to store the two-factor secret")),
+    }
+
+    data.totp.remove_pending(&user.uid);
+    data.totp.use_step(&user.uid, step);
+
+    Ok(Json(RecoveryCodes { recovery_codes }))
+}
@@ -1,6 +1,12 @@
 pub mod route;
 pub mod state;
 pub mod auth;
+pub mod admin;
+pub mod oidc;
+pub mod mfa;
+pub mod group;
+pub mod password;
+pub mod user;
 
 pub use route::create_router;
 pub use state::AppState;
\ No newline at end of file
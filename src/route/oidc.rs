@@ -0,0 +1,460 @@
+use std::net::SocketAddr;
+
+use axum::{
+    extract::{ConnectInfo, Query, State}, http::{header, HeaderMap, StatusCode}, response::{Html, IntoResponse, Redirect, Response}, Extension, Form, Json
+};
+use base64::prelude::*;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use subtle::ConstantTimeEq;
+use utoipa::IntoParams;
+
+use crate::common::{oidc::OidcClient, token::generate_token, user::User};
+
+use super::{auth::{self, encode_oidc_jwt, Cliams}, mfa, route, AppState};
+
+/// Cookie holding the CSRF token of the login form, sent back with the form.
+const CSRF_COOKIE: &str = "oidc_csrf";
+
+#[derive(Debug, Clone, Deserialize, Serialize, IntoParams)]
+pub struct AuthorizeParams {
+    pub response_type: String,
+    pub client_id: String,
+    pub redirect_uri: String,
+    pub scope: String,
+    pub state: Option<String>,
+    pub nonce: Option<String>,
+    pub code_challenge: Option<String>,
+    pub code_challenge_method: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct AuthorizeForm {
+    #[serde(flatten)]
+    pub params: AuthorizeParams,
+    pub username: String,
+    pub password: String,
+    pub otp: Option<String>,
+    #[serde(default)]
+    pub csrf_token: String,
+}
+
+#[derive(Deserialize)]
+pub struct TokenForm {
+    pub grant_type: String,
+    pub code: String,
+    pub redirect_uri: String,
+    pub client_id: Option<String>,
+    pub client_secret: Option<String>,
+    pub code_verifier: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct TokenResponse {
+    access_token: String,
+    token_type: String,
+    expires_in: i64,
+    id_token: String,
+}
+
+#[derive(Serialize)]
+struct IdTokenClaims {
+    iss: String,
+    sub: String,
+    aud: String,
+    exp: usize,
+    iat: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    nonce: Option<String>,
+    #[serde(flatten)]
+    profile: Value,
+}
+
+pub struct OidcError {
+    error: &'static str,
+    description: &'static str,
+    status_code: StatusCode,
+}
+
+impl OidcError {
+    fn new(status_code: StatusCode, error: &'static str, description: &'static str) -> Self {
+        Self {
+            error,
+            description,
+            status_code,
+        }
+    }
+}
+
+impl IntoResponse for OidcError {
+    fn into_response(self) -> Response {
+        let body = Json(json!({
+            "error": self.error,
+            "error_description": self.description,
+        }));
+
+        (self.status_code, body).into_response()
+    }
+}
+
+/// Claims describing `user`, filtered by the requested scopes.
+fn user_claims(user: &User, scope: &str) -> Value {
+    let scopes: Vec<&str> = scope.split_whitespace().collect();
+    let mut claims = json!({});
+
+    if scopes.contains(&"profile") {
+        claims["name"] = json!(user.name);
+        claims["given_name"] = json!(user.first_name);
+        claims["family_name"] = json!(user.last_name);
+        claims["preferred_username"] = json!(user.uid);
+    }
+    if scopes.contains(&"email") {
+        claims["email"] = json!(user.primary_mail());
+    }
+    if scopes.contains(&"groups") {
+        let mut groups: Vec<&String> = user.member.iter().flatten().collect();
+        groups.sort();
+        claims["groups"] = json!(groups);
+    }
+
+    claims
+}
+
+fn find_client<'a>(data: &'a AppState, client_id: &str) -> Option<&'a OidcClient> {
+    data.oidc_clients.iter().find(|c| c.client_id == client_id)
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+/// The login form, with a fresh CSRF token in a hidden field and in a cookie.
+/// It is never shown in a frame.
+fn login_page(data: &AppState, status: StatusCode, params: &AuthorizeParams, error: Option<&str>) -> Response {
+    let csrf_token = generate_token();
+    let hidden: String = serde_json::to_value(params)
+        .unwrap()
+        .as_object()
+        .unwrap()
+        .iter()
+        .filter_map(|(name, value)| value.as_str().map(|v| (name.as_str(), v)))
+        .chain([("csrf_token", csrf_token.as_str())])
+        .map(|(name, value)| format!(r#"<input type="hidden" name="{}" value="{}">"#, name, escape(value)))
+        .collect();
+    let error = error.map(|e| format!("<p>{}</p>", escape(e))).unwrap_or_default();
+
+    let secure = if data.env.oidc_issuer.starts_with("https://") { "; Secure" } else { "" };
+    let cookie = format!("{}={}; Path={}/authorize; HttpOnly; SameSite=Strict{}", CSRF_COOKIE, csrf_token, route::OIDC_PATH, secure);
+    let headers = [
+        (header::SET_COOKIE, cookie),
+        (header::X_FRAME_OPTIONS, "DENY".to_string()),
+        (header::CONTENT_SECURITY_POLICY, "frame-ancestors 'none'".to_string()),
+        (header::CACHE_CONTROL, "no-store".to_string()),
+    ];
+
+    let page = Html(format!(
+        r#"<!DOCTYPE html>
+<html>
+<head><meta charset="utf-8"><title>Polyorbite</title></head>
+<body>
+<h1>Polyorbite</h1>
+{}
+<form method="post">
+{}
+<label>Username <input name="username" autocomplete="username" required></label>
+<label>Password <input name="password" type="password" autocomplete="current-password" required></label>
+<label>Two-factor code <input name="otp" autocomplete="one-time-code"></label>
+<button type="submit">Sign in</button>
+</form>
+</body>
+</html>"#,
+        error, hidden
+    ));
+
+    (status, headers, page).into_response()
+}
+
+/// Whether the form carries the CSRF token of the cookie set with the login page.
+fn csrf_matches(headers: &HeaderMap, csrf_token: &str) -> bool {
+    let cookie = headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|h| h.to_str().ok())
+        .flat_map(|h| h.split(';'))
+        .filter_map(|c| c.trim().split_once('='))
+        .find(|(name, _)| *name == CSRF_COOKIE)
+        .map(|(_, value)| value);
+
+    match cookie {
+        Some(cookie) => !csrf_token.is_empty() && bool::from(cookie.as_bytes().ct_eq(csrf_token.as_bytes())),
+        None => false,
+    }
+}
+
+fn redirect_with(redirect_uri: &str, params: &[(&str, &str)]) -> Response {
+    let query: String = params
+        .iter()
+        .map(|(k, v)| format!("{}={}", k, urlencoding::encode(v)))
+        .collect::<Vec<String>>()
+        .join("&");
+    let separator = if redirect_uri.contains('?') { '&' } else { '?' };
+    Redirect::to(&format!("{}{}{}", redirect_uri, separator, query)).into_response()
+}
+
+/// Check the request against the registered client. Errors that concern the
+/// client or its redirect URI are never redirected.
+fn authorize_error(data: &AppState, params: &AuthorizeParams) -> Option<Response> {
+    let client = match find_client(data, &params.client_id) {
+        Some(client) => client,
+        None => return Some(OidcError::new(StatusCode::BAD_REQUEST, "invalid_client", "Unknown client").into_response()),
+    };
+
+    if !client.allows_redirect(&params.redirect_uri) {
+        return Some(OidcError::new(StatusCode::BAD_REQUEST, "invalid_request", "Unregistered redirect_uri").into_response());
+    }
+
+    let state = params.state.as_deref().unwrap_or_default();
+    let error = if params.response_type != "code" {
+        Some("unsupported_response_type")
+    } else if !params.scope.split_whitespace().any(|s| s == "openid") {
+        Some("invalid_scope")
+    } else if params.code_challenge.is_some() && params.code_challenge_method.as_deref() != Some("S256")
+        || client.is_public() && params.code_challenge.is_none() {
+        Some("invalid_request")
+    } else {
+        None
+    };
+
+    error.map(|error| redirect_with(&params.redirect_uri, &[("error", error), ("state", state)]))
+}
+
+#[utoipa::path(
+    get,
+    path = "/.well-known/openid-configuration",
+    responses(
+        (status = 200, description = "OpenID Connect discovery document")
+    )
+)]
+pub async fn discovery(State(data): State<AppState>) -> Json<Value> {
+    let issuer = data.env.oidc_issuer.trim_end_matches('/');
+
+    Json(json!({
+        "issuer": issuer,
+        "authorization_endpoint": format!("{}/api/oidc/authorize", issuer),
+        "token_endpoint": format!("{}/api/oidc/token", issuer),
+        "userinfo_endpoint": format!("{}/api/oidc/userinfo", issuer),
+        "jwks_uri": format!("{}/.well-known/jwks.json", issuer),
+        "response_types_supported": ["code"],
+        "grant_types_supported": ["authorization_code"],
+        "subject_types_supported": ["public"],
+        "id_token_signing_alg_values_supported": [data.env.jwt_algorithm],
+        "scopes_supported": ["openid", "profile", "email", "groups"],
+        "token_endpoint_auth_methods_supported": ["client_secret_basic", "client_secret_post", "none"],
+        "code_challenge_methods_supported": ["S256"],
+        "claims_supported": ["sub", "name", "given_name", "family_name", "preferred_username", "email", "groups"],
+    }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/oidc/authorize",
+    params(AuthorizeParams),
+    responses(
+        (status = 200, description = "Login form"),
+        (status = 302, description = "Redirect to the client with an error"),
+        (status = 400, description = "Unknown client or redirect_uri")
+    )
+)]
+pub async fn authorize(State(data): State<AppState>, Query(params): Query<AuthorizeParams>) -> Response {
+    if let Some(response) = authorize_error(&data, &params) {
+        return response;
+    }
+
+    login_page(&data, StatusCode::OK, &params, None)
+}
+
+pub async fn authorize_submit(
+    State(data): State<AppState>,
+    headers: HeaderMap,
+    connect_info: Option<ConnectInfo<SocketAddr>>,
+    Form(form): Form<AuthorizeForm>
+) -> Response {
+    let params = form.params;
+    if let Some(response) = authorize_error(&data, &params) {
+        return response;
+    }
+
+    if !csrf_matches(&headers, &form.csrf_token) {
+        return login_page(&data, StatusCode::FORBIDDEN, &params, Some("The form has expired, please sign in again"));
+    }
+
+    let ip = auth::client_ip(&data, &headers, connect_info);
+    let user = match auth::authenticate(&data, &form.username, &form.password, ip.as_deref()).await {
+        Ok(user) => user,
+        Err(e) => return login_page(&data, StatusCode::UNAUTHORIZED, &params, Some(e.message())),
+    };
+
+    if mfa::user_totp(&data, &user).is_some() {
+        let otp = form.otp.unwrap_or_default();
+        if otp.is_empty() {
+            return login_page(&data, StatusCode::UNAUTHORIZED, &params, Some("Two-factor code required"));
+        }
+        if let Err(e) = mfa::verify_second_factor(&data, &user, &otp, ip.as_deref()).await {
+            return login_page(&data, StatusCode::UNAUTHORIZED, &params, Some(e.message()));
+        }
+    }
+
+    let code = data.oidc_codes.issue(
+        params.client_id,
+        params.redirect_uri.clone(),
+        user.uid,
+        params.scope,
+        params.nonce,
+        params.code_challenge,
+    );
+
+    let state = params.state.unwrap_or_default();
+    redirect_with(&params.redirect_uri, &[("code", code.as_str()), ("state", state.as_str())])
+}
+
+fn client_credentials(headers: &HeaderMap, form: &TokenForm) -> (Option<String>, Option<String>) {
+    let basic = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|h| h.to_str().ok())
+        .and_then(|h| h.strip_prefix("Basic "))
+        .and_then(|h| BASE64_STANDARD.decode(h).ok())
+        .and_then(|h| String::from_utf8(h).ok());
+
+    // Both parts are form-encoded before being joined (RFC 6749, section 2.3.1)
+    let decode = |value: &str| urlencoding::decode(&value.replace('+', " ")).map(|v| v.into_owned()).ok();
+    match basic.as_deref().and_then(|b| b.split_once(':')) {
+        Some((id, secret)) => (decode(id), decode(secret)),
+        None => (form.client_id.clone(), form.client_secret.clone()),
+    }
+}
+
+pub async fn token(State(data): State<AppState>, headers: HeaderMap, Form(form): Form<TokenForm>) -> Result<Response, OidcError> {
+    if form.grant_type != "authorization_code" {
+        return Err(OidcError::new(StatusCode::BAD_REQUEST, "unsupported_grant_type", "Only authorization_code is supported"));
+    }
+
+    let (client_id, client_secret) = client_credentials(&headers, &form);
+    let client = client_id
+        .as_deref()
+        .and_then(|id| find_client(&data, id))
+        .ok_or(OidcError::new(StatusCode::UNAUTHORIZED, "invalid_client", "Unknown client"))?;
+
+    if !client.verify_secret(client_secret.as_deref()) {
+        return Err(OidcError::new(StatusCode::UNAUTHORIZED, "invalid_client", "Invalid client credentials"));
+    }
+
+    let code = data
+        .oidc_codes
+        .take(&form.code)
+        .ok_or(OidcError::new(StatusCode::BAD_REQUEST, "invalid_grant", "Invalid or expired code"))?;
+
+    if !code.issued_to(&client.client_id, &form.redirect_uri) {
+        return Err(OidcError::new(StatusCode::BAD_REQUEST, "invalid_grant", "Code was issued to another client"));
+    }
+
+    if !code.verify_pkce(form.code_verifier.as_deref()) {
+        return Err(OidcError::new(StatusCode::BAD_REQUEST, "invalid_grant", "Invalid code_verifier"));
+    }
+
+    let user = data
+        .ldap
+        .lock()
+        .await
+        .users
+        .user(&code.username)
+        .await
+        .ok_or(OidcError::new(StatusCode::BAD_REQUEST, "invalid_grant", "Unknown user"))?;
+
+    let server_error = || OidcError::new(StatusCode::INTERNAL_SERVER_ERROR, "server_error", "Token creation error");
+    let access_token = encode_oidc_jwt(user.uid.clone(), &client.client_id, &code.scope, data.env.jwt_maxage.into(), &data.keys)
+        .map_err(|_| server_error())?;
+
+    let now = Utc::now().timestamp();
+    let expires_in = data.env.jwt_maxage as i64 * 60;
+    let id_token = IdTokenClaims {
+        iss: data.env.oidc_issuer.trim_end_matches('/').to_string(),
+        sub: user.uid.clone(),
+        aud: client.client_id.clone(),
+        exp: (now + expires_in) as usize,
+        iat: now as usize,
+        nonce: code.nonce,
+        profile: user_claims(&user, &code.scope),
+    };
+    let id_token = data.keys.encode(&id_token).map_err(|_| server_error())?;
+
+    let body = Json(TokenResponse {
+        access_token,
+        token_type: "Bearer".to_string(),
+        expires_in,
+        id_token,
+    });
+
+    Ok(([(header::CACHE_CONTROL, "no-store")], body).into_response())
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/oidc/userinfo",
+    responses(
+        (status = 200, description = "Claims about the authenticated user"),
+        (status = 401, description = "Unauthorized")
+    ),
+    security(
+        ("jwt" = [])
+    )
+)]
+pub async fn userinfo(Extension(user): Extension<User>, token: Option<Extension<Cliams>>) -> Json<Value> {
+    // Only what the client was granted, a token without a scope just names its user
+    let scope = token.as_ref().and_then(|Extension(claims)| claims.scope.as_deref()).unwrap_or_default();
+    let mut claims = user_claims(&user, scope);
+    claims["sub"] = json!(user.uid);
+    Json(claims)
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::{header, HeaderMap, HeaderValue};
+    use serde_json::json;
+
+    use crate::common::user::fixture::user;
+
+    use super::{csrf_matches, user_claims};
+
+    #[test]
+    fn claims_follow_the_granted_scopes() {
+        let alice = user("alice", "Alice", "Tremblay", "Polytechnique", &["structure", "avionique"]);
+
+        assert_eq!(user_claims(&alice, ""), json!({}));
+        assert_eq!(user_claims(&alice, "openid email"), json!({ "email": "alice@polyorbite.com" }));
+        assert_eq!(user_claims(&alice, "openid groups"), json!({ "groups": ["avionique", "structure"] }));
+
+        let profile = user_claims(&alice, "openid profile");
+        assert_eq!(profile["preferred_username"], json!("alice"));
+        assert!(profile.get("email").is_none() && profile.get("groups").is_none());
+    }
+
+    #[test]
+    fn the_form_must_carry_the_token_of_its_cookie() {
+        let mut headers = HeaderMap::new();
+        assert!(!csrf_matches(&headers, "abc"));
+
+        headers.insert(header::COOKIE, HeaderValue::from_static("theme=dark; oidc_csrf=abc"));
+        assert!(csrf_matches(&headers, "abc"));
+        assert!(!csrf_matches(&headers, "abd"));
+        assert!(!csrf_matches(&headers, ""));
+
+        headers.insert(header::COOKIE, HeaderValue::from_static("oidc_csrf="));
+        assert!(!csrf_matches(&headers, ""));
+    }
+}
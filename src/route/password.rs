@@ -0,0 +1,191 @@
+use std::net::SocketAddr;
+
+use axum::{
+    extract::{ConnectInfo, Json, State}, http::{HeaderMap, StatusCode}, Extension
+};
+use chrono::Utc;
+use serde::Deserialize;
+use serde_json::json;
+use utoipa::ToSchema;
+
+use crate::common::{password::{is_reused, Password, PasswordPolicy, PolicyViolation}, user::{ModifyUser, User}};
+
+use super::{auth::{self, AuthBody, AuthError}, AppState};
+
+#[derive(Deserialize, ToSchema)]
+pub struct ChangePasswordData {
+    pub current_password: String,
+    pub new_password: String,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct ForgotData {
+    pub username: String,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct ResetData {
+    pub token: String,
+    pub password: String,
+}
+
+pub fn validate_password(data: &AppState, password: &str, user: &User) -> Result<(), AuthError> {
+    let mut violations = data
+        .password_policy
+        .validate(password, &PasswordPolicy::personal_words(user))
+        .err()
+        .unwrap_or_default();
+
+    let history = data.env.password_history;
+    if history > 0 && is_reused(data.password_history.as_ref(), &user.uid, &user.password, password) {
+        let message = format!("The password cannot be your current one or one of your last {} passwords", history);
+        violations.push(PolicyViolation::new("reused", message));
+    }
+
+    if violations.is_empty() {
+        return Ok(());
+    }
+    Err(AuthError::new(StatusCode::UNPROCESSABLE_ENTITY, "The password does not follow the policy")
+        .detail("fields", json!({ "password": violations })))
+}
+
+const PPOLICY_CHANGED_ATTRIBUTE: &str = "pwdChangedTime";
+
+async fn set_password(data: &AppState, username: &str, password: String) -> Result<(), AuthError> {
+    let remembered = (data.env.password_history > 0).then(|| Password::hash(&password, data.env.password_hash));
+
+    let mut modification = ModifyUser::new()
+        .password_hash(password, data.env.password_hash)
+        .attribute(data.env.password_must_change_attribute.clone(), vec![]);
+
+    // ppolicy maintains its own attribute, any other one is written here
+    if data.env.password_changed_attribute != PPOLICY_CHANGED_ATTRIBUTE {
+        let now = Utc::now().format("%Y%m%d%H%M%SZ").to_string();
+        modification = modification.attribute(data.env.password_changed_attribute.clone(), vec![now]);
+    }
+
+    match data.ldap.lock().await.users.modify_user(username, modification).await {
+        Ok(true) => (),
+        _ => return Err(AuthError::new(StatusCode::INTERNAL_SERVER_ERROR, "Unable to change the password")),
+    }
+
+    // The current password counts as one of the last ones
+    if let Some(hash) = remembered {
+        data.password_history.push(username, hash, data.env.password_history + 1);
+    }
+    Ok(())
+}
+
+fn revoke_sessions(data: &AppState, username: &str) -> Result<(), AuthError> {
+    data.denylist.revoke_user(username.to_string()).map_err(auth::revocation_failed)?;
+    data.refresh.revoke_user(username).map_err(auth::revocation_failed)?;
+    Ok(())
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/protected/user/password",
+    request_body = ChangePasswordData,
+    responses(
+        (status = 200, description = "Password changed, the other sessions are revoked", body = AuthBody),
+        (status = 401, description = "Wrong credentials"),
+        (status = 403, description = "Token is restricted"),
+        (status = 422, description = "The new password does not follow the policy"),
+        (status = 429, description = "Too many failed attempts, see the Retry-After header")
+    ),
+    security(
+        ("jwt" = [])
+    )
+)]
+pub async fn change_password(
+    State(data): State<AppState>,
+    Extension(user): Extension<User>,
+    headers: HeaderMap,
+    connect_info: Option<ConnectInfo<SocketAddr>>,
+    Json(password_data): Json<ChangePasswordData>
+) -> Result<Json<AuthBody>, AuthError> {
+    let ip = auth::client_ip(&data, &headers, connect_info);
+    auth::verify_credentials(&data, &user.uid, &password_data.current_password, ip.as_deref()).await?;
+    validate_password(&data, &password_data.new_password, &user)?;
+
+    set_password(&data, &user.uid, password_data.new_password).await?;
+    revoke_sessions(&data, &user.uid)?;
+
+    Ok(Json(auth::issue_tokens(&data, user.uid, None)?))
+}
+
+fn reset_link(data: &AppState, token: &str) -> String {
+    let separator = if data.env.password_reset_url.contains('?') { '&' } else { '?' };
+    format!("{}{}token={}", data.env.password_reset_url, separator, urlencoding::encode(token))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/auth/forgot",
+    request_body = ForgotData,
+    responses(
+        (status = 202, description = "A reset link is mailed to the user if the account exists"),
+        (status = 429, description = "Too many reset requests for this username or address, see the Retry-After header")
+    )
+)]
+pub async fn forgot(
+    State(data): State<AppState>,
+    headers: HeaderMap,
+    connect_info: Option<ConnectInfo<SocketAddr>>,
+    Json(forgot_data): Json<ForgotData>
+) -> Result<StatusCode, AuthError> {
+    let ip = auth::client_ip(&data, &headers, connect_info);
+    if let Some(retry_after) = data.reset_throttle.request(&forgot_data.username, ip.as_deref()) {
+        return Err(AuthError::new(StatusCode::TOO_MANY_REQUESTS, "Too many reset requests, try again later").retry_after(retry_after));
+    }
+
+    let user = data.ldap.lock().await.users.user(&forgot_data.username).await;
+
+    // The answer is the same whether the account exists or not
+    let user = match user {
+        Some(user) if !user.primary_mail().is_empty() => user,
+        _ => return Ok(StatusCode::ACCEPTED),
+    };
+
+    let token = data.reset_tokens.issue(user.uid.clone());
+    let body = format!(
+        "Hello {},\n\nA password reset was requested for your Polyorbite account. Open the link below to choose a new password, it expires in {} minutes:\n\n{}\n\nIf you did not ask for it, you can ignore this message.\n",
+        user.first_name,
+        data.env.password_reset_maxage,
+        reset_link(&data, &token)
+    );
+
+    let mailer = data.mailer.clone();
+    tokio::spawn(async move {
+        if let Err(e) = mailer.send(user.primary_mail(), "Polyorbite password reset", &body).await {
+            tracing::debug!("🔥 Failed to send the reset mail to {}: {:?}", user.uid, e);
+        }
+    });
+
+    Ok(StatusCode::ACCEPTED)
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/auth/reset",
+    request_body = ResetData,
+    responses(
+        (status = 204, description = "Password changed, the existing sessions are revoked"),
+        (status = 400, description = "Invalid or expired reset token"),
+        (status = 422, description = "The new password does not follow the policy")
+    )
+)]
+pub async fn reset(State(data): State<AppState>, Json(reset_data): Json<ResetData>) -> Result<StatusCode, AuthError> {
+    let invalid = || AuthError::new(StatusCode::BAD_REQUEST, "Invalid or expired reset token");
+
+    let username = data.reset_tokens.username(&reset_data.token).ok_or_else(invalid)?;
+    let user = data.ldap.lock().await.users.user(&username).await.ok_or_else(invalid)?;
+
+    validate_password(&data, &reset_data.password, &user)?;
+    data.reset_tokens.take(&reset_data.token).ok_or_else(invalid)?;
+
+    set_password(&data, &username, reset_data.password).await?;
+    revoke_sessions(&data, &username)?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
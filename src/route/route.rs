@@ -1,28 +1,89 @@
 use axum::{
-    extract::State, middleware, response::IntoResponse, routing::{get, post}, Extension, Json, Router
+    extract::DefaultBodyLimit, middleware, response::IntoResponse, routing::{delete, get, post}, Extension, Json, Router
 };
 use serde::{Deserialize, Serialize};
 use utoipa::ToSchema;
 
-use crate::common::user::User;
+use crate::common::{role::Role, user::User};
 
-use super::{auth, AppState};
+use super::{admin, auth, group, mfa, oidc, password, user, AppState};
+
+pub const PROTECTED_PATH: &str = "/api/protected";
+/// The only route a password change token opens.
+pub const PASSWORD_CHANGE_ROUTE: &str = "/user/password";
+pub const OIDC_PATH: &str = "/api/oidc";
+/// The only route the access token of an OIDC client opens.
+pub const OIDC_USERINFO_ROUTE: &str = "/userinfo";
 
 pub fn create_router(state: AppState) ->  Router<AppState> {
     Router::new()
-        .nest("/api/protected", protected().layer(middleware::from_fn_with_state(state, auth::authorize)))
-        .nest("/api/auth", auth())
+        .nest(PROTECTED_PATH, protected().layer(middleware::from_fn_with_state(state.clone(), auth::authorize)))
+        .nest("/api/users", users(&state).layer(middleware::from_fn_with_state(state.clone(), auth::authorize)))
+        .nest("/api/me", me().layer(middleware::from_fn_with_state(state.clone(), auth::authorize)))
+        .nest("/api/auth", auth(state.clone()))
+        .nest(OIDC_PATH, oidc(state))
+        .route("/.well-known/jwks.json", get(auth::jwks))
+        .route("/.well-known/openid-configuration", get(oidc::discovery))
 }
 
-fn auth() -> Router<AppState> {
+fn auth(state: AppState) -> Router<AppState> {
     Router::new()
     .route("/login", post(auth::sign_in))
+    .route("/login/mfa", post(auth::sign_in_mfa))
+    .route("/refresh", post(auth::refresh))
+    .route("/forgot", post(password::forgot))
+    .route("/reset", post(password::reset))
+    .route("/logout", post(auth::logout).route_layer(middleware::from_fn_with_state(state, auth::authorize)))
+}
+
+fn oidc(state: AppState) -> Router<AppState> {
+    Router::new()
+    .route("/authorize", get(oidc::authorize).post(oidc::authorize_submit))
+    .route("/token", post(oidc::token))
+    .route(OIDC_USERINFO_ROUTE, get(oidc::userinfo).route_layer(middleware::from_fn_with_state(state, auth::authorize)))
+}
+
+fn users(state: &AppState) -> Router<AppState> {
+    Router::new()
+    .route("/", post(user::create_user))
+    .route("/:uid", get(user::get_user).patch(user::update_user).delete(user::delete_user))
+    .route_layer(middleware::from_fn(|req, next| auth::require_role(Role::Admin, req, next)))
+    .route("/", get(user::get_users))
+    .route("/:uid/picture", get(user::get_picture).put(user::put_picture).delete(user::delete_picture))
+    .layer(DefaultBodyLimit::max(state.pictures.max_size()))
+    .route_layer(middleware::from_fn(|req, next| auth::require_role(Role::Member, req, next)))
+}
+
+fn me() -> Router<AppState> {
+    Router::new()
+    .route("/", get(user::get_me).patch(user::update_me))
+    .route_layer(middleware::from_fn(|req, next| auth::require_role(Role::Member, req, next)))
 }
 
 fn protected() ->  Router<AppState> {
     Router::new()
     .route("/user", get(get_user))
-    .route("/user/modify", post(modify_user))
+    .route(PASSWORD_CHANGE_ROUTE, post(password::change_password))
+    .route("/mfa/enroll", post(mfa::enroll))
+    .route("/mfa/confirm", post(mfa::confirm))
+    .route("/groups", get(group::get_groups))
+    .route("/groups/:cn", get(group::get_group))
+    .route("/groups/:cn/members", post(group::add_member))
+    .route("/groups/:cn/members/:uid", delete(group::remove_member))
+    .route("/groups/:cn/subgroups", post(group::add_subgroup))
+    .route("/groups/:cn/subgroups/:subgroup", delete(group::remove_subgroup))
+    .route_layer(middleware::from_fn(|req, next| auth::require_role(Role::Member, req, next)))
+    .nest("/admin", admin())
+}
+
+fn admin() -> Router<AppState> {
+    Router::new()
+    .route("/users/:uid/revoke", post(admin::revoke_sessions))
+    .route("/lockouts/:username", delete(admin::clear_lockout))
+    .route("/password-report", get(admin::password_report))
+    .route("/api-keys", post(admin::create_api_key).get(admin::get_api_keys))
+    .route("/api-keys/:id", delete(admin::revoke_api_key))
+    .route_layer(middleware::from_fn(|req, next| auth::require_role(Role::Admin, req, next)))
 }
 
 #[derive(Serialize, Deserialize, ToSchema)]
@@ -46,24 +107,6 @@ pub struct UserResponse {
 pub async fn get_user(Extension(user): Extension<User>) -> impl IntoResponse {
     Json(UserResponse {
         username: user.uid.clone(),
-        email: user.mail.clone(),
+        email: user.primary_mail().to_string(),
     })
 }
-
-#[utoipa::path(
-    post,
-    path = "/api/protected/user/modify",
-    responses(
-        (status = 200, description = "Success"),
-        (status = 401, description = "Unauthorized"),
-        (status = 440, description = "Token has expired")
-    ),
-    security(
-        ("jwt" = [])
-    )
-)]
-pub async fn modify_user(State(data): State<AppState>, Extension(user): Extension<User>) -> impl IntoResponse {
-    let res = data.ldap.lock().await.users.modify_user(&user.uid, crate::common::user::ModifyUser::new().password("test".to_string())).await.unwrap();
-    println!("Modify user result: {}", res);
-    "User modified".to_string()
-}
\ No newline at end of file
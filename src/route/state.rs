@@ -1,19 +1,75 @@
 use std::sync::Arc;
 use tokio::sync::Mutex;
 
-use crate::common::{Config, Ldap};
+use crate::common::{mailer::{mailer, Mailer}, oidc::{AuthorizationCodes, OidcClient}, picture::Pictures, password::{FileHistoryStore, HistoryStore, MemoryHistoryStore, PasswordExpiry, PasswordGenerator, PasswordPolicy}, throttle::{LoginThrottle, ResetThrottle}, totp::TotpState, token::{ApiKeyStore, Denylist, FileApiKeyStore, FileRefreshStore, JwtKeys, MemoryApiKeyStore, MemoryRefreshStore, RefreshStore, ResetTokens}, user::{AttributeMap, FieldPermissions}, Config, Ldap};
 
 #[derive(Clone)]
 pub struct AppState {
     pub ldap: Arc<Mutex<Ldap>>,
     pub env: Config,
+    pub refresh: Arc<dyn RefreshStore>,
+    pub denylist: Arc<Denylist>,
+    pub keys: Arc<JwtKeys>,
+    pub oidc_clients: Arc<Vec<OidcClient>>,
+    pub oidc_codes: Arc<AuthorizationCodes>,
+    pub totp: Arc<TotpState>,
+    pub api_keys: Arc<dyn ApiKeyStore>,
+    pub throttle: Arc<LoginThrottle>,
+    pub mailer: Arc<dyn Mailer>,
+    pub reset_tokens: Arc<ResetTokens>,
+    pub reset_throttle: Arc<ResetThrottle>,
+    pub password_policy: Arc<PasswordPolicy>,
+    pub password_expiry: Arc<PasswordExpiry>,
+    pub password_history: Arc<dyn HistoryStore>,
+    pub password_generator: Arc<PasswordGenerator>,
+    pub pictures: Arc<Pictures>,
+    pub field_permissions: Arc<FieldPermissions>,
+    pub user_attributes: Arc<AttributeMap>,
 }
 
 impl AppState {
     pub fn new(ldap: Ldap, env: Config) -> Self {
+        let refresh: Arc<dyn RefreshStore> = match &env.refresh_store {
+            Some(path) => Arc::new(FileRefreshStore::new(path.into())),
+            None => Arc::new(MemoryRefreshStore::new()),
+        };
+
+        let api_keys: Arc<dyn ApiKeyStore> = match &env.api_key_store {
+            Some(path) => Arc::new(FileApiKeyStore::new(path.into())),
+            None => Arc::new(MemoryApiKeyStore::new()),
+        };
+        let password_history: Arc<dyn HistoryStore> = match &env.password_history_store {
+            Some(path) => Arc::new(FileHistoryStore::new(path.into())),
+            None => Arc::new(MemoryHistoryStore::new()),
+        };
+
+        let throttle = LoginThrottle::new(env.login_max_failures, env.login_max_failures_per_ip, env.login_lockout * 60);
+        let denylist = Arc::new(Denylist::new(env.jwt_maxage as usize * 60, env.denylist_store.as_ref().map(|path| path.into())));
+        let keys = Arc::new(JwtKeys::new(&env));
+        let user_attributes = ldap.users.attribute_map();
+        let oidc_clients = Arc::new(env.oidc_clients.as_deref().map(OidcClient::load).unwrap_or_default());
+
         Self {
             ldap: Arc::new(Mutex::new(ldap)),
+            refresh,
+            denylist,
+            keys,
+            oidc_clients,
+            oidc_codes: Arc::new(AuthorizationCodes::new()),
+            totp: Arc::new(TotpState::new()),
+            api_keys,
+            throttle: Arc::new(throttle),
+            mailer: Arc::from(mailer(&env)),
+            reset_tokens: Arc::new(ResetTokens::new(env.password_reset_maxage * 60)),
+            reset_throttle: Arc::new(ResetThrottle::new(env.password_reset_max_per_user, env.password_reset_max_per_ip)),
+            password_policy: Arc::new(PasswordPolicy::new(&env)),
+            password_expiry: Arc::new(PasswordExpiry::new(&env)),
+            password_history,
+            password_generator: Arc::new(PasswordGenerator::new(&env)),
+            pictures: Arc::new(Pictures::new(&env)),
+            field_permissions: Arc::new(FieldPermissions::new(&env, &user_attributes)),
+            user_attributes,
             env,
         }
     }
-}
\ No newline at end of file
+}
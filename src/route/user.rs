@@ -0,0 +1,762 @@
+use std::collections::{BTreeMap, HashMap};
+
+use axum::{
+    body::Bytes, extract::{Json, Path, Query, State}, http::{header, HeaderMap, StatusCode}, response::{IntoResponse, Response}, Extension
+};
+use ldap3::LdapError;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use utoipa::{IntoParams, ToSchema};
+
+use crate::common::{password::{GeneratedKind, PasswordPolicy, PolicyViolation}, picture::{PictureError, Pictures}, role::{Role, Roles}, user::{AttributeMap, FieldError, ModifyUser, User, UserAttribute, UserBuilder, UserCursor, UserField, UserQuery, UserSort, PICTURE_FIELD}};
+
+use super::{auth::{self, AuthError}, AppState};
+
+const DEFAULT_PAGE_SIZE: usize = 50;
+const MAX_PAGE_SIZE: usize = 500;
+const PROJECTION_FIELDS: [&str; 3] = ["groups", "has_picture", "attributes"];
+/// What members see of each other in the listing, the other fields are for admins.
+const MEMBER_FIELDS: [&str; 9] = ["uid", "mail", "first_name", "last_name", "name", "school", "genie", "groups", "has_picture"];
+const MULTI_VALUED_FIELDS: [&str; 2] = ["mail", "number"];
+
+/// One value or a list of them, a user read from the API can be sent back as is.
+#[derive(Deserialize, ToSchema)]
+#[serde(untagged)]
+pub enum Values {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl Default for Values {
+    fn default() -> Self {
+        Self::Many(vec![])
+    }
+}
+
+impl Values {
+    /// Empty strings are dropped, `""` and `[]` both mean no value.
+    fn into_vec(self) -> Vec<String> {
+        let values = match self {
+            Self::One(value) => vec![value],
+            Self::Many(values) => values,
+        };
+        values.into_iter().filter(|v| !v.is_empty()).collect()
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct UserData {
+    uid: String,
+    /// The first address is the primary one
+    mail: Vec<String>,
+    first_name: String,
+    last_name: String,
+    name: String,
+    school: String,
+    genie: String,
+    matricule: String,
+    number: Vec<String>,
+    groups: Vec<String>,
+    has_picture: bool,
+    /// Custom fields of the deployment, see `USER_CUSTOM_ATTRIBUTES`
+    attributes: BTreeMap<String, Vec<String>>,
+}
+
+impl UserData {
+    fn new(user: User, attributes: &AttributeMap) -> Self {
+        let custom = user.custom(attributes);
+        let mut groups: Vec<String> = user.member.unwrap_or_default().into_iter().collect();
+        groups.sort();
+
+        Self {
+            uid: user.uid,
+            mail: user.mail,
+            first_name: user.first_name,
+            last_name: user.last_name,
+            name: user.name,
+            school: user.school,
+            genie: user.genie,
+            matricule: user.matricule,
+            number: user.number,
+            groups,
+            has_picture: user.picture.is_some(),
+            attributes: custom,
+        }
+    }
+}
+
+/// Without a password, one is generated and has to be changed on first sign-in.
+#[derive(Deserialize, ToSchema)]
+pub struct CreateUserData {
+    pub uid: String,
+    pub mail: Values,
+    pub first_name: String,
+    pub last_name: String,
+    pub name: String,
+    #[serde(default)]
+    pub school: String,
+    #[serde(default)]
+    pub genie: String,
+    #[serde(default)]
+    pub matricule: String,
+    #[serde(default)]
+    pub number: Values,
+    pub password: Option<String>,
+    #[serde(default)]
+    pub generate: GeneratedKind,
+    #[serde(default)]
+    pub attributes: HashMap<String, Values>,
+    #[serde(default)]
+    pub must_change_password: bool,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct CreatedUser {
+    #[serde(flatten)]
+    user: UserData,
+    /// Only returned, once, when it was generated.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    password: Option<String>,
+}
+
+/// Missing fields are left unchanged, an empty string removes an optional attribute.
+/// `mail`, `number` and the custom fields replace all their values, `add` and `remove` change some of them.
+#[derive(Deserialize, ToSchema)]
+pub struct UpdateUserData {
+    pub mail: Option<Values>,
+    pub first_name: Option<String>,
+    pub last_name: Option<String>,
+    pub name: Option<String>,
+    pub school: Option<String>,
+    pub genie: Option<String>,
+    pub matricule: Option<String>,
+    pub number: Option<Values>,
+    /// Custom fields, an empty string or list removes them
+    #[serde(default)]
+    pub attributes: HashMap<String, Values>,
+    /// Values to add to `mail`, `number` or custom fields
+    #[serde(default)]
+    pub add: HashMap<String, Values>,
+    /// Values to remove from `mail`, `number` or custom fields
+    #[serde(default)]
+    pub remove: HashMap<String, Values>,
+}
+
+impl UpdateUserData {
+    /// The fields that are replaced, custom ones included.
+    fn fields(&mut self) -> Vec<(String, Vec<String>)> {
+        let single = [
+            ("first_name", self.first_name.take()),
+            ("last_name", self.last_name.take()),
+            ("name", self.name.take()),
+            ("school", self.school.take()),
+            ("genie", self.genie.take()),
+            ("matricule", self.matricule.take()),
+        ]
+        .into_iter()
+        .filter_map(|(field, value)| Some((field.to_string(), vec![value?])));
+        let multiple = [("mail", self.mail.take()), ("number", self.number.take())]
+            .into_iter()
+            .filter_map(|(field, values)| Some((field.to_string(), values?.into_vec())));
+        let custom = self.attributes.drain().map(|(field, values)| (field, values.into_vec()));
+
+        single.chain(multiple).chain(custom).collect()
+    }
+}
+
+#[derive(Deserialize, IntoParams)]
+pub struct UserListParams {
+    /// Search terms, e.g. `first_name:al* school:polytechnique group:avionique`
+    pub q: Option<String>,
+    /// Field to sort by, prefixed by `-` for descending order (default `uid`)
+    pub sort: Option<String>,
+    /// `next_cursor` of the previous page
+    pub cursor: Option<String>,
+    /// Users per page (default 50, at most 500)
+    pub limit: Option<usize>,
+    /// Comma separated fields to return, all of them by default, or those a member can see
+    pub fields: Option<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct UserPage {
+    #[schema(value_type = Vec<UserData>)]
+    users: Vec<Value>,
+    total: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    next_cursor: Option<String>,
+}
+
+/// 422 with the errors grouped by field, like the password policy ones.
+fn validation_error(errors: Vec<FieldError>, violations: Vec<PolicyViolation>) -> AuthError {
+    let mut fields = Map::new();
+    for error in errors {
+        if let Value::Array(list) = fields.entry(error.field.clone()).or_insert_with(|| json!([])) {
+            list.push(json!(error));
+        }
+    }
+    if !violations.is_empty() {
+        fields.insert("password".to_string(), json!(violations));
+    }
+
+    AuthError::new(StatusCode::UNPROCESSABLE_ENTITY, "Invalid user")
+        .detail("fields", Value::Object(fields))
+}
+
+fn unknown_attributes(data: &AppState, fields: &[&str]) -> Vec<FieldError> {
+    fields
+        .iter()
+        .filter(|field| UserField::new(field).is_none() && !data.user_attributes.custom().contains_key(**field))
+        .map(|field| FieldError::new(field, "unknown", &format!("{} is not a user attribute", field)))
+        .collect()
+}
+
+/// Only `mail`, `number` and the custom fields have several values.
+fn single_valued(fields: &[&str], custom: &BTreeMap<String, String>) -> Vec<FieldError> {
+    fields
+        .iter()
+        .filter(|field| UserField::new(field).is_some() && !MULTI_VALUED_FIELDS.contains(field) && !custom.contains_key(**field))
+        .map(|field| FieldError::new(field, "single_valued", &format!("{} has a single value", field)))
+        .collect()
+}
+
+/// The directory refusing the values of a new user is a 422, anything else a 500.
+fn creation_error(e: &LdapError) -> AuthError {
+    match e {
+        // constraintViolation, invalidAttributeSyntax, objectClassViolation
+        LdapError::LdapResult { result } if matches!(result.rc, 19 | 21 | 65) => {
+            AuthError::new(StatusCode::UNPROCESSABLE_ENTITY, "The directory refused the user").detail("reason", json!(result.text))
+        }
+        _ => AuthError::new(StatusCode::INTERNAL_SERVER_ERROR, "Unable to create the user"),
+    }
+}
+
+fn not_found() -> AuthError {
+    AuthError::new(StatusCode::NOT_FOUND, "User not found")
+}
+
+fn projection(fields: &str) -> Result<Vec<String>, AuthError> {
+    fields
+        .split(',')
+        .map(|field| field.trim())
+        .filter(|field| !field.is_empty())
+        .map(|field| match UserField::new(field).is_some() || PROJECTION_FIELDS.contains(&field) {
+            true => Ok(field.to_string()),
+            false => Err(AuthError::new(StatusCode::BAD_REQUEST, &format!("Unknown field: {}", field))),
+        })
+        .collect()
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/users",
+    params(UserListParams),
+    responses(
+        (status = 200, description = "Success", body = UserPage),
+        (status = 400, description = "Invalid query, sort, cursor or fields"),
+        (status = 401, description = "Unauthorized"),
+        (status = 403, description = "Missing role: member, or a field only admins can see")
+    ),
+    security(
+        ("jwt" = []),
+        ("api_key" = [])
+    )
+)]
+pub async fn get_users(
+    State(data): State<AppState>,
+    Extension(roles): Extension<Roles>,
+    Query(params): Query<UserListParams>
+) -> Result<Json<UserPage>, AuthError> {
+    let bad_request = |message: String| AuthError::new(StatusCode::BAD_REQUEST, &message);
+
+    let query = UserQuery::parse(params.q.as_deref().unwrap_or_default()).map_err(bad_request)?;
+    let sort = match &params.sort {
+        Some(sort) => UserSort::parse(sort).map_err(bad_request)?,
+        None => UserSort::default(),
+    };
+    let cursor = match &params.cursor {
+        Some(cursor) => Some(UserCursor::decode(cursor, &sort).ok_or_else(|| bad_request("Invalid cursor".to_string()))?),
+        None => None,
+    };
+    let mut fields = params.fields.as_deref().map(projection).transpose()?;
+    let limit = params.limit.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE);
+
+    // Searching or sorting by a hidden field would reveal it as well
+    if !roles.has(Role::Admin) {
+        let used = query.fields().into_iter().chain([sort.field()]).map(|f| f.as_str());
+        let requested = fields.iter().flatten().map(|f| f.as_str());
+        if let Some(field) = used.chain(requested).find(|f| !MEMBER_FIELDS.contains(f)) {
+            return Err(AuthError::new(StatusCode::FORBIDDEN, &format!("Only admins can see {}", field)));
+        }
+        fields.get_or_insert_with(|| MEMBER_FIELDS.iter().map(|f| f.to_string()).collect());
+    }
+
+    let (page, total, next_cursor) = data.ldap.lock().await.users.page(&query, &sort, cursor.as_ref(), limit).await;
+    let next_cursor = next_cursor.map(|cursor| cursor.encode());
+
+    let users = page
+        .into_iter()
+        .map(|user| {
+            let mut user = json!(UserData::new(user, &data.user_attributes));
+            if let (Some(fields), Value::Object(map)) = (&fields, &mut user) {
+                map.retain(|key, _| fields.contains(key));
+            }
+            user
+        })
+        .collect();
+
+    Ok(Json(UserPage { users, total, next_cursor }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/users/{uid}",
+    params(
+        ("uid" = String, Path, description = "User id")
+    ),
+    responses(
+        (status = 200, description = "Success", body = UserData),
+        (status = 403, description = "Missing role: admin"),
+        (status = 404, description = "User not found")
+    ),
+    security(
+        ("jwt" = []),
+        ("api_key" = [])
+    )
+)]
+pub async fn get_user(State(data): State<AppState>, Path(uid): Path<String>) -> Result<Json<UserData>, AuthError> {
+    let user = data.ldap.lock().await.users.user(&uid).await.ok_or_else(not_found)?;
+    Ok(Json(UserData::new(user, &data.user_attributes)))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/users",
+    request_body = CreateUserData,
+    responses(
+        (status = 201, description = "User created, a generated password is only shown once", body = CreatedUser),
+        (status = 403, description = "Missing role: admin"),
+        (status = 409, description = "User already exists"),
+        (status = 422, description = "Invalid or missing fields, a password that does not follow the policy, or values the directory refused"),
+        (status = 500, description = "The user could not be created, nothing was kept")
+    ),
+    security(
+        ("jwt" = []),
+        ("api_key" = [])
+    )
+)]
+pub async fn create_user(
+    State(data): State<AppState>,
+    Json(user_data): Json<CreateUserData>
+) -> Result<(StatusCode, Json<CreatedUser>), AuthError> {
+    let mails = user_data.mail.into_vec();
+    let personal_words = PasswordPolicy::words(
+        &user_data.uid,
+        &mails,
+        &[&user_data.first_name, &user_data.last_name, &user_data.name]
+    );
+
+    let (password, generated) = match user_data.password {
+        Some(password) => (password, None),
+        None => {
+            let password = data
+                .password_generator
+                .generate(user_data.generate, &data.password_policy, &personal_words)
+                .ok_or_else(|| AuthError::new(StatusCode::INTERNAL_SERVER_ERROR, "Unable to generate a password that follows the policy"))?;
+            (password.clone(), Some(password))
+        }
+    };
+    let violations = data.password_policy.validate(&password, &personal_words).err().unwrap_or_default();
+
+    let user = UserBuilder::new()
+        .uid(user_data.uid)
+        .mails(mails)
+        .first_name(user_data.first_name)
+        .last_name(user_data.last_name)
+        .name(user_data.name)
+        .school(user_data.school)
+        .genie(user_data.genie)
+        .matricule(user_data.matricule)
+        .numbers(user_data.number.into_vec());
+    let user = match password.is_empty() {
+        true => user,
+        false => user.password_hash(password, data.env.password_hash),
+    };
+
+    let custom: Vec<&str> = user_data.attributes.keys().map(|field| field.as_str()).collect();
+    let unknown = unknown_attributes(&data, &custom);
+    let user = user_data
+        .attributes
+        .into_iter()
+        .map(|(field, values)| (field, values.into_vec()))
+        .filter(|(_, values)| !values.is_empty())
+        .filter_map(|(field, values)| Some((data.user_attributes.custom().get(&field)?, values)))
+        .fold(user, |user, (name, values)| user.attribute(name.clone(), values));
+
+    let user = match (user.build(), violations.is_empty() && unknown.is_empty()) {
+        (Ok(user), true) => user,
+        (result, _) => {
+            let mut errors = result.err().unwrap_or_default();
+            errors.extend(unknown);
+            return Err(validation_error(errors, violations));
+        }
+    };
+
+    let uid = user.uid.clone();
+    let must_change_password = generated.is_some() || user_data.must_change_password;
+
+    let mut ldap = data.ldap.lock().await;
+    match ldap.users.new_user(user, must_change_password).await {
+        Ok(true) => (),
+        Ok(false) => return Err(AuthError::new(StatusCode::CONFLICT, "User already exists")),
+        Err(e) => {
+            tracing::debug!("🔥 Unable to create {}: {:?}", uid, e);
+            return Err(creation_error(&e));
+        }
+    }
+    let user = ldap.users.user(&uid).await.ok_or_else(not_found)?;
+
+    Ok((StatusCode::CREATED, Json(CreatedUser { user: UserData::new(user, &data.user_attributes), password: generated })))
+}
+
+#[utoipa::path(
+    patch,
+    path = "/api/users/{uid}",
+    params(
+        ("uid" = String, Path, description = "User id")
+    ),
+    request_body = UpdateUserData,
+    responses(
+        (status = 200, description = "Success", body = UserData),
+        (status = 403, description = "Missing role: admin"),
+        (status = 404, description = "User not found"),
+        (status = 422, description = "Invalid fields")
+    ),
+    security(
+        ("jwt" = []),
+        ("api_key" = [])
+    )
+)]
+pub async fn update_user(
+    State(data): State<AppState>,
+    Extension(caller): Extension<User>,
+    Path(uid): Path<String>,
+    Json(user_data): Json<UpdateUserData>
+) -> Result<Json<UserData>, AuthError> {
+    let own = caller.uid == uid;
+    update(&data, &uid, user_data, own, true).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/me",
+    responses(
+        (status = 200, description = "Success", body = UserData),
+        (status = 401, description = "Unauthorized")
+    ),
+    security(
+        ("jwt" = [])
+    )
+)]
+pub async fn get_me(State(data): State<AppState>, Extension(user): Extension<User>) -> Json<UserData> {
+    Json(UserData::new(user, &data.user_attributes))
+}
+
+#[utoipa::path(
+    patch,
+    path = "/api/me",
+    request_body = UpdateUserData,
+    responses(
+        (status = 200, description = "Success", body = UserData),
+        (status = 401, description = "Unauthorized"),
+        (status = 422, description = "Invalid fields, or fields the user cannot change (codes admin_only and read_only)")
+    ),
+    security(
+        ("jwt" = [])
+    )
+)]
+pub async fn update_me(
+    State(data): State<AppState>,
+    Extension(user): Extension<User>,
+    Extension(roles): Extension<Roles>,
+    Json(user_data): Json<UpdateUserData>
+) -> Result<Json<UserData>, AuthError> {
+    update(&data, &user.uid, user_data, true, roles.has(Role::Admin)).await
+}
+
+/// Changes the fields that were sent, after checking them against the permission matrix.
+async fn update(data: &AppState, uid: &str, mut user_data: UpdateUserData, own: bool, admin: bool) -> Result<Json<UserData>, AuthError> {
+    let fields = user_data.fields();
+    let changed: Vec<(String, Vec<String>, bool)> = user_data
+        .add
+        .into_iter()
+        .map(|(field, values)| (field, values.into_vec(), true))
+        .chain(user_data.remove.into_iter().map(|(field, values)| (field, values.into_vec(), false)))
+        .collect();
+
+    let names: Vec<&str> = fields.iter().map(|(field, _)| field.as_str()).collect();
+    let changed_names: Vec<&str> = changed.iter().map(|(field, _, _)| field.as_str()).filter(|field| !names.contains(field)).collect();
+    let all_names: Vec<&str> = names.iter().chain(&changed_names).copied().collect();
+    let mut errors = unknown_attributes(data, &all_names);
+    errors.extend(data.field_permissions.check(&all_names, own, admin));
+    errors.extend(single_valued(&changed_names, data.user_attributes.custom()));
+    errors.extend(
+        changed
+            .iter()
+            .filter(|(field, _, _)| names.contains(&field.as_str()))
+            .map(|(field, _, _)| FieldError::new(field, "conflict", &format!("{} cannot be replaced and changed at once", field))),
+    );
+
+    let attributes = &data.user_attributes;
+    let ldap_name = |field: &str| match field {
+        "mail" => Some(attributes.name(UserAttribute::Mail).to_string()),
+        "number" => Some(attributes.name(UserAttribute::Number).to_string()),
+        custom => attributes.custom().get(custom).cloned(),
+    };
+
+    let modification = fields
+        .into_iter()
+        .fold(ModifyUser::new(), |modification, (field, mut values)| match field.as_str() {
+            "mail" => modification.mails(values),
+            "number" => modification.numbers(values),
+            "first_name" => modification.first_name(values.remove(0)),
+            "last_name" => modification.last_name(values.remove(0)),
+            "name" => modification.name(values.remove(0)),
+            "school" => modification.school(values.remove(0)),
+            "genie" => modification.genie(values.remove(0)),
+            "matricule" => modification.matricule(values.remove(0)),
+            custom => match ldap_name(custom) {
+                Some(name) => modification.attribute(name, values),
+                None => modification,
+            },
+        });
+    let modification = changed
+        .into_iter()
+        .fold(modification, |modification, (field, values, add)| match (ldap_name(&field), add) {
+            (Some(name), true) => modification.add_values(name, values),
+            (Some(name), false) => modification.remove_values(name, values),
+            (None, _) => modification,
+        });
+
+    errors.extend(modification.validate(attributes).err().unwrap_or_default());
+    if !errors.is_empty() {
+        return Err(validation_error(errors, vec![]));
+    }
+
+    let mut ldap = data.ldap.lock().await;
+    let user = ldap.users.user(uid).await.ok_or_else(not_found)?;
+
+    if !user.mail.is_empty() && modification.remaining_mails(&user, attributes).is_empty() {
+        return Err(validation_error(vec![FieldError::required("mail")], vec![]));
+    }
+
+    let (changes, binary_changes) = modification.to_ldif(user.clone(), &data.user_attributes);
+    if changes.is_empty() && binary_changes.is_empty() {
+        return Ok(Json(UserData::new(user, &data.user_attributes)));
+    }
+
+    match ldap.users.modify_user(uid, modification).await {
+        Ok(true) => (),
+        _ => return Err(AuthError::new(StatusCode::INTERNAL_SERVER_ERROR, "Unable to update the user")),
+    }
+    let user = ldap.users.user(uid).await.ok_or_else(not_found)?;
+
+    Ok(Json(UserData::new(user, &data.user_attributes)))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/users/{uid}",
+    params(
+        ("uid" = String, Path, description = "User id")
+    ),
+    responses(
+        (status = 204, description = "User deleted and signed out"),
+        (status = 403, description = "Missing role: admin"),
+        (status = 404, description = "User not found")
+    ),
+    security(
+        ("jwt" = []),
+        ("api_key" = [])
+    )
+)]
+pub async fn delete_user(State(data): State<AppState>, Path(uid): Path<String>) -> Result<StatusCode, AuthError> {
+    let mut ldap = data.ldap.lock().await;
+    ldap.users.user(&uid).await.ok_or_else(not_found)?;
+
+    match ldap.users.delete_user(&uid).await {
+        Ok(true) => (),
+        _ => return Err(AuthError::new(StatusCode::INTERNAL_SERVER_ERROR, "Unable to delete the user")),
+    }
+
+    data.denylist.revoke_user(uid.clone()).map_err(auth::revocation_failed)?;
+    data.refresh.revoke_user(&uid).map_err(auth::revocation_failed)?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[derive(Deserialize, IntoParams)]
+pub struct PictureParams {
+    /// Square thumbnail size in pixels, one of `PICTURE_THUMBNAIL_SIZES`
+    pub size: Option<u32>,
+}
+
+fn require_picture_owner(data: &AppState, user: &User, roles: &Roles, uid: &str) -> Result<(), AuthError> {
+    match data.field_permissions.can_edit(PICTURE_FIELD, user.uid == uid, roles.has(Role::Admin)) {
+        true => Ok(()),
+        false => Err(AuthError::new(StatusCode::FORBIDDEN, "You cannot change this picture")),
+    }
+}
+
+fn picture_error(error: PictureError) -> AuthError {
+    let status = match error {
+        PictureError::TooLarge => StatusCode::PAYLOAD_TOO_LARGE,
+        PictureError::UnsupportedFormat => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+        PictureError::Invalid => StatusCode::UNPROCESSABLE_ENTITY,
+    };
+    AuthError::new(status, &error.to_string())
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/users/{uid}/picture",
+    params(
+        ("uid" = String, Path, description = "User id"),
+        PictureParams
+    ),
+    responses(
+        (status = 200, description = "JPEG picture", content_type = "image/jpeg"),
+        (status = 304, description = "Not modified since the ETag in If-None-Match"),
+        (status = 400, description = "Unknown thumbnail size"),
+        (status = 404, description = "User or picture not found")
+    ),
+    security(
+        ("jwt" = []),
+        ("api_key" = [])
+    )
+)]
+pub async fn get_picture(
+    State(data): State<AppState>,
+    Path(uid): Path<String>,
+    Query(params): Query<PictureParams>,
+    headers: HeaderMap
+) -> Result<Response, AuthError> {
+    let user = data.ldap.lock().await.users.user(&uid).await.ok_or_else(not_found)?;
+    let picture = user.picture.ok_or_else(|| AuthError::new(StatusCode::NOT_FOUND, "Picture not found"))?;
+
+    let (etag, picture) = match params.size {
+        Some(size) if !data.pictures.thumbnail_sizes().contains(&size) => {
+            return Err(AuthError::new(StatusCode::BAD_REQUEST, "Unknown thumbnail size"));
+        }
+        Some(size) => {
+            let pictures = data.pictures.clone();
+            let thumbnail = tokio::task::spawn_blocking(move || pictures.thumbnail(&uid, &picture, size))
+                .await
+                .map_err(|_| AuthError::new(StatusCode::INTERNAL_SERVER_ERROR, "Unable to process the picture"))?
+                .map_err(picture_error)?;
+            (Pictures::etag(&thumbnail), thumbnail)
+        }
+        None => (Pictures::etag(&picture), picture),
+    };
+
+    let headers_out = [
+        (header::CONTENT_TYPE, "image/jpeg".to_string()),
+        (header::ETAG, etag.clone()),
+        (header::CACHE_CONTROL, "private, no-cache".to_string()),
+    ];
+    let not_modified = headers
+        .get(header::IF_NONE_MATCH)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.split(',').any(|tag| tag.trim() == etag || tag.trim() == "*"));
+
+    match not_modified {
+        true => Ok((StatusCode::NOT_MODIFIED, headers_out).into_response()),
+        false => Ok((headers_out, picture).into_response()),
+    }
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/users/{uid}/picture",
+    params(
+        ("uid" = String, Path, description = "User id")
+    ),
+    request_body(content = Vec<u8>, description = "PNG, JPEG or WebP image", content_type = "image/*"),
+    responses(
+        (status = 204, description = "Picture stored as JPEG, its ETag is returned"),
+        (status = 403, description = "You cannot change this picture"),
+        (status = 404, description = "User not found"),
+        (status = 413, description = "The picture is too large"),
+        (status = 415, description = "Not a PNG, JPEG or WebP image"),
+        (status = 422, description = "The picture cannot be decoded")
+    ),
+    security(
+        ("jwt" = []),
+        ("api_key" = [])
+    )
+)]
+pub async fn put_picture(
+    State(data): State<AppState>,
+    Extension(user): Extension<User>,
+    Extension(roles): Extension<Roles>,
+    Path(uid): Path<String>,
+    body: Bytes
+) -> Result<Response, AuthError> {
+    require_picture_owner(&data, &user, &roles, &uid)?;
+
+    let pictures = data.pictures.clone();
+    let picture = tokio::task::spawn_blocking(move || pictures.normalize(&body))
+        .await
+        .map_err(|_| AuthError::new(StatusCode::INTERNAL_SERVER_ERROR, "Unable to process the picture"))?
+        .map_err(picture_error)?;
+    let etag = Pictures::etag(&picture);
+
+    let mut ldap = data.ldap.lock().await;
+    ldap.users.user(&uid).await.ok_or_else(not_found)?;
+    match ldap.users.modify_user(&uid, ModifyUser::new().picture(picture)).await {
+        Ok(true) => (),
+        _ => return Err(AuthError::new(StatusCode::INTERNAL_SERVER_ERROR, "Unable to store the picture")),
+    }
+    data.pictures.forget(&uid);
+
+    Ok((StatusCode::NO_CONTENT, [(header::ETAG, etag)]).into_response())
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/users/{uid}/picture",
+    params(
+        ("uid" = String, Path, description = "User id")
+    ),
+    responses(
+        (status = 204, description = "Success"),
+        (status = 403, description = "You cannot change this picture"),
+        (status = 404, description = "User or picture not found")
+    ),
+    security(
+        ("jwt" = []),
+        ("api_key" = [])
+    )
+)]
+pub async fn delete_picture(
+    State(data): State<AppState>,
+    Extension(user): Extension<User>,
+    Extension(roles): Extension<Roles>,
+    Path(uid): Path<String>
+) -> Result<StatusCode, AuthError> {
+    require_picture_owner(&data, &user, &roles, &uid)?;
+
+    let mut ldap = data.ldap.lock().await;
+    let target = ldap.users.user(&uid).await.ok_or_else(not_found)?;
+    if target.picture.is_none() {
+        return Err(AuthError::new(StatusCode::NOT_FOUND, "Picture not found"));
+    }
+
+    match ldap.users.modify_user(&uid, ModifyUser::new().picture(vec![])).await {
+        Ok(true) => (),
+        _ => return Err(AuthError::new(StatusCode::INTERNAL_SERVER_ERROR, "Unable to delete the picture")),
+    }
+    data.pictures.forget(&uid);
+
+    Ok(StatusCode::NO_CONTENT)
+}